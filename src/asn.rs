@@ -0,0 +1,57 @@
+use anyhow::Result;
+use colored::*;
+use ipnet::IpNet;
+use std::collections::BTreeSet;
+
+use crate::diag;
+use crate::whois::{query_whois_server, WhoisOptions};
+
+/// Routing registry asked for `--prefixes` unless `--prefix-source` names
+/// another. RADb mirrors the route objects of the RIRs and most other IRRs.
+pub const DEFAULT_PREFIX_SOURCE: &str = "whois.radb.net";
+
+/// Lists the prefixes registered with `asn` as their origin, by asking a
+/// routing registry for its `route` and `route6` objects. This is only as
+/// complete as the registry: an AS may announce prefixes it never
+/// registered, and stale objects may remain after a prefix is withdrawn.
+pub async fn perform_prefixes(asn: &str, source: &str, options: &WhoisOptions) -> Result<()> {
+    let asn = asn.trim().to_uppercase();
+    let lookup = {
+        let (query, source, options) = (asn.clone(), source.to_string(), options.clone());
+        tokio::task::spawn_blocking(move || query_whois_server(&source, "-i origin ", &query, &options))
+    };
+    let response = options
+        .within_deadline(&asn, async { lookup.await? })
+        .await
+        .map_err(|e| anyhow::anyhow!("Prefix lookup at {} failed (best effort): {}", source, e))?;
+
+    let prefixes = route_prefixes(&response);
+
+    if diag::is_quiet() {
+        prefixes.iter().for_each(|prefix| println!("{}", prefix));
+        return Ok(());
+    }
+    println!();
+    println!("{}", format!("Prefixes originated by {}:", asn).green().bold());
+    println!("{}", "-".repeat(50));
+    println!("Source: {} (route objects, best effort)", source);
+    if prefixes.is_empty() {
+        println!("{}", "No route objects found".yellow());
+    }
+    for prefix in &prefixes {
+        println!("{}", prefix);
+    }
+    Ok(())
+}
+
+/// Collects the `route:` and `route6:` values of an IRR response, IPv4
+/// before IPv6 and each in address order, without duplicates from objects
+/// registered by several maintainers.
+fn route_prefixes(response: &str) -> BTreeSet<IpNet> {
+    response
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(key, _)| matches!(key.trim(), "route" | "route6"))
+        .filter_map(|(_, value)| value.trim().parse::<IpNet>().ok())
+        .collect()
+}
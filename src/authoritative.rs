@@ -0,0 +1,72 @@
+use anyhow::Result;
+use colored::*;
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::proto::rr::RecordType;
+use trust_dns_resolver::TokioAsyncResolver;
+use whois_dns_tool::dns::DnsAnswer;
+
+use crate::{create_resolver, diag, lookup_name_servers, print_dns_records, DnsOutput};
+
+/// Runs the query against the zone's own name servers instead of the
+/// recursive resolver, like `dig @ns1.example.com`, so cached answers cannot
+/// hide a recent change. The servers are tried in the order the zone lists
+/// them until one answers.
+pub async fn perform_authoritative_dns(domain: &str, record_type: RecordType, output: DnsOutput) -> Result<()> {
+    let resolver = create_resolver();
+    let (zone, name_servers) = find_zone(&resolver, domain).await?;
+
+    let mut last_error = None;
+    for name_server in &name_servers {
+        let ips: Vec<_> = match resolver.lookup_ip(name_server.as_str()).await {
+            Ok(ips) => ips.iter().collect(),
+            Err(e) => {
+                diag::verbose(&format!("Could not resolve {}: {}", name_server, e));
+                last_error = Some(e.into());
+                continue;
+            }
+        };
+
+        diag::verbose(&format!("Querying {} ({:?})", name_server, ips));
+        match direct_resolver(&ips).lookup(domain, record_type).await {
+            Ok(response) => {
+                if !diag::is_quiet() {
+                    println!("{} {} (zone {})", "Authoritative server:".bold(), name_server, zone);
+                }
+                print_dns_records(&DnsAnswer::from_lookup(&response), output);
+                return Ok(());
+            }
+            Err(e) => {
+                diag::notice(&format!("{} did not answer: {}. Trying next...", name_server, e));
+                last_error = Some(e.into());
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No name servers found for {}", zone)))
+}
+
+/// Walks up from `domain` to the closest name that has NS records, so
+/// `www.example.com` is answered by the servers of `example.com`.
+pub async fn find_zone(resolver: &TokioAsyncResolver, domain: &str) -> Result<(String, Vec<String>)> {
+    let mut zone = domain.trim_end_matches('.');
+    loop {
+        if let Ok(name_servers) = lookup_name_servers(resolver, zone).await {
+            if !name_servers.is_empty() {
+                return Ok((zone.to_string(), name_servers));
+            }
+        }
+        match zone.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => zone = parent,
+            _ => return Err(anyhow::anyhow!("Could not find the name servers of {}", domain)),
+        }
+    }
+}
+
+/// Builds a resolver that only talks to `ips` and does not ask for recursion.
+fn direct_resolver(ips: &[std::net::IpAddr]) -> TokioAsyncResolver {
+    let config = ResolverConfig::from_parts(None, vec![], NameServerConfigGroup::from_ips_clear(ips, 53, true));
+    let mut opts = ResolverOpts::default();
+    opts.recursion_desired = false;
+    opts.cache_size = 0;
+    TokioAsyncResolver::tokio(config, opts)
+}
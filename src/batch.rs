@@ -0,0 +1,729 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::*;
+use ipnet::IpNet;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
+use tokio::task::JoinSet;
+use trust_dns_resolver::proto::rr::RecordType;
+use whois_dns_tool::dns::DnsAnswer;
+
+use crate::failure::{Exit, FailureKind};
+use crate::ratelimit::RateLimiter;
+use crate::sqlite::SqliteSink;
+use crate::whois::{lookup_whois_async, plan_whois, WhoisOptions};
+use crate::{
+    concurrency, create_resolver, fingerprint, diag, normalize_target, parse, print_dns_plan, suffix,
+    emit_dns_records, print_dns_records, print_whois_plan, print_whois_result, DnsOutput, OutputFormat, Versioned,
+};
+
+pub struct BatchOptions {
+    pub input_format: InputFormat,
+    /// CSV column holding the targets, a 1-based number or a header name
+    pub column: Option<String>,
+    pub limit: Option<usize>,
+    pub dedupe: bool,
+    pub concurrency: usize,
+    pub format: OutputFormat,
+    pub whois: WhoisOptions,
+    pub expand_cidr: bool,
+    pub max_expand: usize,
+    /// Record type to look up instead of WHOIS
+    pub dns: Option<RecordType>,
+    /// Queries started per second across all concurrent lookups
+    pub rate: Option<f64>,
+    /// Stop at the first failed lookup instead of continuing
+    pub fail_fast: bool,
+    /// Collect the TTLs of DNS answers for the summary
+    pub ttl_stats: bool,
+    /// Database the results are also written to
+    pub sqlite: Option<PathBuf>,
+    /// Summarize the WHOIS answers by these keys at the end
+    pub group_by: Vec<GroupBy>,
+    /// Print content hashes instead of the WHOIS responses
+    pub hash: bool,
+    pub dry_run: bool,
+}
+
+/// The answer for one target of the batch.
+enum Answer {
+    Whois { server: String, response: String },
+    Dns(Vec<DnsAnswer>),
+}
+
+/// One batch result as emitted by `--format json`, `jsonl` and `yaml`.
+#[derive(Serialize)]
+struct BatchRecord {
+    target: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dates: Option<parse::Dates>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<Vec<parse::StatusCode>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    records: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// SHA-256 of the canonical response, with `--hash`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    /// Whether `hash` differs from the one stored by an earlier run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed: Option<bool>,
+}
+
+/// The document printed by `--format json` and `--format yaml`.
+#[derive(Serialize)]
+struct BatchResults {
+    results: Vec<BatchRecord>,
+    /// The targets of each `--group-by` bucket
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    groups: Groups,
+}
+
+/// What `--group-by` sorts the answered WHOIS targets by.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    Registrar,
+    /// The month the registration expires, as `YYYY-MM`
+    ExpiryMonth,
+    /// Each EPP status code; a domain with several is counted once per code
+    Status,
+}
+
+impl GroupBy {
+    fn name(self) -> &'static str {
+        match self {
+            GroupBy::Registrar => "registrar",
+            GroupBy::ExpiryMonth => "expiry_month",
+            GroupBy::Status => "status",
+        }
+    }
+}
+
+/// Targets by bucket, keyed by the `GroupBy` name.
+type Groups = BTreeMap<&'static str, BTreeMap<String, Vec<String>>>;
+
+/// Puts an answered target into its bucket for every `--group-by` key.
+/// Targets without the field go into `unknown`.
+fn add_to_groups(groups: &mut Groups, group_by: &[GroupBy], target: &str, response: &str) {
+    let fields = parse::parse_whois_fields(response);
+    for key in group_by {
+        let buckets: Vec<String> = match key {
+            GroupBy::Registrar => parse::registrar(&fields).map(str::to_string).into_iter().collect(),
+            GroupBy::ExpiryMonth => parse::dates(&fields)
+                .expires
+                .and_then(|expires| expires.get(..7).map(str::to_string))
+                .into_iter()
+                .collect(),
+            GroupBy::Status => parse::status_codes(&fields).into_iter().map(|status| status.code).collect(),
+        };
+        let buckets = if buckets.is_empty() { vec!["unknown".to_string()] } else { buckets };
+        let group = groups.entry(key.name()).or_default();
+        for bucket in buckets {
+            group.entry(bucket).or_default().push(target.to_string());
+        }
+    }
+}
+
+impl BatchRecord {
+    fn new(target: String, result: &Result<Answer>) -> Self {
+        let mut record = BatchRecord {
+            target,
+            success: result.is_ok(),
+            server: None,
+            fields: None,
+            dates: None,
+            status: None,
+            records: None,
+            error: None,
+            hash: None,
+            changed: None,
+        };
+
+        match result {
+            Ok(Answer::Whois { server, response }) => {
+                record.server = Some(server.clone());
+                let fields = parse::parse_whois_fields(response);
+                record.dates = Some(parse::dates(&fields));
+                record.status = Some(parse::status_codes(&fields));
+                record.fields = Some(parse::fields_to_map(&fields));
+            }
+            Ok(Answer::Dns(answers)) => {
+                record.records = Some(answers.iter().map(|answer| answer.data.to_string()).collect());
+            }
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        record
+    }
+}
+
+#[derive(Default)]
+pub struct BatchStats {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub duplicates: usize,
+    /// Targets not looked up because the batch was interrupted or stopped
+    /// by `--fail-fast`
+    pub skipped: usize,
+    /// `(target, ttl)` of every answered record, with `--ttl-stats`
+    pub ttls: Vec<(String, u32)>,
+    groups: Groups,
+    /// Lookups allowed in flight at once
+    concurrency: usize,
+    /// Whether that limit is the global `--concurrency`, which WHOIS and
+    /// DNS lookups share
+    shared: bool,
+}
+
+/// How long in-flight lookups may run after the first Ctrl-C.
+const INTERRUPT_GRACE: Duration = Duration::from_secs(10);
+
+/// How the batch file lists its targets.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    /// One target per line, with `#` comments
+    Lines,
+    /// Comma-separated values, targets in the column chosen with `--column`
+    Csv,
+    /// Any text, such as logs: every URL, domain name and IPv4 address in it
+    Text,
+}
+
+/// Reads the raw target list from `path` (or stdin for `-`) in the given
+/// input format.
+fn read_targets(path: &str, options: &BatchOptions) -> Result<Vec<String>> {
+    let mut contents = String::new();
+    if path == "-" {
+        std::io::stdin().read_to_string(&mut contents)?;
+    } else {
+        contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path, e))?;
+    }
+
+    match options.input_format {
+        InputFormat::Lines => Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()),
+        InputFormat::Csv => csv_targets(&contents, options.column.as_deref()),
+        InputFormat::Text => Ok(text_targets(&contents)),
+    }
+}
+
+/// Picks one column of every CSV row. A numeric `--column` counts from 1
+/// and uses every row; a name is looked up in the header row, which is then
+/// skipped. Without `--column` the first column is used.
+fn csv_targets(contents: &str, column: Option<&str>) -> Result<Vec<String>> {
+    let mut rows = contents.lines().filter(|line| !line.trim().is_empty()).map(split_csv_row);
+
+    let index = match column.map(|column| (column, column.parse::<usize>())) {
+        None => 0,
+        Some((_, Ok(0))) => return Err(anyhow::anyhow!("--column counts from 1")),
+        Some((_, Ok(number))) => number - 1,
+        Some((name, Err(_))) => {
+            let header = rows.next().unwrap_or_default();
+            header
+                .iter()
+                .position(|field| field.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow::anyhow!("No column {:?} in the header: {}", name, header.join(", ")))?
+        }
+    };
+
+    let mut targets = Vec::new();
+    for (row, fields) in rows.enumerate() {
+        match fields.get(index).filter(|field| !field.is_empty()) {
+            Some(field) => targets.push(field.clone()),
+            None => diag::verbose(&format!("Row {} has no value in column {}, skipping", row + 1, index + 1)),
+        }
+    }
+    Ok(targets)
+}
+
+/// Splits one CSV line into trimmed fields. Quoted fields may contain commas
+/// and `""` for a literal quote; fields spanning lines are not supported.
+fn split_csv_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Finds the URLs, domain names and IPv4 addresses in free text. URLs are
+/// reduced to their host; names are kept only when they end in a known
+/// public suffix, so file names such as `access.log` are not mistaken for
+/// domains.
+fn text_targets(contents: &str) -> Vec<String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)\b[a-z][a-z0-9+.-]*://[^\s/?#\x22'<>]+",
+            r"|\b(?:\d{1,3}\.){3}\d{1,3}\b",
+            r"|\b(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z](?:[a-z0-9-]*[a-z0-9])?\b",
+        ))
+        .unwrap()
+    });
+
+    pattern
+        .find_iter(contents)
+        .filter_map(|found| {
+            let text = found.as_str();
+            let host = match text.split_once("://") {
+                Some((_, authority)) => {
+                    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+                    host.split(':').next().unwrap_or(host)
+                }
+                None => text,
+            };
+            let is_target = host.parse::<std::net::IpAddr>().is_ok()
+                || suffix::has_known_suffix(&host.to_ascii_lowercase());
+            is_target.then(|| host.to_string())
+        })
+        .collect()
+}
+
+/// Replaces CIDR ranges such as `192.0.2.0/28` with their host addresses
+/// when `--expand-cidr` is given. Ranges with more than `--max-expand` hosts
+/// are kept as a single target, which RIR servers answer with the covering
+/// allocation.
+fn expand_targets(targets: Vec<String>, options: &BatchOptions) -> Vec<String> {
+    let mut expanded = Vec::new();
+
+    for target in targets {
+        let network = match target.parse::<IpNet>() {
+            Ok(network) if options.expand_cidr => network,
+            _ => {
+                expanded.push(target);
+                continue;
+            }
+        };
+
+        let host_bits = network.max_prefix_len() - network.prefix_len();
+        if host_bits >= usize::BITS as u8 || (1usize << host_bits) > options.max_expand {
+            diag::notice(&format!(
+                "{} has more than {} addresses, querying the range instead of expanding it",
+                target, options.max_expand
+            ));
+            expanded.push(target);
+            continue;
+        }
+
+        expanded.extend(network.hosts().map(|host| host.to_string()));
+    }
+
+    expanded
+}
+
+/// Normalizes the targets, expands CIDR ranges and applies `--dedupe` and
+/// `--limit`, returning the targets to query and the number of duplicates
+/// that were dropped.
+fn prepare_targets(raw: Vec<String>, options: &BatchOptions) -> (Vec<String>, usize) {
+    let mut seen = HashSet::new();
+    let mut duplicates = 0;
+    let mut targets = Vec::new();
+
+    let normalized = raw.iter().map(|t| normalize_target(t)).collect();
+    for target in expand_targets(normalized, options) {
+        if options.dedupe && !seen.insert(target.clone()) {
+            duplicates += 1;
+            continue;
+        }
+        targets.push(target);
+    }
+
+    if let Some(limit) = options.limit {
+        targets.truncate(limit);
+    }
+
+    (targets, duplicates)
+}
+
+pub async fn run_batch(path: &str, options: &BatchOptions) -> Result<()> {
+    let (targets, duplicates) = prepare_targets(read_targets(path, options)?, options);
+
+    if options.dry_run {
+        for target in &targets {
+            match options.dns {
+                Some(record_type) => print_dns_plan(target, record_type),
+                None => print_whois_plan(&plan_whois(target, &options.whois), &options.whois),
+            }
+        }
+        if let Some(path) = &options.sqlite {
+            println!("Database: {}", path.display());
+        }
+        return Ok(());
+    }
+
+    let mut database = options.sqlite.as_deref().map(SqliteSink::open).transpose()?;
+
+    let mut stats = BatchStats {
+        total: targets.len(),
+        duplicates,
+        concurrency: options.concurrency.max(1),
+        shared: concurrency::limit().is_some(),
+        ..Default::default()
+    };
+
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let limiter = options
+        .rate
+        .filter(|rate| *rate > 0.0)
+        .map(|rate| Arc::new(RateLimiter::new(rate)));
+    let mut tasks = JoinSet::new();
+
+    // The first Ctrl-C stops new lookups from starting, the second one exits.
+    // `--fail-fast` uses the same flag to stop after the first failure.
+    let stop = Arc::new(AtomicBool::new(false));
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let interrupt = Arc::new(Notify::new());
+    let signal_task = {
+        let stop = stop.clone();
+        let interrupted = interrupted.clone();
+        let interrupt = interrupt.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                stop.store(true, Ordering::SeqCst);
+                interrupted.store(true, Ordering::SeqCst);
+                interrupt.notify_one();
+                diag::notice("Interrupted: finishing in-flight lookups, press Ctrl-C again to exit immediately");
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    std::process::exit(130);
+                }
+            }
+        })
+    };
+
+    for target in targets {
+        let semaphore = semaphore.clone();
+        let limiter = limiter.clone();
+        let stop = stop.clone();
+        let whois_options = options.whois.clone();
+        let dns = options.dns;
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            if let Some(limiter) = limiter {
+                limiter.acquire().await;
+            }
+            if stop.load(Ordering::SeqCst) {
+                return (target, None);
+            }
+            let result = lookup(target.clone(), dns, whois_options).await;
+            (target, Some(result))
+        });
+    }
+
+    let mut records = Vec::new();
+    let mut grace_deadline = None;
+    let mut first_failure = None;
+
+    loop {
+        let joined = tokio::select! {
+            joined = tasks.join_next() => joined,
+            _ = interrupt.notified(), if grace_deadline.is_none() => {
+                grace_deadline = Some(tokio::time::Instant::now() + INTERRUPT_GRACE);
+                continue;
+            }
+            _ = tokio::time::sleep_until(grace_deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if grace_deadline.is_some() =>
+            {
+                diag::notice(&format!("Grace period over, cancelling {} lookups", tasks.len()));
+                stats.skipped += tasks.len();
+                tasks.abort_all();
+                break;
+            }
+        };
+
+        let Some(joined) = joined else { break };
+        let (target, result) = match joined? {
+            (target, Some(result)) => (target, result),
+            (_, None) => {
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        if result.is_ok() {
+            stats.succeeded += 1;
+        } else {
+            stats.failed += 1;
+        }
+        if let (true, Ok(Answer::Dns(answers))) = (options.ttl_stats, &result) {
+            stats.ttls.extend(answers.iter().map(|answer| (target.clone(), answer.ttl)));
+        }
+        if let Ok(Answer::Whois { response, .. }) = &result {
+            add_to_groups(&mut stats.groups, &options.group_by, &target, response);
+        }
+        if let (true, Err(e)) = (options.fail_fast, &result) {
+            first_failure = Some(Exit {
+                code: FailureKind::classify(e).exit_code(),
+                message: format!("Stopped at the first failure, {}: {}", target, e),
+            });
+        }
+
+        if let Some(database) = &mut database {
+            match &result {
+                Ok(Answer::Whois { server, response }) => database.record_whois(&target, server, response)?,
+                Ok(Answer::Dns(answers)) => database.record_dns(&target, answers)?,
+                Err(e) => database.record_failure(&target, e)?,
+            }
+        }
+
+        if let (true, OutputFormat::Text, Ok(Answer::Whois { response, .. })) = (options.hash, options.format, &result) {
+            fingerprint::print_hash(&target, response);
+            continue;
+        }
+        let hash = match (options.hash, &result) {
+            (true, Ok(Answer::Whois { response, .. })) => {
+                let hash = fingerprint::hash_response(response);
+                let change = fingerprint::record(&target, &hash);
+                Some((hash, matches!(change, fingerprint::Change::Changed { .. })))
+            }
+            _ => None,
+        };
+        let new_record = |target: String, result: &Result<Answer>| {
+            let mut record = BatchRecord::new(target, result);
+            if let Some((hash, changed)) = &hash {
+                record.hash = Some(hash.clone());
+                record.changed = Some(*changed);
+            }
+            record
+        };
+
+        match options.format {
+            // The database or the grouping holds the results; the terminal
+            // only gets failures
+            OutputFormat::Text if database.is_some() || !options.group_by.is_empty() => {
+                if result.is_err() {
+                    print_text_result(&target, &result);
+                }
+            }
+            OutputFormat::Text => print_text_result(&target, &result),
+            OutputFormat::Json | OutputFormat::Yaml => records.push(new_record(target, &result)),
+            // DNS answers stream as one line per record, as with `dns`
+            OutputFormat::Jsonl => match &result {
+                Ok(Answer::Dns(answers)) => {
+                    let output = DnsOutput { format: OutputFormat::Jsonl, ..Default::default() };
+                    emit_dns_records(Some(&target), answers, output)?;
+                }
+                _ => {
+                    let record = Versioned::new(new_record(target, &result));
+                    println!("{}", serde_json::to_string(&record)?);
+                }
+            },
+        }
+
+        if first_failure.is_some() {
+            stop.store(true, Ordering::SeqCst);
+            stats.skipped += tasks.len();
+            tasks.abort_all();
+            break;
+        }
+    }
+    signal_task.abort();
+    if options.hash {
+        fingerprint::save();
+    }
+    if let Some(database) = &mut database {
+        database.finish()?;
+    }
+
+    // Lookups finish in any order
+    for targets in stats.groups.values_mut().flat_map(BTreeMap::values_mut) {
+        targets.sort();
+    }
+
+    let document = Versioned::new(BatchResults {
+        results: records,
+        groups: stats.groups.clone(),
+    });
+    match options.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&document)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&document)?),
+        OutputFormat::Text | OutputFormat::Jsonl => {}
+    }
+
+    print_stats(&stats, options.format);
+
+    if interrupted.load(Ordering::SeqCst) {
+        return Err(Exit { code: 130, message: "Batch interrupted".to_string() }.into());
+    }
+    if let Some(failure) = first_failure {
+        return Err(failure.into());
+    }
+    Ok(())
+}
+
+async fn lookup(target: String, dns: Option<RecordType>, whois: WhoisOptions) -> Result<Answer> {
+    match dns {
+        Some(record_type) => {
+            let lookup = whois
+                .within_deadline(&target, async {
+                    Ok(concurrency::limited(create_resolver().lookup(target.as_str(), record_type)).await?)
+                })
+                .await?;
+            Ok(Answer::Dns(DnsAnswer::from_lookup(&lookup)))
+        }
+        None => lookup_whois_async(&target, &whois)
+            .await
+            .map(|(server, response)| Answer::Whois { server, response }),
+    }
+}
+
+fn print_text_result(target: &str, result: &Result<Answer>) {
+    match result {
+        Ok(answer) => {
+            if !diag::is_quiet() {
+                println!("{} {}", "Target:".bold(), target);
+            }
+            match answer {
+                Answer::Whois { server, response } => print_whois_result(server, response),
+                Answer::Dns(answers) => print_dns_records(answers, DnsOutput::default()),
+            }
+        }
+        Err(e) => eprintln!("{} {}: {}", "Failed:".red().bold(), target, e),
+    }
+}
+
+/// Prints the summary to stdout for text output and to stderr otherwise, so
+/// it never ends up inside the JSON stream.
+fn print_stats(stats: &BatchStats, format: OutputFormat) {
+    let print = |line: &str| {
+        if format == OutputFormat::Text {
+            println!("{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    };
+    // The bucket counts are the answer of a grouped batch, so only their
+    // headers are left out
+    if diag::is_quiet() {
+        for (key, buckets) in &stats.groups {
+            group_summary(key, buckets).iter().skip(3).for_each(|line| print(line));
+        }
+        return;
+    }
+
+    let mut lines = vec![
+        format!("{}", "Batch Summary:".green().bold()),
+        "-".repeat(50),
+        format!("Targets: {}", stats.total),
+        format!("Succeeded: {}", stats.succeeded.to_string().green()),
+        format!("Failed: {}", stats.failed.to_string().red()),
+        format!("Duplicates skipped: {}", stats.duplicates),
+        match stats.shared {
+            true => format!("Concurrency: {} (shared by WHOIS and DNS lookups)", stats.concurrency),
+            false => format!("Concurrency: {}", stats.concurrency),
+        },
+    ];
+    if stats.skipped > 0 {
+        lines.push(format!("Not run: {}", stats.skipped.to_string().yellow()));
+    }
+    if !stats.ttls.is_empty() {
+        lines.extend(ttl_distribution(&stats.ttls));
+    }
+    for (key, buckets) in &stats.groups {
+        lines.extend(group_summary(key, buckets));
+    }
+
+    lines.iter().for_each(|line| print(line));
+}
+
+/// Counts the targets of each bucket, largest first. Expiry months are
+/// listed in calendar order instead, so renewals can be planned from the
+/// top down.
+fn group_summary(key: &str, buckets: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    let mut counts: Vec<(&String, usize)> = buckets.iter().map(|(bucket, targets)| (bucket, targets.len())).collect();
+    if key != GroupBy::ExpiryMonth.name() {
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    }
+
+    let mut lines = vec![
+        String::new(),
+        format!("{}", format!("By {}:", key.replace('_', " ")).green().bold()),
+        "-".repeat(50),
+    ];
+    let width = counts.iter().map(|(bucket, _)| bucket.len()).max().unwrap_or(0);
+    for (bucket, count) in counts {
+        lines.push(format!("{:<width$}  {}", bucket, count, width = width));
+    }
+    lines
+}
+
+/// Upper bounds (exclusive) and labels of the `--ttl-stats` histogram.
+const TTL_BUCKETS: [(u32, &str); 6] = [
+    (60, "< 1m"),
+    (300, "1m-5m"),
+    (3600, "5m-1h"),
+    (86400, "1h-1d"),
+    (604800, "1d-1w"),
+    (u32::MAX, ">= 1w"),
+];
+
+const HISTOGRAM_WIDTH: usize = 30;
+
+/// Summarizes the TTLs as min / median / p95 / max and a histogram, and
+/// names the targets with the shortest and longest TTL so outliers are easy
+/// to find.
+fn ttl_distribution(ttls: &[(String, u32)]) -> Vec<String> {
+    let mut sorted: Vec<&(String, u32)> = ttls.iter().collect();
+    sorted.sort_by_key(|(_, ttl)| *ttl);
+    let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100].1;
+
+    let mut lines = vec![
+        String::new(),
+        format!("{}", "TTL Distribution:".green().bold()),
+        "-".repeat(50),
+        format!("Records: {}", sorted.len()),
+        format!(
+            "Min: {}s  Median: {}s  P95: {}s  Max: {}s",
+            percentile(0),
+            percentile(50),
+            percentile(95),
+            percentile(100)
+        ),
+    ];
+
+    let mut counts = [0usize; TTL_BUCKETS.len()];
+    for (_, ttl) in &sorted {
+        let bucket = TTL_BUCKETS.iter().position(|(bound, _)| ttl < bound).unwrap_or(TTL_BUCKETS.len() - 1);
+        counts[bucket] += 1;
+    }
+    let largest = counts.iter().copied().max().unwrap_or(1).max(1);
+    for ((_, label), count) in TTL_BUCKETS.iter().zip(counts) {
+        let width = (count * HISTOGRAM_WIDTH).div_ceil(largest);
+        lines.push(format!("{:>6} {:<30} {}", label, "#".repeat(width), count));
+    }
+
+    let (shortest, longest) = (sorted[0], sorted[sorted.len() - 1]);
+    lines.push(format!("Shortest: {} ({}s)", shortest.0, shortest.1));
+    lines.push(format!("Longest: {} ({}s)", longest.0, longest.1));
+    lines
+}
@@ -0,0 +1,171 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::*;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use trust_dns_resolver::proto::rr::RecordType;
+
+use crate::failure::FailureKind;
+use crate::whois::{self, WhoisOptions};
+use crate::{create_resolver, diag, dnscache, normalize_target, wire, OutputFormat, Versioned};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum BenchKind {
+    Whois,
+    Dns,
+}
+
+pub struct BenchOptions {
+    pub kind: BenchKind,
+    pub record_type: RecordType,
+    pub iterations: usize,
+    /// WHOIS or DNS server to send every lookup to
+    pub server: Option<String>,
+    pub format: OutputFormat,
+    pub whois: WhoisOptions,
+}
+
+/// Latencies of the lookups after the warmup, as printed by `bench --format
+/// json`.
+#[derive(Serialize)]
+struct BenchReport {
+    target: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    iterations: usize,
+    /// Lookups that got an answer, not counting the warmup
+    samples: usize,
+    failures: usize,
+    min_ms: f64,
+    mean_ms: f64,
+    median_ms: f64,
+    p95_ms: f64,
+    max_ms: f64,
+}
+
+/// Runs the same lookup `iterations` times with caching turned off and
+/// reports the latency of all but the first, which pays for connection
+/// setup and name resolution. A DNS answer that the name has no records
+/// counts as an answer; any other failure is left out of the numbers.
+pub async fn run_bench(target: &str, options: &BenchOptions) -> Result<()> {
+    dnscache::init(true);
+    let target = normalize_target(target);
+
+    let mut samples = Vec::new();
+    let mut failures = 0;
+    let mut last_error = None;
+    for iteration in 0..options.iterations {
+        let start = Instant::now();
+        let result = lookup(&target, options).await;
+        let elapsed = start.elapsed();
+
+        let answered = match result {
+            Ok(()) => true,
+            Err(e) if FailureKind::classify(&e) == FailureKind::NotFound => true,
+            Err(e) => {
+                diag::verbose(&format!("Lookup {} failed: {}", iteration + 1, e));
+                last_error = Some(e);
+                false
+            }
+        };
+        if iteration == 0 {
+            diag::verbose(&format!("Warmup: {:.1} ms", millis(elapsed)));
+            continue;
+        }
+        if answered {
+            diag::verbose(&format!("Lookup {}: {:.1} ms", iteration + 1, millis(elapsed)));
+            samples.push(elapsed);
+        } else {
+            failures += 1;
+        }
+    }
+
+    if samples.is_empty() {
+        let e = last_error.unwrap_or_else(|| anyhow::anyhow!("No lookup answered"));
+        let message = format!("Every lookup of {} failed", target);
+        return Err(e.context(message));
+    }
+
+    samples.sort();
+    let percentile = |p: usize| millis(samples[(samples.len() - 1) * p / 100]);
+    let report = BenchReport {
+        kind: match options.kind {
+            BenchKind::Whois => "whois".to_string(),
+            BenchKind::Dns => format!("dns {}", options.record_type),
+        },
+        server: options.server.clone(),
+        iterations: options.iterations,
+        samples: samples.len(),
+        failures,
+        min_ms: percentile(0),
+        mean_ms: samples.iter().copied().map(millis).sum::<f64>() / samples.len() as f64,
+        median_ms: percentile(50),
+        p95_ms: percentile(95),
+        max_ms: percentile(100),
+        target,
+    };
+
+    match options.format {
+        OutputFormat::Text => print_report(&report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&Versioned::new(&report))?),
+        OutputFormat::Jsonl => println!("{}", serde_json::to_string(&Versioned::new(&report))?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&Versioned::new(&report))?),
+    }
+    Ok(())
+}
+
+/// One lookup, without printing its answer.
+async fn lookup(target: &str, options: &BenchOptions) -> Result<()> {
+    match (options.kind, options.server.as_deref()) {
+        (BenchKind::Whois, None) => {
+            whois::lookup_whois_async(target, &options.whois).await?;
+        }
+        (BenchKind::Whois, Some(server)) => {
+            let query = whois::plan_whois(target, &options.whois).query;
+            let (server, whois_options) = (server.to_string(), options.whois.clone());
+            let lookup = tokio::task::spawn_blocking(move || {
+                whois::query_whois_server(&server, "", &query, &whois_options)
+            });
+            options.whois.within_deadline(target, async { lookup.await? }).await?;
+        }
+        (BenchKind::Dns, None) => {
+            create_resolver().lookup(target, options.record_type).await?;
+        }
+        (BenchKind::Dns, Some(server)) => {
+            wire::query_server(target, options.record_type, server).await?;
+        }
+    }
+    Ok(())
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn print_report(report: &BenchReport) {
+    let row = |label: &str, value: &str| println!("{:<9}{}", format!("{}:", label).bold(), value);
+    if !diag::is_quiet() {
+        println!("{}", "Benchmark:".green().bold());
+        println!("{}", "-".repeat(50));
+        row("Target", &report.target);
+        row("Type", &report.kind);
+        if let Some(server) = &report.server {
+            row("Server", server);
+        }
+        row(
+            "Samples",
+            &format!("{} of {} (first discarded, {} failed)", report.samples, report.iterations, report.failures),
+        );
+    }
+    for (label, value) in [
+        ("Min", report.min_ms),
+        ("Mean", report.mean_ms),
+        ("Median", report.median_ms),
+        ("P95", report.p95_ms),
+        ("Max", report.max_ms),
+    ] {
+        row(label, &format!("{:.1} ms", value));
+    }
+}
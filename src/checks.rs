@@ -0,0 +1,298 @@
+//! Health checks run by `summary --checks` on the records it gathered. Each
+//! check looks at records already looked up and returns at most one issue.
+
+use colored::*;
+use serde::Serialize;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::proto::rr::RecordType;
+use whois_dns_tool::dns::{DnsAnswer, RecordData};
+
+use crate::{concurrency, create_resolver, diag, family, suffix};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth a look, but mail and resolution keep working
+    Warn,
+    /// Breaks resolution or lets anyone send mail as the domain
+    Error,
+}
+
+#[derive(Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Short name of the check, such as `spf-all`
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Issue {
+    fn new(severity: Severity, check: &'static str, message: String) -> Self {
+        Issue { severity, check, message }
+    }
+}
+
+/// The values of the TXT records among `answers`, with the character
+/// strings of each record joined.
+fn txt_values(answers: &[DnsAnswer]) -> Vec<String> {
+    answers
+        .iter()
+        .filter_map(|answer| match &answer.data {
+            RecordData::Txt(strings) => Some(strings.concat()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn spf_record(txt: &[DnsAnswer]) -> Option<String> {
+    txt_values(txt)
+        .into_iter()
+        .find(|value| value.to_ascii_lowercase().starts_with("v=spf1"))
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.').to_lowercase()
+}
+
+/// An SPF record ending in `+all` (or a bare `all`) lets every host on the
+/// internet send mail for the domain.
+pub fn spf_allows_all(txt: &[DnsAnswer]) -> Option<Issue> {
+    let spf = spf_record(txt)?;
+    let allows_all = spf
+        .split_whitespace()
+        .any(|term| term.eq_ignore_ascii_case("+all") || term.eq_ignore_ascii_case("all"));
+    allows_all.then(|| {
+        Issue::new(
+            Severity::Error,
+            "spf-all",
+            format!("SPF record passes mail from any host: {}", spf),
+        )
+    })
+}
+
+/// A domain that publishes senders in SPF but has no MX cannot receive
+/// bounces or replies. `v=spf1 -all`, which says the domain sends no mail,
+/// needs no MX.
+pub fn spf_without_mx(txt: &[DnsAnswer], mx: &[DnsAnswer]) -> Option<Issue> {
+    let spf = spf_record(txt)?;
+    let sends_no_mail = spf.split_whitespace().skip(1).eq(["-all"]);
+    (mx.is_empty() && !sends_no_mail).then(|| {
+        Issue::new(
+            Severity::Warn,
+            "spf-without-mx",
+            "SPF record present but no MX records; the domain cannot receive replies or bounces".to_string(),
+        )
+    })
+}
+
+/// `dmarc` are the TXT records of `_dmarc.<domain>`.
+pub fn missing_dmarc(dmarc: &[DnsAnswer]) -> Option<Issue> {
+    let published = txt_values(dmarc)
+        .iter()
+        .any(|value| value.to_ascii_lowercase().starts_with("v=dmarc1"));
+    (!published).then(|| {
+        Issue::new(
+            Severity::Warn,
+            "missing-dmarc",
+            "No DMARC record at _dmarc; receivers cannot tell how to treat mail failing SPF or DKIM".to_string(),
+        )
+    })
+}
+
+/// Compares the NS records of the zone with the name servers in the WHOIS
+/// record. Without name servers in WHOIS there is nothing to compare.
+pub fn name_server_mismatch(zone: &[DnsAnswer], registry: &[String]) -> Option<Issue> {
+    if registry.is_empty() {
+        return None;
+    }
+    let live: Vec<String> = zone
+        .iter()
+        .filter_map(|answer| match &answer.data {
+            RecordData::Ns(host) => Some(normalize_host(host)),
+            _ => None,
+        })
+        .collect();
+    let registry: Vec<String> = registry.iter().map(|host| normalize_host(host)).collect();
+
+    let registry_only: Vec<&str> = registry.iter().filter(|ns| !live.contains(ns)).map(String::as_str).collect();
+    let zone_only: Vec<&str> = live.iter().filter(|ns| !registry.contains(ns)).map(String::as_str).collect();
+    if registry_only.is_empty() && zone_only.is_empty() {
+        return None;
+    }
+    let mut differences = Vec::new();
+    if !registry_only.is_empty() {
+        differences.push(format!("registry only: {}", registry_only.join(", ")));
+    }
+    if !zone_only.is_empty() {
+        differences.push(format!("zone only: {}", zone_only.join(", ")));
+    }
+    Some(Issue::new(
+        Severity::Warn,
+        "ns-mismatch",
+        format!("Name servers differ between the registry and the zone ({})", differences.join("; ")),
+    ))
+}
+
+/// A CNAME whose target has no addresses sends visitors nowhere, and a
+/// target that can be registered again can be taken over.
+pub fn dangling_cname(name: &str, target: &str, target_resolves: bool) -> Option<Issue> {
+    (!target_resolves).then(|| {
+        Issue::new(
+            Severity::Error,
+            "dangling-cname",
+            format!("{} is a CNAME to {}, which does not resolve", name, target),
+        )
+    })
+}
+
+/// A CNAME at the zone apex, the registrable domain itself. RFC 1034
+/// allows no other data next to a CNAME, yet the apex must carry the SOA
+/// and NS records; resolvers that follow the CNAME may ignore the NS and MX
+/// records, breaking delegation and mail. Some DNS providers accept it
+/// anyway. `answers` may come from any query for `domain`: a resolver
+/// returns the CNAME in front of the records of its target.
+pub fn apex_cname(domain: &str, answers: &[DnsAnswer]) -> Option<Issue> {
+    let domain = normalize_host(domain);
+    if suffix::registrable_domain(&domain).as_deref() != Some(domain.as_str()) {
+        return None;
+    }
+    let target = answers.iter().find_map(|answer| match &answer.data {
+        RecordData::Cname(target) if normalize_host(&answer.name) == domain => Some(target),
+        _ => None,
+    })?;
+    Some(Issue::new(
+        Severity::Error,
+        "apex-cname",
+        format!(
+            "{} is a CNAME to {} at the zone apex; RFC 1034 forbids other records next to a CNAME, so its NS and MX records may be ignored",
+            domain, target
+        ),
+    ))
+}
+
+/// Warns on stderr when an address, MX or NS query for the apex was
+/// answered through a CNAME.
+pub fn warn_apex_cname(domain: &str, record_type: RecordType, answers: &[DnsAnswer]) {
+    if !matches!(record_type, RecordType::A | RecordType::AAAA | RecordType::MX | RecordType::NS) {
+        return;
+    }
+    if let Some(issue) = apex_cname(domain, answers) {
+        diag::notice(&format!("{} {}", "WARNING:".yellow().bold(), issue.message));
+    }
+}
+
+/// Runs every check on the records of a summary. `None` stands for a
+/// record type whose lookup failed; the checks that need it are skipped
+/// rather than reporting records as missing. The DMARC and CNAME checks
+/// make lookups of their own.
+pub async fn run_checks(
+    domain: &str,
+    txt: Option<&[DnsAnswer]>,
+    mx: Option<&[DnsAnswer]>,
+    ns: Option<&[DnsAnswer]>,
+    registry_ns: &[String],
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    if let Some(txt) = txt {
+        issues.extend(spf_allows_all(txt));
+        if let Some(mx) = mx {
+            issues.extend(spf_without_mx(txt, mx));
+        }
+    }
+    if let Some(dmarc) = lookup(&format!("_dmarc.{}", domain), RecordType::TXT).await {
+        issues.extend(missing_dmarc(&dmarc));
+    }
+    if let Some(ns) = ns {
+        issues.extend(name_server_mismatch(ns, registry_ns));
+    }
+
+    let cname = lookup(domain, RecordType::CNAME).await.unwrap_or_default();
+    let target = cname.iter().find_map(|answer| match &answer.data {
+        RecordData::Cname(target) => Some(target.clone()),
+        _ => None,
+    });
+    issues.extend(apex_cname(domain, &cname));
+    if let Some(target) = target {
+        // Whether the target exists at all, so IPv4-only targets are not
+        // dangling under -6
+        let resolves = concurrency::limited(family::all_addresses(&create_resolver(), &target)).await.is_ok();
+        issues.extend(dangling_cname(domain, &target, resolves));
+    }
+    issues
+}
+
+/// The records of one type, empty when the name has none and `None` when
+/// the lookup failed.
+async fn lookup(name: &str, record_type: RecordType) -> Option<Vec<DnsAnswer>> {
+    match concurrency::limited(create_resolver().lookup(name, record_type)).await {
+        Ok(lookup) => Some(
+            DnsAnswer::from_lookup(&lookup)
+                .into_iter()
+                .filter(|answer| answer.record_type == record_type)
+                .collect(),
+        ),
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Some(Vec::new()),
+        Err(_) => None,
+    }
+}
+
+/// Prints one `WARN` or `ERROR` line per issue.
+pub fn print_issues(issues: &[Issue]) {
+    if issues.is_empty() {
+        println!("{}", "No issues found".green());
+    }
+    for issue in issues {
+        let label = match issue.severity {
+            Severity::Warn => "WARN ".yellow().bold(),
+            Severity::Error => "ERROR".red().bold(),
+        };
+        println!("{} {}", label, issue.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn answer(name: &str, record_type: RecordType, data: RecordData) -> DnsAnswer {
+        DnsAnswer { name: name.to_string(), record_type, ttl: 300, data }
+    }
+
+    fn cname(name: &str, target: &str) -> DnsAnswer {
+        answer(name, RecordType::CNAME, RecordData::Cname(target.to_string()))
+    }
+
+    #[test]
+    fn apex_with_a_cname_is_an_error() {
+        let answers = [
+            cname("example.com.", "example.herokudns.com."),
+            answer("example.herokudns.com.", RecordType::A, RecordData::A(Ipv4Addr::new(192, 0, 2, 1))),
+        ];
+        let issue = apex_cname("Example.COM.", &answers).unwrap();
+        assert_eq!(issue.severity, Severity::Error);
+        assert_eq!(issue.check, "apex-cname");
+        assert!(issue.message.starts_with("example.com is a CNAME to example.herokudns.com."), "{}", issue.message);
+
+        let under_public_suffix = [cname("example.co.uk.", "example.herokudns.com.")];
+        assert!(apex_cname("example.co.uk", &under_public_suffix).is_some());
+    }
+
+    #[test]
+    fn apex_without_a_cname_passes() {
+        let answers = [answer("example.com.", RecordType::A, RecordData::A(Ipv4Addr::new(192, 0, 2, 1)))];
+        assert!(apex_cname("example.com", &answers).is_none());
+        assert!(apex_cname("example.com", &[]).is_none());
+
+        // A CNAME below the apex, or one for another name in the answer, is fine
+        assert!(apex_cname("www.example.com", &[cname("www.example.com.", "example.com.")]).is_none());
+        assert!(apex_cname("example.com", &[cname("www.example.com.", "example.com.")]).is_none());
+    }
+
+    #[test]
+    fn dangling_cname_needs_a_target_that_does_not_resolve() {
+        assert!(dangling_cname("www.example.com", "gone.example.net.", true).is_none());
+        let issue = dangling_cname("www.example.com", "gone.example.net.", false).unwrap();
+        assert_eq!(issue.check, "dangling-cname");
+    }
+}
@@ -0,0 +1,37 @@
+//! The global `--concurrency` limit: one semaphore shared by WHOIS and DNS
+//! lookups, so a command that runs both kinds at once (a batch, `summary`,
+//! `dns --check-dnsbl`) never has more queries in flight than were asked
+//! for. Without the flag lookups are not limited here, and `batch` uses its
+//! own default.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Lookups `batch` runs at once when `--concurrency` is not given.
+pub const BATCH_DEFAULT: usize = 4;
+
+static LIMIT: Mutex<Option<(usize, Arc<Semaphore>)>> = Mutex::new(None);
+
+/// Sets the limit. A REPL command with its own `--concurrency` replaces it;
+/// lookups already holding a permit keep it.
+pub fn init(limit: Option<usize>) {
+    *LIMIT.lock().unwrap() = limit.map(|limit| (limit.max(1), Arc::new(Semaphore::new(limit.max(1)))));
+}
+
+/// The `--concurrency` given, if any.
+pub fn limit() -> Option<usize> {
+    LIMIT.lock().unwrap().as_ref().map(|(limit, _)| *limit)
+}
+
+/// Waits for a free slot. The slot is held until the permit is dropped.
+pub async fn acquire() -> Option<OwnedSemaphorePermit> {
+    let semaphore = LIMIT.lock().unwrap().as_ref().map(|(_, semaphore)| semaphore.clone())?;
+    semaphore.acquire_owned().await.ok()
+}
+
+/// Runs one lookup in a slot.
+pub async fn limited<F: Future>(lookup: F) -> F::Output {
+    let _permit = acquire().await;
+    lookup.await
+}
@@ -0,0 +1,235 @@
+use anyhow::Result;
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use trust_dns_resolver::proto::op::Message;
+use trust_dns_resolver::proto::rr::{RData, Record, RecordType};
+
+use crate::authoritative::find_zone;
+use crate::{create_resolver, diag, family, normalize_target, wire};
+
+/// What the parent zone says about the delegation: the NS names and the
+/// glue addresses it hands out with them.
+struct ParentView {
+    server: String,
+    name_servers: BTreeSet<String>,
+    glue: BTreeMap<String, Vec<IpAddr>>,
+}
+
+/// Compares the delegation at the parent zone with the NS records the
+/// child's own servers publish. A name server listed on only one side, or
+/// glue that no longer matches the server's address, is a common cause of
+/// intermittent resolution failures.
+pub async fn perform_delegation(target: &str) -> Result<()> {
+    let domain = normalize_target(target);
+    let parent = domain
+        .split_once('.')
+        .map(|(_, parent)| parent.to_string())
+        .ok_or_else(|| anyhow::anyhow!("{} has no parent zone", domain))?;
+
+    let resolver = create_resolver();
+    let (parent_zone, parent_servers) = find_zone(&resolver, &parent).await?;
+    let view = ask_parent(&domain, &parent_servers).await?;
+
+    // Ask every delegated server for the NS set it publishes
+    let mut child: BTreeMap<String, Result<BTreeSet<String>>> = BTreeMap::new();
+    for name_server in &view.name_servers {
+        let addresses = match view.glue.get(name_server) {
+            Some(glue) => glue.clone(),
+            None => match resolver.lookup_ip(name_server.as_str()).await {
+                Ok(ips) => ips.iter().collect(),
+                Err(e) => {
+                    child.insert(name_server.clone(), Err(e.into()));
+                    continue;
+                }
+            },
+        };
+        child.insert(name_server.clone(), ask_child(&domain, &addresses).await);
+    }
+
+    let child_set: BTreeSet<String> = child
+        .values()
+        .filter_map(|answer| answer.as_ref().ok())
+        .flatten()
+        .cloned()
+        .collect();
+
+    println!("{}", "Delegation Check:".green().bold());
+    println!("{}", "-".repeat(50));
+    println!("Domain: {}", domain);
+    println!("Parent: {} (asked {})", parent_zone, view.server);
+    println!();
+    println!("{:<30} {:<7} {:<7} {:<24} Status", "Name Server", "Parent", "Child", "Glue");
+
+    let mut mismatches = 0;
+    for name_server in view.name_servers.union(&child_set) {
+        let at_parent = view.name_servers.contains(name_server);
+        let at_child = child_set.contains(name_server);
+        let glue = view.glue.get(name_server);
+
+        let glue_problem = match glue {
+            Some(glue) => check_glue(&resolver, name_server, glue).await,
+            None => None,
+        };
+        let status = match (&glue_problem, at_parent, at_child) {
+            (Some(problem), _, _) => problem.red(),
+            (None, true, true) => "match".green(),
+            (None, true, false) => "parent only".red(),
+            (None, _, _) => "child only".yellow(),
+        };
+        if glue_problem.is_some() || !(at_parent && at_child) {
+            mismatches += 1;
+        }
+
+        let glue = glue
+            .map(|ips| ips.iter().map(IpAddr::to_string).collect::<Vec<_>>().join(", "))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<30} {:<7} {:<7} {:<24} {}",
+            name_server,
+            if at_parent { "yes" } else { "-" },
+            if at_child { "yes" } else { "-" },
+            glue,
+            status
+        );
+    }
+
+    println!();
+    for (name_server, answer) in &child {
+        match answer {
+            Ok(set) if *set == child_set => {}
+            Ok(set) => println!(
+                "{} {} publishes {}",
+                "Inconsistent:".yellow(),
+                name_server,
+                set.iter().cloned().collect::<Vec<_>>().join(", ")
+            ),
+            Err(e) => println!("{} {} did not answer: {}", "Unreachable:".red(), name_server, e),
+        }
+    }
+
+    if mismatches == 0 {
+        println!("{}", "Parent and child delegation agree".green());
+    } else {
+        println!("{}", format!("{} mismatches between parent and child", mismatches).red());
+    }
+    Ok(())
+}
+
+/// Asks the parent servers in turn for the NS records of `domain`. The
+/// delegation comes back in the authority section of a referral, or in the
+/// answer when the parent also serves the child zone.
+async fn ask_parent(domain: &str, parent_servers: &[String]) -> Result<ParentView> {
+    let resolver = create_resolver();
+    let mut last_error = None;
+
+    for server in parent_servers {
+        let Some(ip) = resolver.lookup_ip(server.as_str()).await.ok().and_then(|ips| ips.iter().next()) else {
+            continue;
+        };
+        match wire::query_server(domain, RecordType::NS, &ip.to_string()).await {
+            Ok(response) => {
+                let name_servers = ns_names(domain, response.answers().iter().chain(response.name_servers()));
+                if name_servers.is_empty() {
+                    last_error = Some(anyhow::anyhow!("{} has no delegation for {}", server, domain));
+                    continue;
+                }
+                let glue = glue_records(&response, &name_servers);
+                return Ok(ParentView { server: server.clone(), name_servers, glue });
+            }
+            Err(e) => {
+                diag::notice(&format!("Parent server {} failed: {}. Trying next...", server, e));
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No parent server could be reached")))
+}
+
+/// Asks one delegated server, at any of its addresses, for the NS set of
+/// the zone.
+async fn ask_child(domain: &str, addresses: &[IpAddr]) -> Result<BTreeSet<String>> {
+    let mut last_error = None;
+    for ip in addresses {
+        match wire::query_server(domain, RecordType::NS, &ip.to_string()).await {
+            Ok(response) if response.authoritative() => return Ok(ns_names(domain, response.answers().iter())),
+            Ok(_) => last_error = Some(anyhow::anyhow!("{} is not authoritative (lame delegation)", ip)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("no addresses")))
+}
+
+/// Collects the NS targets for `domain`, lowercased and without the dot.
+fn ns_names<'a>(domain: &str, records: impl Iterator<Item = &'a Record>) -> BTreeSet<String> {
+    records
+        .filter(|record| normalize_name(&record.name().to_string()) == domain)
+        .filter_map(|record| match record.data() {
+            Some(RData::NS(ns)) => Some(normalize_name(&ns.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Picks the A and AAAA records of the additional section that belong to
+/// one of the delegated servers.
+fn glue_records(response: &Message, name_servers: &BTreeSet<String>) -> BTreeMap<String, Vec<IpAddr>> {
+    let mut glue: BTreeMap<String, Vec<IpAddr>> = BTreeMap::new();
+    for record in response.additionals() {
+        let name = normalize_name(&record.name().to_string());
+        if !name_servers.contains(&name) {
+            continue;
+        }
+        let ip = match record.data() {
+            Some(RData::A(ip)) => IpAddr::V4(ip.0),
+            Some(RData::AAAA(ip)) => IpAddr::V6(ip.0),
+            _ => continue,
+        };
+        glue.entry(name).or_default().push(ip);
+    }
+    glue
+}
+
+/// Returns why the glue for `name_server` is wrong, if it is: the host no
+/// longer resolves, or the glue lists addresses the host no longer has.
+/// Both families are looked up whatever `-4` or `-6` say, so valid glue of
+/// the other family is not taken for stale.
+async fn check_glue(
+    resolver: &trust_dns_resolver::TokioAsyncResolver,
+    name_server: &str,
+    glue: &[IpAddr],
+) -> Option<String> {
+    match family::all_addresses(resolver, name_server).await {
+        Ok(ips) => stale_glue(glue, &ips.into_iter().collect()),
+        Err(_) => Some("glue host does not resolve".to_string()),
+    }
+}
+
+fn stale_glue(glue: &[IpAddr], live: &BTreeSet<IpAddr>) -> Option<String> {
+    let stale: Vec<String> = glue
+        .iter()
+        .filter(|ip| !live.contains(ip))
+        .map(IpAddr::to_string)
+        .collect();
+    (!stale.is_empty()).then(|| format!("stale glue {}", stale.join(", ")))
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glue_of_both_families_is_compared_with_all_addresses() {
+        let glue: Vec<IpAddr> = vec!["192.0.2.53".parse().unwrap(), "2001:db8::53".parse().unwrap()];
+        let live: BTreeSet<IpAddr> = glue.iter().copied().collect();
+        assert_eq!(stale_glue(&glue, &live), None);
+
+        let moved: BTreeSet<IpAddr> = ["192.0.2.54".parse().unwrap(), "2001:db8::53".parse().unwrap()].into();
+        assert_eq!(stale_glue(&glue, &moved).as_deref(), Some("stale glue 192.0.2.53"));
+    }
+}
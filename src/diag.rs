@@ -0,0 +1,36 @@
+//! Diagnostics written to stderr, controlled by the global `--quiet` and
+//! `--verbose` flags.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn init(quiet: bool, verbose: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Whether decoration (headers, separators) should be left out of the output.
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// Logs a notice such as a fallback to IANA, shown whatever `RUST_LOG`
+/// says; see `logging`. Silenced by `--quiet` unless `--verbose` is given
+/// as well.
+pub fn notice(message: &str) {
+    if !is_quiet() || is_verbose() {
+        tracing::info!(target: crate::logging::NOTICE_TARGET, "{}", message);
+    }
+}
+
+/// Logs a debug message, shown with `--verbose` or when `RUST_LOG`
+/// enables it; see `logging`.
+pub fn verbose(message: &str) {
+    tracing::debug!(target: crate::logging::TARGET, "{}", message);
+}
@@ -0,0 +1,101 @@
+//! Line diffs of two text records, shared by the commands that show how a
+//! WHOIS response or DNS answer changed.
+
+use colored::*;
+
+/// One line of a diff.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// Compares `old` and `new` line by line using their longest common
+/// subsequence, so a changed line shows as removed followed by added.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the length of the common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            lines.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    lines.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    lines
+}
+
+/// Prints the diff with added lines in green, removed lines in red and
+/// unchanged lines dimmed. Colors follow `colored::control`, which the
+/// tool sets from `--no-color`, `NO_COLOR` and `CLICOLOR_FORCE`; the `+ `,
+/// `- ` and two-space prefixes keep the diff readable without them.
+pub fn print_diff(old: &str, new: &str) {
+    for line in diff_lines(old, new) {
+        match line {
+            DiffLine::Same(text) => println!("  {}", text.dimmed()),
+            DiffLine::Added(text) => println!("{}", format!("+ {}", text).green()),
+            DiffLine::Removed(text) => println!("{}", format!("- {}", text).red()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_texts_are_unchanged_runs() {
+        assert_eq!(diff_lines("a\nb", "a\nb"), [DiffLine::Same("a"), DiffLine::Same("b")]);
+        assert!(diff_lines("", "").is_empty());
+    }
+
+    #[test]
+    fn inserted_lines_are_added_in_place() {
+        assert_eq!(
+            diff_lines("a\nc", "a\nb1\nb2\nc"),
+            [DiffLine::Same("a"), DiffLine::Added("b1"), DiffLine::Added("b2"), DiffLine::Same("c")]
+        );
+        assert_eq!(diff_lines("", "a"), [DiffLine::Added("a")]);
+    }
+
+    #[test]
+    fn deleted_lines_are_removed_in_place() {
+        assert_eq!(
+            diff_lines("a\nb\nc\nd", "a\nd"),
+            [DiffLine::Same("a"), DiffLine::Removed("b"), DiffLine::Removed("c"), DiffLine::Same("d")]
+        );
+        assert_eq!(diff_lines("a\nb", "a"), [DiffLine::Same("a"), DiffLine::Removed("b")]);
+    }
+
+    #[test]
+    fn changed_lines_are_removed_then_added() {
+        assert_eq!(
+            diff_lines("Registrar: Old\nStatus: ok", "Registrar: New\nStatus: ok"),
+            [DiffLine::Removed("Registrar: Old"), DiffLine::Added("Registrar: New"), DiffLine::Same("Status: ok")]
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Typed DNS answers that do not expose trust-dns record types.
+
+use data_encoding::{BASE32_DNSSEC, BASE64, HEXUPPER};
+use serde::Serialize;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::proto::rr::dnssec::rdata::DNSSECRData;
+use trust_dns_resolver::proto::rr::{RData, Record};
+
+pub use trust_dns_resolver::proto::rr::RecordType;
+
+/// One record of a DNS answer.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DnsAnswer {
+    /// Owner name, fully qualified with a trailing dot
+    pub name: String,
+    #[serde(serialize_with = "serialize_record_type")]
+    pub record_type: RecordType,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+/// The data of a record, one variant per supported record type. Any other
+/// type is kept in its presentation format.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Mx { preference: u16, exchange: String },
+    /// The character strings of the record, in order. Long values are split
+    /// into strings of at most 255 bytes.
+    Txt(Vec<String>),
+    Ns(String),
+    Cname(String),
+    Soa {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: i32,
+        retry: i32,
+        expire: i32,
+        minimum: u32,
+    },
+    Dnskey {
+        flags: u16,
+        protocol: u8,
+        algorithm: u8,
+        key_tag: u16,
+        /// Base64, as in zone files
+        public_key: String,
+    },
+    Ds {
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        /// Upper-case hex
+        digest: String,
+    },
+    Rrsig {
+        type_covered: String,
+        algorithm: u8,
+        labels: u8,
+        original_ttl: u32,
+        /// Seconds since the Unix epoch
+        expiration: u32,
+        inception: u32,
+        key_tag: u16,
+        signer_name: String,
+        /// Base64, as in zone files
+        signature: String,
+    },
+    Nsec {
+        next_name: String,
+        types: Vec<String>,
+    },
+    Nsec3 {
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        /// Upper-case hex, empty when the zone uses no salt
+        salt: String,
+        /// Base32hex, as in zone files
+        next_hashed_owner: String,
+        types: Vec<String>,
+    },
+    Naptr {
+        order: u16,
+        preference: u16,
+        flags: String,
+        services: String,
+        regexp: String,
+        replacement: String,
+    },
+    Tlsa {
+        usage: u8,
+        selector: u8,
+        matching_type: u8,
+        /// Upper-case hex, as in zone files
+        cert_data: String,
+    },
+    Other(String),
+}
+
+impl DnsAnswer {
+    /// Converts every record of a resolver lookup, including the CNAME
+    /// chain that led to the answer.
+    pub fn from_lookup(lookup: &Lookup) -> Vec<DnsAnswer> {
+        lookup.records().iter().map(DnsAnswer::from).collect()
+    }
+}
+
+impl From<&Record> for DnsAnswer {
+    fn from(record: &Record) -> Self {
+        DnsAnswer {
+            name: record.name().to_string(),
+            record_type: record.record_type(),
+            ttl: record.ttl(),
+            data: record.data().map(RecordData::from).unwrap_or(RecordData::Other(String::new())),
+        }
+    }
+}
+
+impl From<&RData> for RecordData {
+    fn from(data: &RData) -> Self {
+        match data {
+            RData::A(ip) => RecordData::A(ip.0),
+            RData::AAAA(ip) => RecordData::Aaaa(ip.0),
+            RData::MX(mx) => RecordData::Mx {
+                preference: mx.preference(),
+                exchange: mx.exchange().to_string(),
+            },
+            RData::TXT(txt) => RecordData::Txt(
+                txt.txt_data()
+                    .iter()
+                    .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+                    .collect(),
+            ),
+            RData::NS(ns) => RecordData::Ns(ns.to_string()),
+            RData::CNAME(cname) => RecordData::Cname(cname.to_string()),
+            RData::SOA(soa) => RecordData::Soa {
+                mname: soa.mname().to_string(),
+                rname: soa.rname().to_string(),
+                serial: soa.serial(),
+                refresh: soa.refresh(),
+                retry: soa.retry(),
+                expire: soa.expire(),
+                minimum: soa.minimum(),
+            },
+            RData::NAPTR(naptr) => RecordData::Naptr {
+                order: naptr.order(),
+                preference: naptr.preference(),
+                flags: String::from_utf8_lossy(naptr.flags()).into_owned(),
+                services: String::from_utf8_lossy(naptr.services()).into_owned(),
+                regexp: String::from_utf8_lossy(naptr.regexp()).into_owned(),
+                replacement: naptr.replacement().to_string(),
+            },
+            RData::TLSA(tlsa) => RecordData::Tlsa {
+                usage: tlsa.cert_usage().into(),
+                selector: tlsa.selector().into(),
+                matching_type: tlsa.matching().into(),
+                cert_data: HEXUPPER.encode(tlsa.cert_data()),
+            },
+            RData::DNSSEC(dnssec) => RecordData::from(dnssec),
+            other => RecordData::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<&DNSSECRData> for RecordData {
+    fn from(data: &DNSSECRData) -> Self {
+        match data {
+            DNSSECRData::DNSKEY(key) => RecordData::Dnskey {
+                flags: key.flags(),
+                protocol: 3,
+                algorithm: key.algorithm().into(),
+                key_tag: key.calculate_key_tag().unwrap_or_default(),
+                public_key: BASE64.encode(key.public_key()),
+            },
+            DNSSECRData::DS(ds) => RecordData::Ds {
+                key_tag: ds.key_tag(),
+                algorithm: ds.algorithm().into(),
+                digest_type: ds.digest_type().into(),
+                digest: HEXUPPER.encode(ds.digest()),
+            },
+            DNSSECRData::RRSIG(sig) => RecordData::Rrsig {
+                type_covered: sig.type_covered().to_string(),
+                algorithm: sig.algorithm().into(),
+                labels: sig.num_labels(),
+                original_ttl: sig.original_ttl(),
+                expiration: sig.sig_expiration(),
+                inception: sig.sig_inception(),
+                key_tag: sig.key_tag(),
+                signer_name: sig.signer_name().to_string(),
+                signature: BASE64.encode(sig.sig()),
+            },
+            DNSSECRData::NSEC(nsec) => RecordData::Nsec {
+                next_name: nsec.next_domain_name().to_string(),
+                types: nsec.type_bit_maps().iter().map(ToString::to_string).collect(),
+            },
+            DNSSECRData::NSEC3(nsec3) => RecordData::Nsec3 {
+                hash_algorithm: nsec3.hash_algorithm().into(),
+                flags: nsec3.flags(),
+                iterations: nsec3.iterations(),
+                salt: HEXUPPER.encode(nsec3.salt()),
+                next_hashed_owner: BASE32_DNSSEC.encode(nsec3.next_hashed_owner_name()).to_uppercase(),
+                types: nsec3.type_bit_maps().iter().map(ToString::to_string).collect(),
+            },
+            other => RecordData::Other(other.to_string()),
+        }
+    }
+}
+
+/// Formats the character strings of a TXT record as zone files do, each in
+/// double quotes: `"v=DKIM1; p=MIIB..." "...IDAQAB"`.
+pub fn quote_txt(strings: &[String]) -> String {
+    strings
+        .iter()
+        .map(|string| format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats an RRSIG timestamp the way zone files do, `YYYYMMDDHHmmSS` in UTC.
+pub fn format_sig_time(timestamp: u32) -> String {
+    chrono::DateTime::from_timestamp(i64::from(timestamp), 0)
+        .map(|time| time.format("%Y%m%d%H%M%S").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Formats the data without labels, the way `dig +short` does.
+impl fmt::Display for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordData::A(ip) => write!(f, "{}", ip),
+            RecordData::Aaaa(ip) => write!(f, "{}", ip),
+            RecordData::Mx { preference, exchange } => write!(f, "{} {}", preference, exchange),
+            // The strings of a long value such as a DKIM key make up one
+            // string, so they are joined without a separator
+            RecordData::Txt(strings) => f.write_str(&strings.concat()),
+            RecordData::Soa { mname, rname, serial, refresh, retry, expire, minimum } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            RecordData::Dnskey { flags, protocol, algorithm, public_key, .. } => {
+                write!(f, "{} {} {} {}", flags, protocol, algorithm, public_key)
+            }
+            RecordData::Ds { key_tag, algorithm, digest_type, digest } => {
+                write!(f, "{} {} {} {}", key_tag, algorithm, digest_type, digest)
+            }
+            RecordData::Rrsig {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer_name,
+                signature,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {} {} {}",
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                format_sig_time(*expiration),
+                format_sig_time(*inception),
+                key_tag,
+                signer_name,
+                signature
+            ),
+            RecordData::Nsec { next_name, types } => write!(f, "{} {}", next_name, types.join(" ")),
+            RecordData::Nsec3 { hash_algorithm, flags, iterations, salt, next_hashed_owner, types } => write!(
+                f,
+                "{} {} {} {} {} {}",
+                hash_algorithm,
+                flags,
+                iterations,
+                if salt.is_empty() { "-" } else { salt },
+                next_hashed_owner,
+                types.join(" ")
+            ),
+            RecordData::Naptr { order, preference, flags, services, regexp, replacement } => write!(
+                f,
+                "{} {} {} {} {} {}",
+                order,
+                preference,
+                quote_txt(std::slice::from_ref(flags)),
+                quote_txt(std::slice::from_ref(services)),
+                quote_txt(std::slice::from_ref(regexp)),
+                replacement
+            ),
+            RecordData::Tlsa { usage, selector, matching_type, cert_data } => {
+                write!(f, "{} {} {} {}", usage, selector, matching_type, cert_data)
+            }
+            RecordData::Ns(name) | RecordData::Cname(name) | RecordData::Other(name) => f.write_str(name),
+        }
+    }
+}
+
+fn serialize_record_type<S: serde::Serializer>(record_type: &RecordType, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(record_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use trust_dns_resolver::proto::rr::rdata::tlsa::{CertUsage, Matching, Selector};
+    use trust_dns_resolver::proto::rr::rdata::{A, AAAA, CNAME, MX, NAPTR, SOA, TLSA, TXT};
+    use trust_dns_resolver::proto::rr::Name;
+
+    fn record(data: RData) -> Record {
+        Record::from_rdata(Name::from_str("example.com.").unwrap(), 300, data)
+    }
+
+    fn name(text: &str) -> Name {
+        Name::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn address_records_convert_to_typed_answers() {
+        let answer = DnsAnswer::from(&record(RData::A(A(Ipv4Addr::new(192, 0, 2, 1)))));
+        assert_eq!(
+            answer,
+            DnsAnswer {
+                name: "example.com.".to_string(),
+                record_type: RecordType::A,
+                ttl: 300,
+                data: RecordData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            }
+        );
+        let answer = DnsAnswer::from(&record(RData::AAAA(AAAA(Ipv6Addr::LOCALHOST))));
+        assert_eq!(answer.record_type, RecordType::AAAA);
+        assert_eq!(answer.data, RecordData::Aaaa(Ipv6Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn mx_soa_and_cname_keep_their_fields() {
+        let mx = DnsAnswer::from(&record(RData::MX(MX::new(10, name("mail.example.com.")))));
+        assert_eq!(mx.data, RecordData::Mx { preference: 10, exchange: "mail.example.com.".to_string() });
+        assert_eq!(mx.data.to_string(), "10 mail.example.com.");
+
+        let soa = SOA::new(name("ns.example.com."), name("hostmaster.example.com."), 2024101401, 7200, 3600, 1209600, 300);
+        let soa = DnsAnswer::from(&record(RData::SOA(soa)));
+        assert_eq!(soa.data.to_string(), "ns.example.com. hostmaster.example.com. 2024101401 7200 3600 1209600 300");
+
+        let cname = DnsAnswer::from(&record(RData::CNAME(CNAME(name("target.example.net.")))));
+        assert_eq!(cname.data, RecordData::Cname("target.example.net.".to_string()));
+    }
+
+    #[test]
+    fn txt_strings_are_kept_in_order() {
+        let txt = DnsAnswer::from(&record(RData::TXT(TXT::new(vec!["v=spf1".to_string(), " -all".to_string()]))));
+        assert_eq!(txt.data, RecordData::Txt(vec!["v=spf1".to_string(), " -all".to_string()]));
+    }
+
+    #[test]
+    fn answers_serialize_with_the_type_name_and_tagged_data() {
+        let answer = DnsAnswer::from(&record(RData::MX(MX::new(10, name("mail.example.com.")))));
+        assert_eq!(
+            serde_json::to_value(&answer).unwrap(),
+            serde_json::json!({
+                "name": "example.com.",
+                "record_type": "MX",
+                "ttl": 300,
+                "data": { "type": "Mx", "value": { "preference": 10, "exchange": "mail.example.com." } }
+            })
+        );
+    }
+
+    #[test]
+    fn dnssec_data_renders_as_in_zone_files() {
+        let digest = "BE74359954660069D5C63D200C39F5603827D7DD02B56F120EE9F3A86764247C";
+        let ds = RecordData::Ds { key_tag: 370, algorithm: 13, digest_type: 2, digest: digest.to_string() };
+        assert_eq!(ds.to_string(), format!("370 13 2 {}", digest));
+
+        let nsec3 = RecordData::Nsec3 {
+            hash_algorithm: 1,
+            flags: 0,
+            iterations: 0,
+            salt: String::new(),
+            next_hashed_owner: "CK0POJMG874LJREF7EFN8430QVIT8BSM".to_string(),
+            types: vec!["NS".to_string(), "SOA".to_string(), "RRSIG".to_string()],
+        };
+        assert_eq!(nsec3.to_string(), "1 0 0 - CK0POJMG874LJREF7EFN8430QVIT8BSM NS SOA RRSIG");
+        assert_eq!(format_sig_time(1_728_950_400), "20241015000000");
+    }
+
+    #[test]
+    fn naptr_and_tlsa_records_render_as_in_zone_files() {
+        let naptr = NAPTR::new(
+            100,
+            10,
+            b"S".to_vec().into_boxed_slice(),
+            b"SIP+D2U".to_vec().into_boxed_slice(),
+            Box::default(),
+            name("_sip._udp.example.com."),
+        );
+        let naptr = DnsAnswer::from(&record(RData::NAPTR(naptr)));
+        assert_eq!(
+            naptr.data,
+            RecordData::Naptr {
+                order: 100,
+                preference: 10,
+                flags: "S".to_string(),
+                services: "SIP+D2U".to_string(),
+                regexp: String::new(),
+                replacement: "_sip._udp.example.com.".to_string(),
+            }
+        );
+        assert_eq!(naptr.data.to_string(), r#"100 10 "S" "SIP+D2U" "" _sip._udp.example.com."#);
+
+        let tlsa = TLSA::new(CertUsage::DomainIssued, Selector::Spki, Matching::Sha256, vec![0xab, 0xcd, 0x01]);
+        let tlsa = DnsAnswer::from(&record(RData::TLSA(tlsa)));
+        assert_eq!(
+            tlsa.data,
+            RecordData::Tlsa { usage: 3, selector: 1, matching_type: 1, cert_data: "ABCD01".to_string() }
+        );
+        assert_eq!(tlsa.data.to_string(), "3 1 1 ABCD01");
+    }
+
+    #[test]
+    fn chunked_txt_records_join_without_a_separator() {
+        // A 2048-bit DKIM key is published as two strings, the first 255 bytes long
+        let key = format!("v=DKIM1; k=rsa; p={}", "MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA".repeat(9));
+        let (first, second) = key.split_at(255);
+        let record = record(RData::TXT(TXT::new(vec![first.to_string(), second.to_string()])));
+        let answer = DnsAnswer::from(&record);
+
+        assert_eq!(answer.data, RecordData::Txt(vec![first.to_string(), second.to_string()]));
+        assert_eq!(answer.data.to_string(), key);
+        let RecordData::Txt(strings) = &answer.data else { unreachable!() };
+        assert_eq!(quote_txt(strings), format!("\"{}\" \"{}\"", first, second));
+    }
+
+    #[test]
+    fn quoted_txt_strings_escape_quotes_and_backslashes() {
+        assert_eq!(quote_txt(&[r#"say "hi" \o/"#.to_string()]), r#""say \"hi\" \\o/""#);
+    }
+}
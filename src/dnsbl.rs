@@ -0,0 +1,119 @@
+//! DNS blocklist checks for `dns --check-dnsbl`. A list is asked about an
+//! address by looking up the address reversed under the list's zone:
+//! `192.0.2.1` in `zen.spamhaus.org` is `1.2.0.192.zen.spamhaus.org`. An A
+//! record means the address is listed, and the TXT record of the same name
+//! usually says why.
+
+use colored::*;
+use std::net::IpAddr;
+use tokio::task::JoinSet;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::proto::rr::RecordType;
+use whois_dns_tool::dns::{DnsAnswer, RecordData};
+
+use crate::{concurrency, create_resolver, diag};
+
+/// Asked when no `--dnsbl` is given. Both are free for low-volume,
+/// non-commercial use only; see their usage policies before checking many
+/// addresses or using them in a product.
+pub const DEFAULT_ZONES: &[&str] = &["zen.spamhaus.org", "bl.spamcop.net"];
+
+/// The answer of one list about one address.
+enum Status {
+    /// The A records returned, with the TXT explanation if there is one
+    Listed { codes: Vec<String>, reason: Option<String> },
+    NotListed,
+    /// The list answered with an error code instead of a listing, as
+    /// Spamhaus does for queries through public resolvers
+    Refused(String),
+    Failed(String),
+}
+
+/// The name to look up for `ip` in `zone`: the octets of an IPv4 address
+/// or the nibbles of an IPv6 address, in reverse order.
+pub fn query_name(ip: IpAddr, zone: &str) -> String {
+    let labels: Vec<String> = match ip {
+        IpAddr::V4(ip) => ip.octets().iter().rev().map(u8::to_string).collect(),
+        IpAddr::V6(ip) => ip
+            .octets()
+            .iter()
+            .rev()
+            .flat_map(|byte| [byte & 0x0f, byte >> 4])
+            .map(|nibble| format!("{:x}", nibble))
+            .collect(),
+    };
+    format!("{}.{}", labels.join("."), zone.trim_end_matches('.'))
+}
+
+async fn check(ip: IpAddr, zone: &str) -> Status {
+    let name = query_name(ip, zone);
+    diag::verbose(&format!("Looking up {}", name));
+    let lookup = match concurrency::limited(create_resolver().lookup(name.as_str(), RecordType::A)).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => return Status::NotListed,
+        Err(e) => return Status::Failed(e.to_string()),
+    };
+    let codes: Vec<String> = DnsAnswer::from_lookup(&lookup)
+        .into_iter()
+        .filter_map(|answer| match answer.data {
+            RecordData::A(code) => Some(code),
+            _ => None,
+        })
+        .map(|code| code.to_string())
+        .collect();
+    // Listings are in 127.0.0.0/8; 127.255.255.x are the error codes of
+    // Spamhaus and similar lists
+    if let Some(code) = codes.iter().find(|code| code.starts_with("127.255.255.")) {
+        return Status::Refused(format!("the list refused the query ({})", code));
+    }
+    if codes.is_empty() {
+        return Status::NotListed;
+    }
+
+    let reason = match concurrency::limited(create_resolver().lookup(name.as_str(), RecordType::TXT)).await {
+        Ok(lookup) => DnsAnswer::from_lookup(&lookup).iter().find_map(|answer| match &answer.data {
+            RecordData::Txt(strings) => Some(strings.concat()),
+            _ => None,
+        }),
+        Err(_) => None,
+    };
+    Status::Listed { codes, reason }
+}
+
+/// Asks every zone about every address at once and prints one line per
+/// address and list.
+pub async fn print_listings(addresses: &[IpAddr], zones: &[String]) -> anyhow::Result<()> {
+    let mut checks = JoinSet::new();
+    for (index, ip) in addresses.iter().enumerate() {
+        for (offset, zone) in zones.iter().enumerate() {
+            let (position, ip, zone) = (index * zones.len() + offset, *ip, zone.clone());
+            checks.spawn(async move { (position, ip, check(ip, &zone).await, zone) });
+        }
+    }
+    // Checks finish in any order; print them in address and zone order
+    let mut results = Vec::new();
+    while let Some(joined) = checks.join_next().await {
+        results.push(joined?);
+    }
+    results.sort_by_key(|(position, ..)| *position);
+
+    if !diag::is_quiet() {
+        println!("{}", "DNS Blocklists:".green().bold());
+        println!("{}", "-".repeat(50));
+    }
+    if addresses.is_empty() {
+        diag::notice("No addresses to check");
+    }
+    for (_, ip, status, zone) in results {
+        let line = match status {
+            Status::Listed { codes, reason } => {
+                let reason = reason.map_or(String::new(), |reason| format!(": {}", reason));
+                format!("{} ({}){}", "LISTED".red().bold(), codes.join(", "), reason)
+            }
+            Status::NotListed => "not listed".green().to_string(),
+            Status::Refused(message) | Status::Failed(message) => format!("{} {}", "unknown".yellow(), message),
+        };
+        println!("{:<40} {:<20} {}", ip.to_string(), zone, line);
+    }
+    Ok(())
+}
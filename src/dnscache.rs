@@ -0,0 +1,93 @@
+//! In-memory cache of answers from direct DNS queries, kept for the TTL of
+//! the records. Lookups through the shared resolver use its own cache, which
+//! follows the same rules; `--no-dns-cache` turns both off.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use trust_dns_resolver::proto::op::Message;
+use trust_dns_resolver::proto::rr::{DNSClass, RData, RecordType};
+
+use crate::diag;
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// `(name, type, class, server)` of a query.
+type Key = (String, RecordType, DNSClass, SocketAddr);
+
+struct Entry {
+    message: Message,
+    expires: Instant,
+}
+
+fn entries() -> &'static Mutex<HashMap<Key, Entry>> {
+    static ENTRIES: OnceLock<Mutex<HashMap<Key, Entry>>> = OnceLock::new();
+    ENTRIES.get_or_init(Default::default)
+}
+
+pub fn init(disabled: bool) {
+    DISABLED.store(disabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    !DISABLED.load(Ordering::Relaxed)
+}
+
+fn key(domain: &str, record_type: RecordType, class: DNSClass, server: SocketAddr) -> Key {
+    (domain.trim_end_matches('.').to_lowercase(), record_type, class, server)
+}
+
+/// Returns the cached response to the query if its TTL has not run out.
+pub fn get(domain: &str, record_type: RecordType, class: DNSClass, server: SocketAddr) -> Option<Message> {
+    if !is_enabled() {
+        return None;
+    }
+    let key = key(domain, record_type, class, server);
+    let mut entries = entries().lock().unwrap();
+    match entries.get(&key) {
+        Some(entry) if entry.expires > Instant::now() => {
+            diag::verbose(&format!("Answer for {} {} from {} is cached", key.0, record_type, server));
+            Some(entry.message.clone())
+        }
+        Some(_) => {
+            entries.remove(&key);
+            None
+        }
+        None => None,
+    }
+}
+
+/// Stores a response for the lowest TTL of its answers. A response without
+/// answers is kept for the negative TTL of the zone's SOA record, and not at
+/// all when there is none.
+pub fn insert(domain: &str, record_type: RecordType, class: DNSClass, server: SocketAddr, message: &Message) {
+    if !is_enabled() {
+        return;
+    }
+    let ttl = match message.answers().iter().map(|record| record.ttl()).min() {
+        Some(ttl) => ttl,
+        None => match message.name_servers().iter().find_map(|record| match record.data() {
+            Some(RData::SOA(soa)) => Some(record.ttl().min(soa.minimum())),
+            _ => None,
+        }) {
+            Some(ttl) => ttl,
+            None => return,
+        },
+    };
+    if ttl == 0 {
+        return;
+    }
+
+    let now = Instant::now();
+    let mut entries = entries().lock().unwrap();
+    entries.retain(|_, entry| entry.expires > now);
+    entries.insert(
+        key(domain, record_type, class, server),
+        Entry {
+            message: message.clone(),
+            expires: now + Duration::from_secs(u64::from(ttl)),
+        },
+    );
+}
@@ -0,0 +1,64 @@
+//! Character sets at the edges: WHOIS responses that are not UTF-8 are read
+//! as Latin-1, and `whois --output` files are written in the encoding given
+//! with `--output-encoding`.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use std::path::Path;
+
+use crate::diag;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutputEncoding {
+    #[default]
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    /// Little-endian with a byte order mark, as Windows tools expect
+    #[value(name = "utf-16", alias = "utf16")]
+    Utf16,
+    /// ISO 8859-1; characters outside it are an error
+    #[value(name = "latin1", alias = "iso-8859-1")]
+    Latin1,
+}
+
+/// Decodes a WHOIS response. Servers rarely say which character set they
+/// use; valid UTF-8 is taken as such and anything else as Latin-1, which
+/// older European registries still send and which never fails to decode.
+pub fn decode_response(server: &str, bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            diag::verbose(&format!("Response from {} is not UTF-8; reading it as Latin-1", server));
+            e.into_bytes().into_iter().map(char::from).collect()
+        }
+    }
+}
+
+pub fn encode(text: &str, encoding: OutputEncoding) -> Result<Vec<u8>> {
+    match encoding {
+        OutputEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        OutputEncoding::Utf16 => {
+            let units = std::iter::once(0xFEFF).chain(text.encode_utf16());
+            Ok(units.flat_map(u16::to_le_bytes).collect())
+        }
+        OutputEncoding::Latin1 => text
+            .chars()
+            .map(|c| {
+                u8::try_from(c).map_err(|_| {
+                    let line = text.lines().position(|line| line.contains(c)).map_or(1, |index| index + 1);
+                    anyhow::anyhow!("{:?} on line {} cannot be written as Latin-1; use --output-encoding utf-8", c, line)
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Writes `text` to `path` in `encoding`, ending it with a newline.
+pub fn write_file(path: &Path, text: &str, encoding: OutputEncoding) -> Result<()> {
+    let mut text = text.to_string();
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    let bytes = encode(&text, encoding)?;
+    std::fs::write(path, bytes).map_err(|e| anyhow::anyhow!("Cannot write {}: {}", path.display(), e))
+}
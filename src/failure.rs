@@ -0,0 +1,88 @@
+//! Exit codes that tell scripts why a run failed.
+
+use std::fmt;
+use std::io::ErrorKind;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+
+use crate::whois::LookupError;
+
+/// Broad categories of lookup failures.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FailureKind {
+    Other,
+    /// The name does not exist or has no records of the requested type
+    NotFound,
+    Timeout,
+    /// The server refused or dropped the connection, or could not be found
+    Network,
+}
+
+impl FailureKind {
+    /// Looks through the error chain for the I/O or resolver error that
+    /// caused the failure.
+    pub fn classify(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<LookupError>() {
+                return match e {
+                    LookupError::TimedOut { .. } => FailureKind::Timeout,
+                    _ => FailureKind::Network,
+                };
+            }
+            if let Some(e) = cause.downcast_ref::<ResolveError>() {
+                return match e.kind() {
+                    ResolveErrorKind::NoRecordsFound { .. } => FailureKind::NotFound,
+                    ResolveErrorKind::Timeout => FailureKind::Timeout,
+                    ResolveErrorKind::Io(_) | ResolveErrorKind::NoConnections => FailureKind::Network,
+                    _ => FailureKind::Other,
+                };
+            }
+            if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+                return match e.kind() {
+                    ErrorKind::TimedOut | ErrorKind::WouldBlock => FailureKind::Timeout,
+                    ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::NotConnected
+                    | ErrorKind::AddrNotAvailable
+                    | ErrorKind::NotFound => FailureKind::Network,
+                    _ => FailureKind::Other,
+                };
+            }
+        }
+        FailureKind::Other
+    }
+
+    pub fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::Other => 1,
+            FailureKind::NotFound => 2,
+            FailureKind::Timeout => 3,
+            FailureKind::Network => 4,
+        }
+    }
+}
+
+/// An error that ends the program with a specific exit code instead of 1.
+#[derive(Debug)]
+pub struct Exit {
+    pub code: u8,
+    pub message: String,
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Exit {}
+
+/// The exit code for an error returned from a command.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error.downcast_ref::<Exit>().map_or(1, |exit| exit.code)
+}
+
+/// Whether the error only carries an exit code, with nothing to print.
+pub fn is_silent(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Exit>().is_some_and(|exit| exit.message.is_empty())
+}
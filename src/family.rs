@@ -0,0 +1,94 @@
+//! The global `-4` and `-6` flags: names the tool resolves by itself, such
+//! as WHOIS servers, name servers and CNAME targets, are resolved to one
+//! address family only. Record types asked for explicitly are looked up as
+//! given.
+
+use anyhow::Result;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use trust_dns_resolver::config::LookupIpStrategy;
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+use trust_dns_resolver::TokioAsyncResolver;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IpFamily {
+    Any,
+    V4,
+    V6,
+}
+
+static FAMILY: AtomicU8 = AtomicU8::new(0);
+
+pub fn init(ipv4: bool, ipv6: bool) {
+    let family = match (ipv4, ipv6) {
+        (true, false) => IpFamily::V4,
+        (false, true) => IpFamily::V6,
+        _ => IpFamily::Any,
+    };
+    FAMILY.store(family as u8, Ordering::Relaxed);
+}
+
+pub fn get() -> IpFamily {
+    match FAMILY.load(Ordering::Relaxed) {
+        1 => IpFamily::V4,
+        2 => IpFamily::V6,
+        _ => IpFamily::Any,
+    }
+}
+
+impl IpFamily {
+    /// `IPv4` or `IPv6`, or `None` when no family is forced.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            IpFamily::Any => None,
+            IpFamily::V4 => Some("IPv4"),
+            IpFamily::V6 => Some("IPv6"),
+        }
+    }
+
+    pub fn allows(self, ip: &IpAddr) -> bool {
+        match self {
+            IpFamily::Any => true,
+            IpFamily::V4 => ip.is_ipv4(),
+            IpFamily::V6 => ip.is_ipv6(),
+        }
+    }
+
+    /// Whether an address record of `record_type` belongs to the family;
+    /// other types always do.
+    pub fn allows_record_type(self, record_type: RecordType) -> bool {
+        !matches!((self, record_type), (IpFamily::V4, RecordType::AAAA) | (IpFamily::V6, RecordType::A))
+    }
+
+    /// The resolver setting for `lookup_ip`.
+    pub fn ip_strategy(self) -> LookupIpStrategy {
+        match self {
+            IpFamily::Any => LookupIpStrategy::default(),
+            IpFamily::V4 => LookupIpStrategy::Ipv4Only,
+            IpFamily::V6 => LookupIpStrategy::Ipv6Only,
+        }
+    }
+}
+
+/// The IPv4 and IPv6 addresses of `name`, whatever `-4` or `-6` say, for
+/// checks that ask whether a host exists rather than how to reach it: a
+/// name server with only IPv6 addresses still resolves under `-4`. Fails
+/// when the name has addresses of neither family.
+pub async fn all_addresses(resolver: &TokioAsyncResolver, name: &str) -> Result<Vec<IpAddr>> {
+    let (v4, v6) = tokio::join!(resolver.lookup(name, RecordType::A), resolver.lookup(name, RecordType::AAAA));
+    let addresses: Vec<IpAddr> = [&v4, &v6]
+        .into_iter()
+        .filter_map(|lookup| lookup.as_ref().ok())
+        .flat_map(|lookup| lookup.iter())
+        .filter_map(|data| match data {
+            RData::A(ip) => Some(IpAddr::V4(ip.0)),
+            RData::AAAA(ip) => Some(IpAddr::V6(ip.0)),
+            _ => None,
+        })
+        .collect();
+    match (addresses.is_empty(), v4) {
+        (false, _) => Ok(addresses),
+        (true, Err(e)) => Err(e.into()),
+        (true, Ok(_)) => Err(anyhow::anyhow!("{} has no addresses", name)),
+    }
+}
@@ -0,0 +1,275 @@
+//! Content hashes of WHOIS responses for `--hash`. The hash is taken over a
+//! canonical form of the response that leaves out what changes between two
+//! queries of the same record, such as the "last update of WHOIS database"
+//! footer, comments and disclaimers, so it only changes when the record
+//! does. The hash of every target is kept in
+//! `$XDG_CACHE_HOME/whois-dns/hashes` (or `~/.cache/whois-dns/hashes`) to
+//! report a change on the next run; the store is read once per run and
+//! written back at the end.
+
+use anyhow::Result;
+use data_encoding::HEXLOWER;
+use ring::digest::{digest, SHA256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::{diag, parse};
+
+/// Lines that differ between queries even when the record did not change,
+/// matched case-insensitively at the start of the line.
+const VOLATILE_MARKERS: &[&str] = &[
+    ">>> last update of whois database",
+    "last update of whois database",
+    "last update of rdap database",
+    "query time",
+    "timestamp",
+    "database last updated",
+    "whois lookup made at",
+    "this query was served",
+];
+
+/// How a hash compares with the one stored for the target.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// No hash was stored for the target
+    New,
+    Unchanged,
+    Changed { previous: String, since: String },
+}
+
+/// The response without comments, disclaimers, volatile lines and blank
+/// lines, with runs of whitespace collapsed and field names lowercased.
+pub fn canonicalize(response: &str) -> String {
+    let hidden = parse::disclaimer_lines(response);
+    response
+        .lines()
+        .zip(hidden)
+        .filter(|(_, hidden)| !hidden)
+        .map(|(line, _)| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty() && !line.starts_with('%') && !line.starts_with('#'))
+        .filter(|line| {
+            let lower = line.to_lowercase();
+            !VOLATILE_MARKERS.iter().any(|marker| lower.starts_with(marker))
+        })
+        .map(|line| match line.split_once(':') {
+            Some((key, value)) => format!("{}:{}", key.to_lowercase(), value),
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// SHA-256 of the canonical form, in lower-case hex.
+pub fn hash_response(response: &str) -> String {
+    HEXLOWER.encode(digest(&SHA256, canonicalize(response).as_bytes()).as_ref())
+}
+
+fn store_path() -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("whois-dns").join("hashes"))
+}
+
+/// `target -> (hash, time it was first seen)`
+type Hashes = BTreeMap<String, (String, String)>;
+
+/// One tab-separated line per target; a missing file holds no hashes.
+fn read_store(path: &Path) -> Result<Hashes> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(anyhow::anyhow!("Cannot read {}: {}", path.display(), e)),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split('\t');
+            let (target, hash, since) = (columns.next()?, columns.next()?, columns.next()?);
+            Some((target.to_string(), (hash.to_string(), since.to_string())))
+        })
+        .collect())
+}
+
+/// Writes the whole store to a file of its own next to `path` and renames
+/// it into place, so a reader never sees a half-written store.
+fn write_store(path: &Path, hashes: &Hashes) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let lines: String = hashes
+        .iter()
+        .map(|(target, (hash, since))| format!("{}\t{}\t{}\n", target, hash, since))
+        .collect();
+    let partial = path.with_extension(format!("{}.partial", std::process::id()));
+    std::fs::write(&partial, lines)?;
+    std::fs::rename(&partial, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&partial);
+    })?;
+    Ok(())
+}
+
+/// The hashes of one run: read once on first use, updated in memory, and
+/// written back by `save`.
+struct Store {
+    path: PathBuf,
+    /// The hashes the file held, with those recorded by this run
+    hashes: Hashes,
+    /// The targets recorded since the last `save`
+    recorded: Hashes,
+}
+
+impl Store {
+    fn load(path: PathBuf) -> Result<Self> {
+        let hashes = read_store(&path)?;
+        Ok(Store { path, hashes, recorded: BTreeMap::new() })
+    }
+
+    fn record(&mut self, target: &str, hash: &str) -> Change {
+        let change = match self.hashes.get(target) {
+            None => Change::New,
+            Some((stored, _)) if stored == hash => return Change::Unchanged,
+            Some((stored, since)) => Change::Changed { previous: stored.clone(), since: since.clone() },
+        };
+        let entry = (hash.to_string(), chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string());
+        self.hashes.insert(target.to_string(), entry.clone());
+        self.recorded.insert(target.to_string(), entry);
+        change
+    }
+
+    /// Writes the recorded hashes over what the file holds now, keeping
+    /// the targets another run stored in the meantime.
+    fn save(&mut self) -> Result<()> {
+        if self.recorded.is_empty() {
+            return Ok(());
+        }
+        let mut hashes = read_store(&self.path)?;
+        hashes.extend(std::mem::take(&mut self.recorded));
+        write_store(&self.path, &hashes)?;
+        self.hashes = hashes;
+        Ok(())
+    }
+}
+
+/// Read on first use; `None` when the store cannot be read, so that a store
+/// that is only unreadable is never overwritten.
+static STORE: OnceLock<Option<Mutex<Store>>> = OnceLock::new();
+
+fn store() -> Option<&'static Mutex<Store>> {
+    STORE
+        .get_or_init(|| {
+            let path = store_path().ok_or_else(|| anyhow::anyhow!("No cache directory (HOME is not set)"));
+            match path.and_then(Store::load) {
+                Ok(store) => Some(Mutex::new(store)),
+                Err(e) => {
+                    diag::verbose(&format!("Could not read the stored hashes: {}", e));
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+/// Compares `hash` with the one stored for `target` and records it, to be
+/// written by `save`. A store that cannot be read is reported with
+/// `--verbose` and the hash counts as new, so a lookup never fails because
+/// of it.
+pub fn record(target: &str, hash: &str) -> Change {
+    store().map_or(Change::New, |store| store.lock().unwrap().record(target, hash))
+}
+
+/// Writes the hashes recorded by `record` to the store, once per command.
+/// A failure is reported with `--verbose`, as in `record`.
+pub fn save() {
+    let Some(store) = STORE.get().and_then(Option::as_ref) else {
+        return;
+    };
+    if let Err(e) = store.lock().unwrap().save() {
+        diag::verbose(&format!("Could not update the stored hashes: {}", e));
+    }
+}
+
+/// `<target> <sha256>`, followed by `changed` when the stored hash was
+/// different; the previous hash and when it was stored go to stderr.
+pub fn print_hash(target: &str, response: &str) {
+    let hash = hash_response(response);
+    match record(target, &hash) {
+        Change::Changed { previous, since } => {
+            println!("{} {} changed", target, hash);
+            diag::notice(&format!("{} changed; the hash stored {} was {}", target, since, previous));
+        }
+        Change::New | Change::Unchanged => println!("{} {}", target, hash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("whois-dns-hashes-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("hashes")
+    }
+
+    #[test]
+    fn records_in_memory_until_saved() {
+        let path = temp_store("memory");
+        let mut store = Store::load(path.clone()).unwrap();
+        assert_eq!(store.record("example.com", "aaa"), Change::New);
+        assert_eq!(store.record("example.com", "aaa"), Change::Unchanged);
+        assert!(matches!(store.record("example.com", "bbb"), Change::Changed { previous, .. } if previous == "aaa"));
+        assert!(!path.exists());
+
+        store.save().unwrap();
+        let saved = read_store(&path).unwrap();
+        assert_eq!(saved["example.com"].0, "bbb");
+        assert_eq!(Store::load(path.clone()).unwrap().record("example.com", "bbb"), Change::Unchanged);
+        assert!(!path.with_extension(format!("{}.partial", std::process::id())).exists());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn saving_keeps_what_another_run_stored_meanwhile() {
+        let path = temp_store("merge");
+        let mut first = Store::load(path.clone()).unwrap();
+        let mut second = Store::load(path.clone()).unwrap();
+        first.record("example.com", "aaa");
+        second.record("example.net", "bbb");
+        first.save().unwrap();
+        second.save().unwrap();
+
+        let saved = read_store(&path).unwrap();
+        assert_eq!(saved.keys().collect::<Vec<_>>(), ["example.com", "example.net"]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn concurrent_records_are_all_saved() {
+        let path = temp_store("concurrent");
+        let store = Mutex::new(Store::load(path.clone()).unwrap());
+        std::thread::scope(|scope| {
+            for worker in 0..8 {
+                let store = &store;
+                scope.spawn(move || {
+                    for n in 0..25 {
+                        store.lock().unwrap().record(&format!("target{}-{}.example", worker, n), "hash");
+                    }
+                });
+            }
+        });
+        store.into_inner().unwrap().save().unwrap();
+        assert_eq!(read_store(&path).unwrap().len(), 200);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn volatile_lines_do_not_change_the_hash() {
+        let first = "Domain Name: EXAMPLE.COM\n>>> Last update of WHOIS database: 2024-10-14T10:00:00Z <<<\n";
+        let second = "domain name:   EXAMPLE.COM\n\n>>> Last update of WHOIS database: 2024-10-15T08:30:00Z <<<\n";
+        assert_eq!(hash_response(first), hash_response(second));
+        assert_ne!(hash_response(first), hash_response("Domain Name: EXAMPLE.NET\n"));
+    }
+}
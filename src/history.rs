@@ -0,0 +1,112 @@
+//! The lookups of earlier runs, kept in `$XDG_DATA_HOME/whois-dns/history`
+//! (or `~/.local/share/whois-dns/history`) and listed by `history`.
+
+use anyhow::Result;
+use colored::*;
+use std::path::PathBuf;
+
+use crate::{diag, Commands};
+
+/// Entries kept in the history file; older ones are dropped first.
+const MAX_ENTRIES: usize = 1000;
+
+/// `$XDG_DATA_HOME/whois-dns/` or `~/.local/share/whois-dns/`.
+fn data_dir() -> Option<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(data_dir.join("whois-dns"))
+}
+
+fn history_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("history"))
+}
+
+/// Where the REPL keeps the lines entered at its prompt, for up-arrow recall
+/// across sessions.
+pub fn repl_history_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("repl_history"))
+}
+
+/// The type and target of a command worth remembering. Batches are left out
+/// since their targets are already in a file.
+fn entry(command: &Commands) -> Option<(String, &str)> {
+    match command {
+        Commands::Whois { target, .. } => Some(("whois".to_string(), target)),
+        Commands::Abuse { target, .. } => Some(("abuse".to_string(), target)),
+        Commands::Rdap { target, .. } => Some(("rdap".to_string(), target)),
+        Commands::Tld { tld, .. } => Some(("tld".to_string(), tld)),
+        Commands::Dns { domain, passive_dns: true, .. } => Some(("passive-dns".to_string(), domain)),
+        Commands::Dns { domain, record_type, .. } => Some((format!("dns {}", record_type.to_uppercase()), domain)),
+        Commands::Delegation { domain } => Some(("delegation".to_string(), domain)),
+        Commands::Summary { target, .. } => Some(("summary".to_string(), target)),
+        _ => None,
+    }
+}
+
+/// Appends the lookup to the history file. A history that cannot be written
+/// never fails the lookup itself.
+pub fn record(command: &Commands) {
+    let Some((kind, target)) = entry(command) else {
+        return;
+    };
+    let line = format!("{}\t{}\t{}", chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"), kind, target);
+    if let Err(e) = append(&line) {
+        diag::verbose(&format!("Could not update the lookup history: {}", e));
+    }
+}
+
+fn append(line: &str) -> Result<()> {
+    let path = history_path().ok_or_else(|| anyhow::anyhow!("No data directory (HOME is not set)"))?;
+    let mut lines = read_lines()?;
+    lines.push(line.to_string());
+    let excess = lines.len().saturating_sub(MAX_ENTRIES);
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, lines[excess..].join("\n") + "\n")?;
+    Ok(())
+}
+
+fn read_lines() -> Result<Vec<String>> {
+    let Some(path) = history_path() else {
+        return Ok(Vec::new());
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(contents.lines().filter(|line| !line.is_empty()).map(str::to_string).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(anyhow::anyhow!("Cannot read {}: {}", path.display(), e)),
+    }
+}
+
+/// Prints the last `limit` lookups, oldest first.
+pub fn print_history(limit: usize) -> Result<()> {
+    let lines = read_lines()?;
+    if lines.is_empty() {
+        diag::notice("No lookups recorded yet");
+        return Ok(());
+    }
+    for line in &lines[lines.len().saturating_sub(limit)..] {
+        let mut columns = line.splitn(3, '\t');
+        let (Some(time), Some(kind), Some(target)) = (columns.next(), columns.next(), columns.next()) else {
+            continue;
+        };
+        println!("{}  {:<12} {}", time.dimmed(), kind, target);
+    }
+    Ok(())
+}
+
+/// Removes the lookup history and the REPL's line history.
+pub fn clear() -> Result<()> {
+    for path in [history_path(), repl_history_path()].into_iter().flatten() {
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow::anyhow!("Cannot remove {}: {}", path.display(), e)),
+        }
+    }
+    diag::notice("History cleared");
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Display of internationalized domain names. Queries always use the
+//! `xn--` A-label form; with `--unicode` the output shows the U-labels.
+
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+static UNICODE: AtomicBool = AtomicBool::new(false);
+
+pub fn init(unicode: bool) {
+    UNICODE.store(unicode, Ordering::Relaxed);
+}
+
+/// Rewrites every `xn--` label in `text` as Unicode when `--unicode` is
+/// given, so `xn--bcher-kva.example` reads `bücher.example`. Labels that
+/// are not valid Punycode are left as they are.
+pub fn display(text: &str) -> Cow<'_, str> {
+    if !UNICODE.load(Ordering::Relaxed) {
+        return Cow::Borrowed(text);
+    }
+    static A_LABEL: OnceLock<Regex> = OnceLock::new();
+    let a_label = A_LABEL.get_or_init(|| Regex::new(r"(?i)\bxn--([a-z0-9-]+)").unwrap());
+    a_label.replace_all(text, |captures: &Captures| {
+        idna::punycode::decode_to_string(&captures[1].to_ascii_lowercase())
+            .unwrap_or_else(|| captures[0].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `display` reads the global `--unicode` flag, so the cases run in one
+    /// test rather than racing each other.
+    #[test]
+    fn a_labels_round_trip_to_unicode_only_with_the_flag() {
+        for unicode in ["bücher.example", "münchen.de", "пример.рф", "例え.jp"] {
+            let ascii = idna::domain_to_ascii(unicode).unwrap();
+            assert!(ascii.contains("xn--"), "{}", ascii);
+
+            init(false);
+            assert_eq!(display(&ascii), ascii);
+            init(true);
+            assert_eq!(display(&ascii), unicode);
+            assert_eq!(idna::domain_to_ascii(&display(&ascii)).unwrap(), ascii);
+        }
+
+        assert_eq!(display("Name Server: NS1.XN--BCHER-KVA.EXAMPLE"), "Name Server: NS1.bücher.EXAMPLE");
+        init(false);
+    }
+}
@@ -0,0 +1,5 @@
+//! Library side of `whois-dns-tool`. The command line tool is built on top
+//! of these modules; more of it moves here as it stabilizes.
+
+pub mod diff;
+pub mod dns;
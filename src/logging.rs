@@ -0,0 +1,227 @@
+//! Diagnostics as `tracing` events and spans, written by `tracing-subscriber`.
+//! `--verbose` shows the tool's own messages; `RUST_LOG` picks any targets
+//! and levels, including those of the DNS resolver, and marks each line with
+//! its level, target and the spans it happened in. `--log-format json`
+//! writes one JSON object per event for log pipelines. Notices from
+//! `diag::notice` go through the same subscriber.
+
+use clap::ValueEnum;
+use std::fmt;
+use std::sync::OnceLock;
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, MakeWriter};
+use tracing_subscriber::layer::{self, Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, Registry};
+
+use crate::diag;
+
+/// Target of the messages written by `diag::verbose`, and the prefix of
+/// the targets of the spans and events in this crate.
+pub const TARGET: &str = "whois_dns_tool";
+
+/// Target of the messages written by `diag::notice`, which are shown
+/// whatever `RUST_LOG` says.
+pub const NOTICE_TARGET: &str = "whois_dns_tool::notice";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    /// The message, with the level, target and spans when `RUST_LOG` is set
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+type FormatLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+struct Installed {
+    handle: reload::Handle<FormatLayer, Registry>,
+    /// Whether `RUST_LOG` was set, which makes text lines detailed
+    detailed: bool,
+}
+
+/// Installs the subscriber on first use and sets the format. Later calls,
+/// such as from REPL commands, only change the format; `RUST_LOG` is read
+/// once.
+pub fn init(format: LogFormat) {
+    static INSTALLED: OnceLock<Installed> = OnceLock::new();
+    let installed = INSTALLED.get_or_init(|| {
+        let rust_log = std::env::var("RUST_LOG").ok();
+        let (layer, handle) = reload::Layer::new(format_layer(format, rust_log.is_some(), std::io::stderr));
+        let subscriber = Registry::default().with(layer.with_filter(Filter::new(rust_log.as_deref())));
+        let _ = tracing::subscriber::set_global_default(subscriber);
+        Installed { handle, detailed: rust_log.is_some() }
+    });
+    let _ = installed.handle.reload(format_layer(format, installed.detailed, std::io::stderr));
+}
+
+/// The layer writing each event in `format`; plain text lines carry only
+/// the message and its fields unless `detailed`.
+fn format_layer<W>(format: LogFormat, detailed: bool, writer: W) -> FormatLayer
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Json => Box::new(layer.json().flatten_event(true).with_current_span(false).with_span_list(true)),
+        LogFormat::Text if detailed => Box::new(layer.without_time()),
+        // `--verbose` alone keeps the plain messages it always printed
+        LogFormat::Text => Box::new(layer.event_format(MessageOnly)),
+    }
+}
+
+/// Writes the message and fields of an event, without level, target or
+/// spans.
+struct MessageOnly;
+
+impl<S, N> FormatEvent<S, N> for MessageOnly
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    fn format_event(&self, context: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        context.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// `RUST_LOG` as an `EnvFilter`, nothing when unset. Notices always pass,
+/// and `--verbose` always enables the debug output of this crate.
+struct Filter {
+    env: EnvFilter,
+}
+
+impl Filter {
+    fn new(rust_log: Option<&str>) -> Self {
+        let env = EnvFilter::builder().with_default_directive(LevelFilter::OFF.into()).parse_lossy(rust_log.unwrap_or(""));
+        Filter { env }
+    }
+}
+
+fn always_allowed(metadata: &Metadata<'_>) -> bool {
+    metadata.target() == NOTICE_TARGET
+        || (diag::is_verbose() && metadata.target().starts_with(TARGET) && *metadata.level() <= Level::DEBUG)
+}
+
+impl<S> layer::Filter<S> for Filter
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn enabled(&self, metadata: &Metadata<'_>, context: &Context<'_, S>) -> bool {
+        always_allowed(metadata) || layer::Filter::<S>::enabled(&self.env, metadata, context)
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // `--verbose` can change between REPL commands, so the callsites of
+        // this crate ask `enabled` each time
+        if metadata.target().starts_with(TARGET) {
+            return Interest::sometimes();
+        }
+        layer::Filter::<S>::callsite_enabled(&self.env, metadata)
+    }
+
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+        layer::Filter::<S>::on_new_span(&self.env, attributes, id, context);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, context: Context<'_, S>) {
+        layer::Filter::<S>::on_record(&self.env, id, values, context);
+    }
+
+    fn on_enter(&self, id: &Id, context: Context<'_, S>) {
+        layer::Filter::<S>::on_enter(&self.env, id, context);
+    }
+
+    fn on_exit(&self, id: &Id, context: Context<'_, S>) {
+        layer::Filter::<S>::on_exit(&self.env, id, context);
+    }
+
+    fn on_close(&self, id: Id, context: Context<'_, S>) {
+        layer::Filter::<S>::on_close(&self.env, id, context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects what the subscriber writes.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'writer> MakeWriter<'writer> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'writer self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn capture(format: LogFormat, rust_log: Option<&str>, log: impl FnOnce()) -> String {
+        let buffer = Buffer::default();
+        let layer = format_layer(format, rust_log.is_some(), buffer.clone()).with_filter(Filter::new(rust_log));
+        tracing::subscriber::with_default(Registry::default().with(layer), log);
+        let bytes = buffer.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn notices_pass_without_rust_log_as_plain_messages() {
+        let output = capture(LogFormat::Text, None, || {
+            tracing::info!(target: NOTICE_TARGET, "{}", "Trying whois.iana.org...");
+            tracing::warn!(target: "trust_dns_proto", "not shown");
+        });
+        assert_eq!(output, "Trying whois.iana.org...\n");
+    }
+
+    #[test]
+    fn rust_log_picks_targets_and_levels() {
+        let output = capture(LogFormat::Text, Some("whois_dns_tool=trace,trust_dns_proto=warn"), || {
+            let span = tracing::info_span!(target: TARGET, "lookup", query = "example.com");
+            let _entered = span.enter();
+            tracing::trace!(target: "whois_dns_tool::whois", bytes = 42, "read");
+            tracing::debug!(target: "trust_dns_proto", "not shown");
+        });
+        assert!(!output.contains("not shown"), "{}", output);
+        let line = output.lines().next().unwrap();
+        assert!(line.contains("TRACE"), "{}", line);
+        assert!(line.contains("lookup{query=\"example.com\"}"), "{}", line);
+        assert!(line.contains("whois_dns_tool::whois: read bytes=42"), "{}", line);
+    }
+
+    #[test]
+    fn json_lines_carry_level_target_fields_and_spans() {
+        let output = capture(LogFormat::Json, Some("whois_dns_tool=debug"), || {
+            let span = tracing::info_span!(target: TARGET, "query", server = "whois.verisign-grs.com");
+            let _entered = span.enter();
+            tracing::debug!(target: TARGET, attempt = 2, "connected");
+        });
+        let event: serde_json::Value = serde_json::from_str(output.trim_end()).unwrap();
+        assert_eq!(event["level"], "DEBUG");
+        assert_eq!(event["target"], TARGET);
+        assert_eq!(event["message"], "connected");
+        assert_eq!(event["attempt"], 2);
+        assert_eq!(event["spans"][0]["name"], "query");
+        assert_eq!(event["spans"][0]["server"], "whois.verisign-grs.com");
+        assert!(event["timestamp"].is_string());
+    }
+}
@@ -1,12 +1,54 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
 use colored::*;
-use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
-use trust_dns_resolver::Resolver;
-use trust_dns_resolver::proto::rr::RecordType;
+use trust_dns_resolver::config::ResolverOpts;
+use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::proto::rr::dnssec::Algorithm;
+use trust_dns_resolver::proto::rr::{DNSClass, RecordType};
 use anyhow::Result;
-use std::io::{Write, Read};
-use std::net::TcpStream;
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Mutex, OnceLock};
+
+mod asn;
+mod authoritative;
+mod batch;
+mod bench;
+mod checks;
+mod concurrency;
+mod delegation;
+mod diag;
+mod dnsbl;
+mod dnscache;
+mod encoding;
+mod failure;
+mod family;
+mod fingerprint;
+mod history;
+mod idn;
+mod logging;
+mod parse;
+mod pager;
+mod passive;
+mod pretty;
+mod ratelimit;
+mod rdap;
+mod redact;
+mod repl;
+mod resolver;
+mod selftest;
+mod serverfile;
+mod sqlite;
+mod suffix;
+mod summary;
+mod whois;
+mod wire;
+
+use whois::WhoisOptions;
+use whois_dns_tool::diff;
+use whois_dns_tool::dns::{self, DnsAnswer, RecordData};
 
 #[derive(Parser)]
 #[command(name = "whois-dns")]
@@ -14,6 +56,40 @@ use std::time::Duration;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[arg(short, long, global = true, help = "Print only the response, without headers or notices")]
+    quiet: bool,
+    #[arg(short, long, global = true, help = "Print diagnostics about server selection and queries")]
+    verbose: bool,
+    #[arg(long, global = true, help = "Show which servers would be queried without sending any query")]
+    dry_run: bool,
+    #[arg(long, global = true, value_name = "PATH", help = "Public suffix list to use instead of the cached or embedded one")]
+    psl: Option<PathBuf>,
+    #[arg(long, global = true, value_name = "PATH", help = "TOML file of WHOIS servers merged into the built-in table [default: ~/.config/whois-dns/servers.toml]")]
+    servers_file: Option<PathBuf>,
+    #[arg(long, global = true, help = "Send every DNS query instead of reusing answers within their TTL")]
+    no_dns_cache: bool,
+    #[arg(long, global = true, help = "Show internationalized names in Unicode instead of their xn-- form")]
+    unicode: bool,
+    #[arg(long, global = true, overrides_with = "unicode", help = "Show xn-- names as received, overriding --unicode")]
+    no_punycode_decode: bool,
+    #[arg(long, global = true, help = "Use only the system resolver configuration, failing if it is unusable")]
+    system_resolver: bool,
+    #[arg(long, global = true, conflicts_with = "system_resolver", help = "Send DNS lookups to Cloudflare's public resolver instead of the system one")]
+    public_resolver: bool,
+    #[arg(long, global = true, value_name = "AGENT", value_parser = rdap::parse_user_agent, help = "User-Agent header of HTTP requests [default: whois-dns/<version>]")]
+    user_agent: Option<String>,
+    #[arg(long, global = true, help = "Print long WHOIS responses directly instead of through $PAGER")]
+    no_pager: bool,
+    #[arg(short = '4', long = "ipv4", global = true, help = "Resolve WHOIS servers, name servers and other derived names to IPv4 addresses only")]
+    ipv4: bool,
+    #[arg(short = '6', long = "ipv6", global = true, conflicts_with = "ipv4", help = "Resolve WHOIS servers, name servers and other derived names to IPv6 addresses only")]
+    ipv6: bool,
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), help = "Largest number of WHOIS and DNS lookups in flight at once, shared by both [batch default: 4]")]
+    concurrency: Option<u64>,
+    #[arg(long, global = true, value_enum, default_value_t = logging::LogFormat::Text, help = "Format of --verbose and RUST_LOG diagnostics on stderr")]
+    log_format: logging::LogFormat,
+    #[arg(long, global = true, help = "Print without colors, as with NO_COLOR set")]
+    no_color: bool,
 }
 
 #[derive(Subcommand)]
@@ -22,6 +98,94 @@ enum Commands {
     Whois {
         #[arg(help = "Domain or IP address to lookup")]
         target: String,
+        #[arg(long, help = "Compare the registry name servers with the live NS records")]
+        check_ns: bool,
+        #[arg(long, help = "Print a summary of the parsed fields instead of the raw response")]
+        pretty: bool,
+        #[arg(long, conflicts_with = "pretty", help = "Print registrar, expiry, status and name server count on one line")]
+        oneline: bool,
+        #[arg(long, help = "For an AS number, also list the prefixes it originates (best effort)")]
+        prefixes: bool,
+        #[arg(long, value_name = "HOST", default_value = asn::DEFAULT_PREFIX_SOURCE, help = "Routing registry WHOIS server asked for --prefixes")]
+        prefix_source: String,
+        #[arg(long, help = "After the response, list the related records and web addresses it points to")]
+        show_links: bool,
+        #[arg(long, help = "Print the registry's terms of use and notices instead of collapsing them")]
+        show_disclaimer: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["check_ns", "pretty", "oneline", "prefixes", "show_links", "show_disclaimer"],
+            help = "Print nothing; exit with 0 if the domain is registered and 1 if it is not"
+        )]
+        exists: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["check_ns", "pretty", "oneline", "prefixes", "show_links", "exists"],
+            help = "Print the target and a SHA-256 of the canonical response, flagging a change since the last run"
+        )]
+        hash: bool,
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["pretty", "oneline", "exists", "hash"], help = "Write the response to a file instead of printing it")]
+        output: Option<PathBuf>,
+        #[arg(long, value_enum, requires = "output", default_value_t = encoding::OutputEncoding::Utf8, help = "Character set of the --output file")]
+        output_encoding: encoding::OutputEncoding,
+        #[arg(
+            long,
+            value_name = "SECS",
+            value_parser = clap::value_parser!(u64).range(1..),
+            conflicts_with_all = ["check_ns", "pretty", "oneline", "prefixes", "exists", "hash", "output"],
+            help = "Repeat the lookup every SECS seconds and print what changed in the record, until interrupted"
+        )]
+        watch: Option<u64>,
+        #[command(flatten)]
+        options: WhoisOptions,
+    },
+    #[command(about = "Show how the WHOIS records of two targets differ")]
+    Compare {
+        #[arg(help = "Domain, IP address or AS number whose record is shown as removed lines")]
+        first: String,
+        #[arg(help = "Domain, IP address or AS number whose record is shown as added lines")]
+        second: String,
+        #[command(flatten)]
+        options: WhoisOptions,
+    },
+    #[command(about = "Show the abuse contact for a domain or IP address")]
+    Abuse {
+        #[arg(help = "Domain or IP address to lookup")]
+        target: String,
+        #[command(flatten)]
+        options: WhoisOptions,
+    },
+    #[command(about = "Show which WHOIS server would be used for a target")]
+    WhichServer {
+        #[arg(help = "Domain, IP address or AS number")]
+        target: String,
+        #[command(flatten)]
+        options: WhoisOptions,
+    },
+    #[command(about = "List the WHOIS server table, including the server file and WHOIS_SERVER_<TLD> overrides")]
+    Servers {
+        #[arg(long, value_enum, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+    },
+    #[command(about = "Show IANA's record of a top-level domain")]
+    Tld {
+        #[arg(help = "Top-level domain such as com or .de, or a domain under it")]
+        tld: String,
+        #[arg(long, value_enum, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+        #[command(flatten)]
+        options: WhoisOptions,
+    },
+    #[command(about = "Perform an RDAP lookup")]
+    Rdap {
+        #[arg(help = "Domain, IP address or AS number to lookup")]
+        target: String,
+        #[arg(long, help = "Skip TLS certificate verification, for self-signed test servers or debugging proxies")]
+        insecure: bool,
+        #[arg(long, overrides_with = "insecure", help = "Verify TLS certificates (the default), overriding --insecure")]
+        strict_tls: bool,
+        #[arg(long, help = "After the response, list its links as rel: href")]
+        show_links: bool,
     },
     #[command(about = "Perform a DNS lookup")]
     Dns {
@@ -29,146 +193,1467 @@ enum Commands {
         domain: String,
         #[arg(help = "Record type (A, AAAA, MX, TXT, etc.)", default_value = "A")]
         record_type: String,
+        #[arg(long, help = "Print a hex dump of the DNS request and response packets")]
+        show_wire: bool,
+        #[arg(long, conflicts_with = "show_wire", help = "Ask the zone's authoritative name servers instead of the resolver")]
+        authoritative: bool,
+        #[arg(long, value_parser = parse_dns_class, default_value = "IN", help = "Query class: IN, CH (CHAOS) or HS (Hesiod)")]
+        class: DNSClass,
+        #[arg(long, value_name = "ADDR", conflicts_with = "authoritative", help = "Send the query to this server instead of the resolver")]
+        server: Option<String>,
+        #[arg(long, conflicts_with_all = ["show_wire", "authoritative", "server", "class"], help = "When the resolver cannot be reached, retry with Cloudflare, Google and Quad9 in turn")]
+        resolver_failover: bool,
+        #[arg(long, value_enum, help = "Order of the records", default_value = "default")]
+        order: RecordOrder,
+        #[arg(long, help = "Show TXT records as their quoted character strings instead of joined")]
+        raw_txt: bool,
+        #[arg(long, help = "Count the unique addresses per /24 (IPv4) or /48 (IPv6) instead of listing them")]
+        summarize_cidr: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["show_wire", "authoritative", "passive_dns", "summarize_cidr"],
+            help = "Print each record as TYPE<tab>DATA, without headers or color"
+        )]
+        only_record_data: bool,
+        #[arg(long, requires = "only_record_data", help = "Add the TTL column to --only-record-data: TYPE<tab>TTL<tab>DATA")]
+        with_ttl: bool,
+        #[arg(
+            long,
+            value_enum,
+            default_value = "text",
+            conflicts_with_all = ["show_wire", "authoritative", "passive_dns", "summarize_cidr", "only_record_data"],
+            help = "Output format; jsonl prints one object per record"
+        )]
+        format: OutputFormat,
+        #[arg(
+            long,
+            conflicts_with_all = ["show_wire", "authoritative", "server", "class", "resolver_failover"],
+            help = "Show historical resolutions from the source in PASSIVE_DNS_URL instead of a live lookup"
+        )]
+        passive_dns: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["show_wire", "authoritative", "server", "class", "passive_dns", "format", "only_record_data"],
+            help = "Look up the A and AAAA addresses, or an IP address given as the domain, in DNS blocklists"
+        )]
+        check_dnsbl: bool,
+        #[arg(
+            long,
+            value_name = "ZONE",
+            value_delimiter = ',',
+            requires = "check_dnsbl",
+            help = "Blocklist zone to ask, repeatable [default: zen.spamhaus.org, bl.spamcop.net]"
+        )]
+        dnsbl: Vec<String>,
+    },
+    #[command(about = "Compare the delegation at the parent zone with the child's NS records")]
+    Delegation {
+        #[arg(help = "Domain whose delegation to check")]
+        domain: String,
     },
+    #[command(about = "Show the DNS records and WHOIS highlights of a domain together")]
+    Summary {
+        #[arg(help = "Domain to summarize")]
+        target: String,
+        #[arg(long, value_enum, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+        #[arg(long, help = "Check SPF, DMARC, MX, CNAME and name server records for common mistakes")]
+        checks: bool,
+        #[command(flatten)]
+        options: WhoisOptions,
+    },
+    #[command(about = "Perform WHOIS lookups for every target in a file")]
+    Batch {
+        #[arg(help = "File with one target per line (use - for stdin)")]
+        file: String,
+        #[arg(long, value_enum, help = "How the file lists its targets", default_value = "lines")]
+        input_format: batch::InputFormat,
+        #[arg(long, value_name = "N|NAME", help = "CSV column holding the targets, counted from 1 or named in the header [default: 1]")]
+        column: Option<String>,
+        #[arg(long, help = "Process only the first N targets")]
+        limit: Option<usize>,
+        #[arg(long, help = "Drop duplicate targets before querying")]
+        dedupe: bool,
+        #[arg(long, value_enum, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+        #[arg(long, help = "Expand CIDR ranges into one lookup per address")]
+        expand_cidr: bool,
+        #[arg(long, help = "Largest range --expand-cidr expands, bigger ones are queried as a whole", default_value_t = 256)]
+        max_expand: usize,
+        #[arg(long, value_name = "RECORD_TYPE", help = "Look up this DNS record type instead of WHOIS")]
+        dns: Option<String>,
+        #[arg(long, value_name = "QPS", help = "Maximum number of queries started per second")]
+        rate: Option<f64>,
+        #[arg(long, requires = "dns", help = "Print the distribution of record TTLs at the end")]
+        ttl_stats: bool,
+        #[arg(long, help = "Stop at the first failed lookup and exit with its error category")]
+        fail_fast: bool,
+        #[arg(long = "continue", conflicts_with = "fail_fast", help = "Keep going after failed lookups (the default)")]
+        keep_going: bool,
+        #[arg(long, value_name = "PATH", help = "Also write the results to this SQLite database, creating it if needed")]
+        sqlite: Option<PathBuf>,
+        #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "dns", help = "Count the WHOIS answers by registrar, expiry-month and/or status")]
+        group_by: Vec<batch::GroupBy>,
+        #[arg(long, conflicts_with = "dns", help = "Print each target with a SHA-256 of its canonical response, flagging changes since the last run")]
+        hash: bool,
+        #[command(flatten)]
+        whois: WhoisOptions,
+    },
+    #[command(about = "Measure the latency of repeated WHOIS or DNS lookups")]
+    Bench {
+        #[arg(help = "Domain, IP address or AS number to look up")]
+        target: String,
+        #[arg(long = "type", value_enum, help = "Kind of lookup to time", default_value = "whois")]
+        kind: bench::BenchKind,
+        #[arg(long, value_name = "RECORD_TYPE", help = "Record type for --type dns", default_value = "A")]
+        record_type: String,
+        #[arg(long, value_parser = clap::value_parser!(u64).range(2..), help = "Number of lookups, including the first one that is discarded", default_value_t = 10)]
+        iterations: u64,
+        #[arg(long, value_name = "ADDR", help = "WHOIS or DNS server to send every lookup to")]
+        server: Option<String>,
+        #[arg(long, value_enum, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+        #[command(flatten)]
+        options: WhoisOptions,
+    },
+    #[command(about = "Check that every server of the built-in WHOIS table answers plausibly")]
+    Selftest {
+        #[arg(long, value_name = "TLD", help = "Only test the servers for this TLD")]
+        only_tld: Option<String>,
+        #[arg(long, value_name = "SECS", help = "Timeout for each server", default_value_t = 5)]
+        timeout: u64,
+        #[arg(long, value_enum, help = "Output format", default_value = "text")]
+        format: OutputFormat,
+    },
+    #[command(about = "Manage the public suffix list")]
+    Psl {
+        #[command(subcommand)]
+        action: PslAction,
+    },
+    #[command(about = "Start an interactive prompt for repeated lookups")]
+    Repl,
+    #[command(about = "List recent lookups")]
+    History {
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+        #[arg(long, help = "Number of lookups to list", default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    #[command(about = "Delete the lookup history and the REPL line history")]
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum PslAction {
+    #[command(about = "Download the latest list from publicsuffix.org into the cache")]
+    Update,
+}
+
+/// How DNS records are printed.
+#[derive(Clone, Copy, Default)]
+struct DnsOutput {
+    order: RecordOrder,
+    /// Show the character strings of TXT records separately, quoted
+    raw_txt: bool,
+    /// Replace the A and AAAA records with the networks they fall in
+    summarize_cidr: bool,
+    /// Print `TYPE<tab>DATA` lines without headers or color
+    only_record_data: bool,
+    /// Add the TTL as a column between the type and the data
+    with_ttl: bool,
+    format: OutputFormat,
+}
+
+/// One DNS record as streamed by `--format jsonl`, by `dns` and `batch
+/// --dns` alike. `target` is the name that was asked for, given in batches.
+#[derive(Serialize)]
+struct RecordLine<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a str>,
+    name: &'a str,
+    #[serde(rename = "type")]
+    record_type: String,
+    ttl: u32,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct DnsRecords<'a> {
+    records: Vec<RecordLine<'a>>,
 }
 
-fn create_whois_servers() -> Vec<(&'static str, &'static str, &'static str)> {
-    vec![
-        ("com", "whois.verisign-grs.com", "domain "),
-        ("net", "whois.verisign-grs.com", "domain "),
-        ("org", "whois.pir.org", ""),
-        ("edu", "whois.educause.edu", ""),
-        ("it", "whois.nic.it", ""),
-        ("uk", "whois.nic.uk", ""),
-        ("ru", "whois.tcinet.ru", ""),
-        ("de", "whois.denic.de", "-T dn "),
-        ("nl", "whois.domain-registry.nl", ""),
-    ]
+/// How `dns --order` sorts the records. A CNAME chain always stays in
+/// front of the records it leads to.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum RecordOrder {
+    /// As the server returned them
+    #[default]
+    Default,
+    /// By the record data as text
+    Alpha,
+    /// Addresses numerically, MX by preference, DS and DNSKEY by key tag;
+    /// other types by text
+    Numeric,
+    /// MX by preference, other records as returned
+    Priority,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Jsonl,
+    /// The same document as `json`, as YAML
+    Yaml,
+}
+
+/// Version of the JSON layout. Bump it whenever a field is renamed, removed
+/// or changes type; adding a field does not need a bump.
+const JSON_SCHEMA_VERSION: u32 = 3;
+
+/// Wraps a JSON document with the `schema_version` consumers check first.
+#[derive(Serialize)]
+struct Versioned<T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    data: T,
+}
+
+impl<T> Versioned<T> {
+    fn new(data: T) -> Self {
+        Versioned { schema_version: JSON_SCHEMA_VERSION, data }
+    }
+}
+
+fn main() -> ExitCode {
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start the async runtime");
+    let code = runtime.block_on(run());
+    // A WHOIS lookup abandoned at its --deadline may still be waiting on a
+    // blocking thread; do not wait for it before exiting
+    runtime.shutdown_background();
+    code
+}
+
+async fn run() -> ExitCode {
     let cli = Cli::parse();
+    diag::init(cli.quiet, cli.verbose);
+    logging::init(cli.log_format);
+    init_color(cli.no_color);
+    suffix::init(cli.psl);
+    dnscache::init(cli.no_dns_cache);
+    idn::init(cli.unicode);
+    rdap::init(cli.user_agent);
+    pager::init(cli.no_pager);
+    concurrency::init(cli.concurrency.map(|limit| limit as usize));
+    family::init(cli.ipv4, cli.ipv6);
+    if let Some(name) = family::get().name() {
+        diag::notice(&format!("Using {} addresses only for WHOIS servers and derived lookups", name));
+    }
+
+    let choice = if cli.system_resolver {
+        resolver::ResolverChoice::System
+    } else if cli.public_resolver {
+        resolver::ResolverChoice::Public
+    } else {
+        resolver::ResolverChoice::Auto
+    };
+    let setup = resolver::init(choice)
+        .and_then(|()| whois::check_builtin_table())
+        .and_then(|()| serverfile::init(cli.servers_file));
+    let result = match (setup, cli.command) {
+        (Err(e), _) => Err(e),
+        (Ok(()), Commands::Repl) => repl::run_repl().await,
+        (Ok(()), command) => run_command(command, cli.dry_run).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if !failure::is_silent(&e) {
+                eprintln!("Error: {:?}", e);
+            }
+            ExitCode::from(failure::exit_code(&e))
+        }
+    }
+}
 
-    match cli.command {
-        Commands::Whois { target } => {
-            perform_whois(&target)?;
+/// Runs a single command. Shared by the command line and the REPL.
+async fn run_command(command: Commands, dry_run: bool) -> Result<()> {
+    if !dry_run {
+        history::record(&command);
+    }
+    match command {
+        Commands::Whois { options, target, .. } if dry_run => {
+            print_whois_plan(&whois::plan_whois(&target, &options), &options);
+        }
+        Commands::Whois { target, prefixes: true, options, .. }
+            if whois::plan_whois(&target, &options).kind != whois::TargetKind::Asn =>
+        {
+            return Err(anyhow::anyhow!("--prefixes needs an AS number such as AS15169, not {}", target));
+        }
+        Commands::Whois { target, exists: true, options, .. } => {
+            perform_exists(&target, &options).await?;
+        }
+        Commands::Whois { target, watch: Some(interval), show_disclaimer, options, .. } => {
+            watch_whois(&target, interval, show_disclaimer, &options).await?;
+        }
+        Commands::Whois { target, hash: true, options, .. } => {
+            let (_, response) = whois::lookup_whois_async(&target, &options).await?;
+            fingerprint::print_hash(&normalize_target(&target), &response);
+            fingerprint::save();
+        }
+        Commands::Whois {
+            target,
+            check_ns,
+            pretty,
+            oneline,
+            prefixes,
+            prefix_source,
+            show_links,
+            show_disclaimer,
+            output,
+            output_encoding,
+            options,
+            ..
+        } => {
+            let output = output.map(|path| (path, output_encoding));
+            let display = WhoisDisplay { pretty, oneline, show_links, show_disclaimer, output };
+            perform_whois(&target, check_ns, display, &options).await?;
+            if prefixes {
+                asn::perform_prefixes(&target, &prefix_source, &options).await?;
+            }
+        }
+        Commands::Compare { first, second, options } if dry_run => {
+            print_whois_plan(&whois::plan_whois(&first, &options), &options);
+            print_whois_plan(&whois::plan_whois(&second, &options), &options);
+        }
+        Commands::Compare { first, second, options } => {
+            perform_compare(&first, &second, &options).await?;
+        }
+        Commands::Abuse { target, options } if dry_run => {
+            print_whois_plan(&whois::plan_whois(&target, &options), &options);
+        }
+        Commands::Abuse { target, options } => {
+            perform_abuse(&target, &options).await?;
+        }
+        Commands::WhichServer { target, options } => {
+            print_which_server(&target, &options)?;
+        }
+        Commands::Servers { format } => {
+            print_servers(format)?;
+        }
+        Commands::Tld { tld, format, options } => {
+            perform_tld(&tld, format, &options, dry_run).await?;
+        }
+        Commands::Rdap { target, insecure, show_links, .. } => {
+            rdap::perform_rdap(&target, insecure, show_links).await?;
         }
-        Commands::Dns { domain, record_type } => {
-            perform_dns(&domain, &record_type).await?;
+        Commands::Dns { domain, passive_dns: true, .. } => {
+            passive::perform_passive_dns(&domain, dry_run).await?;
         }
+        Commands::Dns { domain, check_dnsbl: true, dnsbl, .. } if dry_run && domain.parse::<IpAddr>().is_ok() => {
+            for zone in dnsbl_zones(dnsbl) {
+                println!("DNSBL: A {}", dnsbl::query_name(domain.parse()?, &zone));
+            }
+        }
+        Commands::Dns { domain, record_type, authoritative, class, server, check_dnsbl, dnsbl, .. } if dry_run => {
+            print_dns_plan(&domain, parse_record_type(&record_type)?);
+            if check_dnsbl {
+                println!("DNSBL: each address in {}", dnsbl_zones(dnsbl).join(", "));
+            }
+            if class != DNSClass::IN {
+                println!("Class: {}", class);
+            }
+            if let Some(server) = server {
+                println!("Answer from: {} (instead of the resolver)", server);
+            } else if authoritative {
+                println!("Answer from: authoritative name servers of the zone");
+            }
+        }
+        Commands::Dns { domain, record_type, show_wire: true, class, server, .. } => {
+            wire::perform_wire_query(&domain, parse_record_type(&record_type)?, class, server.as_deref()).await?;
+        }
+        Commands::Dns { domain, record_type, authoritative: true, order, raw_txt, summarize_cidr, .. } => {
+            let output = DnsOutput { order, raw_txt, summarize_cidr, ..Default::default() };
+            authoritative::perform_authoritative_dns(&domain, parse_record_type(&record_type)?, output).await?;
+        }
+        Commands::Dns {
+            domain,
+            record_type,
+            class,
+            server,
+            order,
+            raw_txt,
+            summarize_cidr,
+            only_record_data,
+            with_ttl,
+            format,
+            // --check-dnsbl, --resolver-failover and --passive-dns conflict
+            // with --class and --server, so none of them is dropped here
+            ..
+        } if class != DNSClass::IN || server.is_some() => {
+            let output = DnsOutput { order, raw_txt, summarize_cidr, only_record_data, with_ttl, format };
+            wire::perform_direct_query(&domain, parse_record_type(&record_type)?, class, server.as_deref(), output)
+                .await?;
+        }
+        Commands::Dns { domain, check_dnsbl: true, dnsbl, .. } if domain.parse::<IpAddr>().is_ok() => {
+            dnsbl::print_listings(&[domain.parse()?], &dnsbl_zones(dnsbl)).await?;
+        }
+        Commands::Dns {
+            domain,
+            record_type,
+            resolver_failover,
+            order,
+            raw_txt,
+            summarize_cidr,
+            only_record_data,
+            with_ttl,
+            format,
+            check_dnsbl,
+            dnsbl,
+            ..
+        } => {
+            let output = DnsOutput { order, raw_txt, summarize_cidr, only_record_data, with_ttl, format };
+            let answers = perform_dns(&domain, &record_type, resolver_failover, output).await?;
+            if check_dnsbl {
+                let addresses: Vec<IpAddr> = answers
+                    .iter()
+                    .filter_map(|answer| match answer.data {
+                        RecordData::A(ip) => Some(IpAddr::V4(ip)),
+                        RecordData::Aaaa(ip) => Some(IpAddr::V6(ip)),
+                        _ => None,
+                    })
+                    .collect();
+                if !diag::is_quiet() {
+                    println!();
+                }
+                dnsbl::print_listings(&addresses, &dnsbl_zones(dnsbl)).await?;
+            }
+        }
+        Commands::Delegation { domain } if dry_run => {
+            let domain = normalize_target(&domain);
+            println!("{}", "Delegation Plan:".green().bold());
+            println!("{}", "-".repeat(50));
+            println!("Domain: {}", domain);
+            println!("Parent: NS {} from the servers of the parent zone", domain);
+            println!("Child: NS {} from each delegated server", domain);
+        }
+        Commands::Delegation { domain } => {
+            delegation::perform_delegation(&domain).await?;
+        }
+        Commands::Summary { target, format, checks, options } => {
+            summary::perform_summary(&target, format, checks, &options, dry_run).await?;
+        }
+        Commands::Batch {
+            file,
+            input_format,
+            column,
+            limit,
+            dedupe,
+            format,
+            expand_cidr,
+            max_expand,
+            dns,
+            rate,
+            ttl_stats,
+            fail_fast,
+            keep_going: _,
+            sqlite,
+            group_by,
+            hash,
+            whois,
+        } => {
+            if column.is_some() && input_format != batch::InputFormat::Csv {
+                return Err(anyhow::anyhow!("--column only applies to --input-format csv"));
+            }
+            let options = batch::BatchOptions {
+                input_format,
+                column,
+                limit,
+                dedupe,
+                concurrency: concurrency::limit().unwrap_or(concurrency::BATCH_DEFAULT),
+                format,
+                whois,
+                expand_cidr,
+                max_expand,
+                dns: dns.as_deref().map(parse_record_type).transpose()?,
+                rate,
+                fail_fast,
+                ttl_stats,
+                sqlite,
+                group_by,
+                hash,
+                dry_run,
+            };
+            batch::run_batch(&file, &options).await?;
+        }
+        Commands::Bench { target, kind, record_type, iterations, server, options, .. } if dry_run => {
+            let record_type = parse_record_type(&record_type)?;
+            match (kind, server) {
+                (_, Some(server)) => println!("Server: {}", server),
+                (bench::BenchKind::Whois, None) => {
+                    print_whois_plan(&whois::plan_whois(&target, &options), &options)
+                }
+                (bench::BenchKind::Dns, None) => print_dns_plan(&normalize_target(&target), record_type),
+            }
+            println!("Iterations: {} (the first is discarded)", iterations);
+        }
+        Commands::Bench { target, kind, record_type, iterations, server, format, options } => {
+            let options = bench::BenchOptions {
+                kind,
+                record_type: parse_record_type(&record_type)?,
+                iterations: iterations as usize,
+                server,
+                format,
+                whois: options,
+            };
+            bench::run_bench(&target, &options).await?;
+        }
+        Commands::Selftest { only_tld, .. } if dry_run => {
+            selftest::print_plan(only_tld.as_deref())?;
+        }
+        Commands::Selftest { only_tld, timeout, format } => {
+            selftest::run_selftest(only_tld.as_deref(), timeout, format).await?;
+        }
+        Commands::History { action: Some(HistoryAction::Clear), .. } if dry_run => {
+            println!("Would delete the lookup history and the REPL line history");
+        }
+        Commands::History { action: Some(HistoryAction::Clear), .. } => {
+            history::clear()?;
+        }
+        Commands::History { action: None, limit } => {
+            history::print_history(limit)?;
+        }
+        Commands::Psl { action: PslAction::Update } if dry_run => {
+            let path = suffix::cache_path().map_or("-".to_string(), |path| path.display().to_string());
+            println!("Download: {}", suffix::PSL_URL);
+            println!("Save to: {}", path);
+        }
+        Commands::Psl { action: PslAction::Update } => {
+            suffix::update().await?;
+        }
+        Commands::Repl => return Err(anyhow::anyhow!("Already in the REPL")),
     }
 
     Ok(())
 }
 
-fn get_tld(domain: &str) -> Option<&str> {
-    domain.split('.').last()
+/// Returns the last label of `domain`, lowercased. A single trailing dot is
+/// ignored, so `EXAMPLE.COM.` yields `com`.
+fn get_tld(domain: &str) -> Option<String> {
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    domain
+        .split('.')
+        .next_back()
+        .filter(|label| !label.is_empty())
+        .map(str::to_lowercase)
 }
 
-fn perform_whois(target: &str) -> Result<()> {
-    let servers = create_whois_servers();
+/// `--no-color` turns colors off; otherwise `NO_COLOR`, `CLICOLOR_FORCE`
+/// and whether the output is a terminal decide.
+fn init_color(no_color: bool) {
+    if no_color {
+        colored::control::set_override(false);
+    } else {
+        colored::control::unset_override();
+    }
+}
 
-    // First try the TLD-specific server
-    if let Some(tld) = get_tld(target) {
-        if let Some(&(_, server, prefix)) = servers.iter().find(|&&(t, _, _)| t == tld) {
-            match query_whois_server(server, prefix, target) {
-                Ok(result) => {
-                    print_whois_result(server, &result);
-                    return Ok(());
-                }
-                Err(e) => {
-                    eprintln!("TLD-specific server failed: {}. Trying IANA...", e);
-                }
+/// Cleans up a target as it typically appears in lists: surrounding
+/// whitespace, a URL scheme, a path, a trailing slash or a single trailing dot
+/// are dropped and the result is lowercased, so `https://Example.com./`
+/// becomes `example.com`. CIDR ranges are kept intact.
+fn normalize_target(raw: &str) -> String {
+    let target = raw.trim();
+    if target.parse::<ipnet::IpNet>().is_ok() {
+        return target.to_lowercase();
+    }
+    let target = target
+        .split_once("://")
+        .map_or(target, |(_, rest)| rest);
+    let target = target.split('/').next().unwrap_or(target);
+    let target = target.strip_suffix('.').unwrap_or(target);
+    target.to_lowercase()
+}
+
+/// How `whois` prints a response.
+struct WhoisDisplay {
+    pretty: bool,
+    oneline: bool,
+    show_links: bool,
+    show_disclaimer: bool,
+    /// `--output` and `--output-encoding`
+    output: Option<(PathBuf, encoding::OutputEncoding)>,
+}
+
+async fn perform_whois(target: &str, check_ns: bool, display: WhoisDisplay, options: &WhoisOptions) -> Result<()> {
+    let (server, result) = whois::lookup_whois_async(target, options).await?;
+    if display.pretty {
+        pretty::print_pretty_whois(&normalize_target(target), &server, &result);
+    } else if display.oneline {
+        pretty::print_oneline_whois(&normalize_target(target), &result);
+    } else if let Some((path, output_encoding)) = &display.output {
+        write_response(path, &result, *output_encoding)?;
+        diag::notice(&format!("Response from {} written to {}", server, path.display()));
+    } else if display.show_disclaimer {
+        pager::print(whois_result_text(&server, &result)).await;
+    } else {
+        pager::print(whois_result_text(&server, &collapse_disclaimer(&result))).await;
+    }
+    if display.show_links {
+        pretty::print_links(&parse::related_links(&parse::parse_whois_fields(&result)));
+    }
+
+    if check_ns {
+        // The registrable domain the WHOIS query was for, not the raw input
+        check_name_servers(&whois::plan_whois(target, options).query, &result).await?;
+    }
+
+    Ok(())
+}
+
+/// `whois --watch`: prints the record, then looks it up again every
+/// `interval` seconds and prints a diff whenever the canonical response
+/// changes, so footer timestamps alone do not count as a change. A lookup
+/// that fails is reported and retried at the next interval.
+async fn watch_whois(target: &str, interval: u64, show_disclaimer: bool, options: &WhoisOptions) -> Result<()> {
+    let (server, response) = whois::lookup_whois_async(target, options).await?;
+    diag::notice(&format!("Watching {} on {} every {}s; press Ctrl-C to stop", target, server, interval));
+    let shown = if show_disclaimer { response.clone() } else { collapse_disclaimer(&response) };
+    println!("{}", idn::display(&shown));
+
+    let mut previous = fingerprint::canonicalize(&response);
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        let response = match whois::lookup_whois_async(target, options).await {
+            Ok((_, response)) => response,
+            Err(e) => {
+                diag::notice(&format!("Lookup of {} failed: {}. Trying again in {}s", target, e, interval));
+                continue;
             }
+        };
+        let current = fingerprint::canonicalize(&response);
+        if current == previous {
+            diag::verbose(&format!("No change in the record of {}", target));
+            continue;
         }
+        println!();
+        println!("{}", format!("{} changed at {}:", target, chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")).bold());
+        diff::print_diff(&previous, &current);
+        previous = current;
+    }
+}
+
+/// `compare`: looks up both targets at once and prints how their canonical
+/// responses differ, the first as removed lines and the second as added.
+async fn perform_compare(first: &str, second: &str, options: &WhoisOptions) -> Result<()> {
+    let ((first_server, first_response), (second_server, second_response)) = tokio::try_join!(
+        whois::lookup_whois_async(first, options),
+        whois::lookup_whois_async(second, options)
+    )?;
+    if !diag::is_quiet() {
+        println!("{}", format!("--- {} ({})", first, first_server).red());
+        println!("{}", format!("+++ {} ({})", second, second_server).green());
     }
+    diff::print_diff(
+        &idn::display(&fingerprint::canonicalize(&first_response)),
+        &idn::display(&fingerprint::canonicalize(&second_response)),
+    );
+    Ok(())
+}
 
-    // Fallback to IANA
-    match query_whois_server("whois.iana.org", "", target) {
-        Ok(result) => {
-            print_whois_result("whois.iana.org", &result);
-            Ok(())
+/// Prints nothing and tells through the exit code whether `target` is
+/// registered. A lookup that fails exits with the batch codes for timeouts
+/// and network errors, and 5 otherwise, so a failure is never taken for
+/// "not registered".
+async fn perform_exists(target: &str, options: &WhoisOptions) -> Result<()> {
+    // Fallback notices would clutter a shell `if`; --verbose still shows them
+    let options = WhoisOptions { quiet: true, ..options.clone() };
+    let response = match whois::lookup_whois_async(target, &options).await {
+        Ok((_, response)) => response,
+        Err(e) => {
+            let code = match failure::FailureKind::classify(&e) {
+                kind @ (failure::FailureKind::Timeout | failure::FailureKind::Network) => kind.exit_code(),
+                _ => 5,
+            };
+            return Err(failure::Exit { code, message: e.to_string() }.into());
         }
-        Err(e) => Err(anyhow::anyhow!("WHOIS lookup failed: {}", e))
+    };
+    if parse::classify_whois_not_found(&response) {
+        return Err(failure::Exit { code: 1, message: String::new() }.into());
     }
+    Ok(())
 }
 
-fn query_whois_server(server: &str, prefix: &str, target: &str) -> Result<String> {
-    let mut stream = TcpStream::connect((server, 43))?;
-    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
-    stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+/// Prints only the abuse contact of `target`. Fails when the WHOIS record
+/// does not publish one, so scripts can tell from the exit code.
+async fn perform_abuse(target: &str, options: &WhoisOptions) -> Result<()> {
+    let (server, result) = whois::lookup_whois_async(target, options).await?;
+    let contact = parse::abuse_contact(&result);
 
-    // Send query
-    let query = format!("{}{}\r\n", prefix, target);
-    stream.write_all(query.as_bytes())?;
+    if contact.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No abuse contact published for {} (server: {})",
+            target,
+            server
+        ));
+    }
 
-    // Read response
-    let mut response = String::new();
-    stream.read_to_string(&mut response)?;
+    if diag::is_quiet() {
+        contact.emails.iter().chain(&contact.phones).for_each(|value| println!("{}", value));
+        return Ok(());
+    }
 
-    if response.trim().is_empty() {
-        return Err(anyhow::anyhow!("Empty response from server"));
+    println!("{}", "Abuse Contact:".green().bold());
+    println!("{}", "-".repeat(50));
+    println!("Server used: {}", server.blue());
+    for email in &contact.emails {
+        println!("Email: {}", email);
     }
+    for phone in &contact.phones {
+        println!("Phone: {}", phone);
+    }
+    Ok(())
+}
+
+/// Writes the whole response for `--output`, disclaimers included:
+/// collapsing them, and its dimmed placeholder, is for the terminal only.
+fn write_response(path: &Path, response: &str, output_encoding: encoding::OutputEncoding) -> Result<()> {
+    encoding::write_file(path, response, output_encoding)
+}
 
-    Ok(response)
+/// Replaces each disclaimer block of a response with one dimmed line
+/// saying how to see it.
+fn collapse_disclaimer(response: &str) -> String {
+    let hidden = parse::disclaimer_lines(response);
+    let mut lines = Vec::new();
+    let mut previous_hidden = false;
+    for (line, &is_hidden) in response.lines().zip(&hidden) {
+        if !is_hidden {
+            lines.push(line.to_string());
+        } else if !previous_hidden {
+            lines.push("[disclaimer hidden, use --show-disclaimer]".dimmed().to_string());
+        }
+        previous_hidden = is_hidden;
+    }
+    lines.join("\n")
 }
 
+/// Prints the response directly, as `batch` does for every target.
 fn print_whois_result(server: &str, result: &str) {
-    println!("{}", "WHOIS Information:".green().bold());
+    println!("{}", whois_result_text(server, result));
+}
+
+/// The response under a header naming the server, or alone with `--quiet`.
+fn whois_result_text(server: &str, result: &str) -> String {
+    let result = idn::display(result);
+    if diag::is_quiet() {
+        return result.into_owned();
+    }
+
+    let separator = "-".repeat(50);
+    format!(
+        "{}\n{}\nServer used: {}\n{}\n{}",
+        "WHOIS Information:".green().bold(),
+        separator,
+        server.blue(),
+        separator,
+        result
+    )
+}
+
+/// Prints the server selection for a WHOIS target, as used by `--dry-run`.
+fn print_whois_plan(plan: &whois::WhoisPlan, options: &WhoisOptions) {
+    println!("{}", "WHOIS Query Plan:".green().bold());
     println!("{}", "-".repeat(50));
-    println!("Server used: {}", server.blue());
+    println!("Target: {} ({:?})", plan.query, plan.kind);
+    match &plan.server {
+        Some(entry) => {
+            let (host, port) = whois::host_and_port(&entry.hosts[0]);
+            println!("Server: {} port {}, from {}", host.blue(), port, entry.source());
+            for alternate in &entry.hosts[1..] {
+                println!("Alternate: {}", alternate);
+            }
+            println!("Query: {:?}", format!("{}{}", options.query_prefix(entry), plan.query));
+            println!("Follow registrar referral: {}", if plan.follow_referral { "yes" } else { "no" });
+            if options.only_tld_server {
+                println!("Fallback: none (--only-tld-server)");
+            } else {
+                println!("Fallback: {}", options.fallback_chain().join(", "));
+            }
+        }
+        None if options.only_tld_server => {
+            println!("Server: none ({})", whois::no_configured_server(plan));
+        }
+        None => {
+            let chain = options.fallback_chain();
+            let (host, port) = whois::host_and_port(chain[0]);
+            println!("Server: {} port {} (no table entry)", host.blue(), port);
+            for alternate in &chain[1..] {
+                println!("Alternate: {}", alternate);
+            }
+            println!("Query: {:?}", plan.query);
+        }
+    }
+}
+
+/// One entry of the WHOIS server table, as printed by `servers --format json`.
+#[derive(Serialize)]
+struct ServerEntry {
+    tld: String,
+    servers: Vec<String>,
+    prefix: String,
+    model: &'static str,
+    /// `builtin`, `file` or `env`
+    source: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variable: Option<String>,
+}
+
+/// Prints the WHOIS server table sorted by TLD, with the environment
+/// overrides already applied.
+fn print_servers(format: OutputFormat) -> Result<()> {
+    let mut servers: Vec<ServerEntry> = whois::create_whois_servers()
+        .into_iter()
+        .map(|entry| ServerEntry {
+            variable: (entry.origin == whois::Origin::Env).then(|| whois::server_env_var(&entry.tld)),
+            source: match entry.origin {
+                whois::Origin::Builtin => "builtin",
+                whois::Origin::File => "file",
+                whois::Origin::Env => "env",
+            },
+            model: if entry.thick { "thick" } else { "thin" },
+            prefix: entry.prefix,
+            servers: entry.hosts,
+            tld: entry.tld,
+        })
+        .collect();
+    servers.sort_by(|a, b| a.tld.cmp(&b.tld));
+
+    #[derive(Serialize)]
+    struct ServerTable {
+        servers: Vec<ServerEntry>,
+    }
+    let table = ServerTable { servers };
+    match format {
+        OutputFormat::Text => {
+            if !diag::is_quiet() {
+                println!("{}", "WHOIS Servers:".green().bold());
+                println!("{}", "-".repeat(50));
+                println!("{:<8} {:<40} {:<10} {:<6} Source", "TLD", "Server", "Prefix", "Model");
+            }
+            for entry in &table.servers {
+                let source = match &entry.variable {
+                    Some(variable) => format!("env ({})", variable),
+                    None => entry.source.to_string(),
+                };
+                println!(
+                    "{:<8} {:<40} {:<10} {:<6} {}",
+                    format!(".{}", entry.tld),
+                    entry.servers.join(", "),
+                    format!("{:?}", entry.prefix),
+                    entry.model,
+                    source
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&Versioned::new(&table))?),
+        OutputFormat::Jsonl => {
+            for entry in &table.servers {
+                println!("{}", serde_json::to_string(&Versioned::new(entry))?);
+            }
+        }
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&Versioned::new(&table))?),
+    }
+    Ok(())
+}
+
+/// Asks IANA about the TLD of `target` and prints its sponsor, registry
+/// server, status, name servers and dates.
+async fn perform_tld(target: &str, format: OutputFormat, options: &WhoisOptions, dry_run: bool) -> Result<()> {
+    let tld = get_tld(&normalize_target(target)).ok_or_else(|| anyhow::anyhow!("No TLD in {:?}", target))?;
+    if dry_run {
+        println!("{}", "WHOIS Query Plan:".green().bold());
+        println!("{}", "-".repeat(50));
+        println!("Target: {} (TLD)", tld);
+        println!("Server: {} port 43", whois::IANA_WHOIS.blue());
+        println!("Query: {:?}", tld);
+        return Ok(());
+    }
+
+    let lookup = {
+        let (tld, options) = (tld.clone(), options.clone());
+        tokio::task::spawn_blocking(move || whois::query_whois_server(whois::IANA_WHOIS, "", &tld, &options))
+    };
+    let response = options.within_deadline(&tld, async { lookup.await? }).await?;
+    let record = parse::parse_iana_response(&response);
+    if record.domain.is_none() {
+        return Err(anyhow::anyhow!("IANA has no record of .{}", tld));
+    }
+
+    match format {
+        OutputFormat::Text => {
+            if !diag::is_quiet() {
+                println!("{}", "IANA Record:".green().bold());
+                println!("{}", "-".repeat(50));
+            }
+            let optional_rows = [
+                ("Domain", &record.domain),
+                ("Sponsor", &record.organisation),
+                ("WHOIS", &record.whois),
+                ("Status", &record.status),
+            ];
+            for (label, value) in optional_rows {
+                if let Some(value) = value {
+                    pretty::print_row(label, value);
+                }
+            }
+            for ns in &record.name_servers {
+                pretty::print_row("Name Server", format!("{} {}", ns.name, ns.addresses.join(" ")).trim_end());
+            }
+            for (label, value) in [("Created", &record.created), ("Changed", &record.changed)] {
+                if let Some(value) = value {
+                    pretty::print_row(label, value);
+                }
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&Versioned::new(&record))?),
+        OutputFormat::Jsonl => println!("{}", serde_json::to_string(&Versioned::new(&record))?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&Versioned::new(&record))?),
+    }
+    Ok(())
+}
+
+/// Prints the server, prefix and port chosen for `target` and where the
+/// choice came from. Only contacts IANA when the table has no entry.
+fn print_which_server(target: &str, options: &WhoisOptions) -> Result<()> {
+    let plan = whois::plan_whois(target, options);
+    if plan.server.is_none() && options.only_tld_server {
+        return Err(whois::no_configured_server(&plan));
+    }
+    let row = |label: &str, value: &str| println!("{:<8}{}", format!("{}:", label).bold(), value);
+
+    let (server, prefix, source) = match &plan.server {
+        Some(entry) => (
+            entry.hosts.join(", "),
+            options.query_prefix(entry),
+            entry.source(),
+        ),
+        None if !options.fallback.is_empty() => (
+            options.fallback.join(", "),
+            String::new(),
+            "--fallback (no table entry)".to_string(),
+        ),
+        None => match whois::discover_whois_server(&plan, options) {
+            Ok(Some(server)) => match whois::registry_quirk(&server).filter(|_| plan.kind == whois::TargetKind::Domain) {
+                Some(quirk) if !quirk.suffix.is_empty() => {
+                    let source = format!("IANA discovery, query suffix {:?} for {}", quirk.suffix, quirk.server);
+                    (server, quirk.prefix.to_string(), source)
+                }
+                Some(quirk) => (server, quirk.prefix.to_string(), "IANA discovery".to_string()),
+                None => (server, String::new(), "IANA discovery".to_string()),
+            },
+            Ok(None) => (
+                whois::IANA_WHOIS.to_string(),
+                String::new(),
+                "IANA fallback (IANA names no server)".to_string(),
+            ),
+            Err(e) => (
+                whois::IANA_WHOIS.to_string(),
+                String::new(),
+                format!("IANA fallback (discovery failed: {})", e),
+            ),
+        },
+    };
+
+    row("Target", &plan.query);
+    row("Type", &plan.kind.to_string());
+    row("Server", &server);
+    row("Prefix", &format!("{:?}", prefix));
+    let first = server.split(", ").next().unwrap_or_default();
+    row("Port", &whois::host_and_port(first).1.to_string());
+    row("Source", &source);
+    Ok(())
+}
+
+/// Prints the resolver and query for a DNS lookup, as used by `--dry-run`.
+fn print_dns_plan(domain: &str, record_type: RecordType) {
+    println!("{}", "DNS Query Plan:".green().bold());
     println!("{}", "-".repeat(50));
-    println!("{}", result);
+    println!("Domain: {}", domain);
+    println!("Record type: {}", record_type);
+    for name_server in resolver::config().name_servers() {
+        println!("Resolver: {} ({})", name_server.socket_addr, name_server.protocol);
+    }
 }
 
-async fn perform_dns(domain: &str, record_type_str: &str) -> Result<()> {
-    let resolver = Resolver::new(
-        ResolverConfig::default(),
-        ResolverOpts::default(),
-    )?;
+/// Returns the resolver shared by all lookups of this run, so its cache stays
+/// warm across REPL commands. With `--no-dns-cache` a second resolver that
+/// keeps nothing is used instead.
+fn create_resolver() -> TokioAsyncResolver {
+    // One resolver per combination of --no-dns-cache and -4/-6, which REPL
+    // commands may change, each keeping its cache for the rest of the run
+    static RESOLVERS: OnceLock<Mutex<HashMap<(bool, family::IpFamily), TokioAsyncResolver>>> = OnceLock::new();
+    let (cached, family) = (dnscache::is_enabled(), family::get());
+    let mut resolvers = RESOLVERS.get_or_init(Default::default).lock().unwrap();
+    resolvers
+        .entry((cached, family))
+        .or_insert_with(|| {
+            let mut opts = ResolverOpts::default();
+            if !cached {
+                opts.cache_size = 0;
+            }
+            opts.ip_strategy = family.ip_strategy();
+            TokioAsyncResolver::tokio(resolver::config(), opts)
+        })
+        .clone()
+}
+
+fn parse_record_type(record_type_str: &str) -> Result<RecordType> {
+    match record_type_str.to_uppercase().as_str() {
+        "A" => Ok(RecordType::A),
+        "AAAA" => Ok(RecordType::AAAA),
+        "MX" => Ok(RecordType::MX),
+        "TXT" => Ok(RecordType::TXT),
+        "NS" => Ok(RecordType::NS),
+        "CNAME" => Ok(RecordType::CNAME),
+        "SOA" => Ok(RecordType::SOA),
+        "DNSKEY" => Ok(RecordType::DNSKEY),
+        "DS" => Ok(RecordType::DS),
+        "RRSIG" => Ok(RecordType::RRSIG),
+        "NSEC" => Ok(RecordType::NSEC),
+        "NSEC3" => Ok(RecordType::NSEC3),
+        "NAPTR" => Ok(RecordType::NAPTR),
+        "TLSA" => Ok(RecordType::TLSA),
+        _ => Err(anyhow::anyhow!("Unsupported record type")),
+    }
+}
+
+/// Parses `--class`. Only the classes that are still in use are accepted.
+fn parse_dns_class(class: &str) -> Result<DNSClass, String> {
+    match class.to_uppercase().as_str() {
+        "IN" => Ok(DNSClass::IN),
+        "CH" | "CHAOS" => Ok(DNSClass::CH),
+        "HS" | "HESIOD" => Ok(DNSClass::HS),
+        _ => Err(format!("unknown DNS class {:?} (expected IN, CH or HS)", class)),
+    }
+}
+
+/// Looks up and prints the records, returning them for checks that
+/// follow, such as `--check-dnsbl`.
+async fn perform_dns(
+    domain: &str,
+    record_type_str: &str,
+    failover: bool,
+    output: DnsOutput,
+) -> Result<Vec<DnsAnswer>> {
+    let record_type = parse_record_type(record_type_str)?;
 
-    let record_type = match record_type_str.to_uppercase().as_str() {
-        "A" => RecordType::A,
-        "AAAA" => RecordType::AAAA,
-        "MX" => RecordType::MX,
-        "TXT" => RecordType::TXT,
-        "NS" => RecordType::NS,
-        "CNAME" => RecordType::CNAME,
-        _ => return Err(anyhow::anyhow!("Unsupported record type")),
+    let response = if failover {
+        resolver::lookup_with_failover(domain, record_type).await?
+    } else {
+        concurrency::limited(create_resolver().lookup(domain, record_type)).await?
     };
+    let answers = DnsAnswer::from_lookup(&response);
+    match output.format {
+        OutputFormat::Text => print_dns_records(&answers, output),
+        _ => emit_dns_records(None, &answers, output)?,
+    }
+    checks::warn_apex_cname(domain, record_type, &answers);
+
+    Ok(answers)
+}
 
-    let response = resolver.lookup(domain, record_type)?;
+/// The zones given with `--dnsbl`, or the default ones.
+fn dnsbl_zones(zones: Vec<String>) -> Vec<String> {
+    if zones.is_empty() {
+        dnsbl::DEFAULT_ZONES.iter().map(|zone| zone.to_string()).collect()
+    } else {
+        zones
+    }
+}
+
+/// Sorts the records for `--order`. The sort is stable, so records that
+/// compare equal keep the server's order.
+fn sort_records(answers: &mut [DnsAnswer], order: RecordOrder) {
+    if order == RecordOrder::Default {
+        return;
+    }
+    let alpha = |a: &DnsAnswer, b: &DnsAnswer| a.data.to_string().cmp(&b.data.to_string());
+    let priority = |a: &DnsAnswer, b: &DnsAnswer| match (&a.data, &b.data) {
+        (RecordData::Mx { preference: p, exchange: x }, RecordData::Mx { preference: q, exchange: y }) => {
+            Some((p, x).cmp(&(q, y)))
+        }
+        _ => None,
+    };
+    let numeric = |a: &DnsAnswer, b: &DnsAnswer| match (&a.data, &b.data) {
+        (RecordData::A(x), RecordData::A(y)) => Some(x.cmp(y)),
+        (RecordData::Aaaa(x), RecordData::Aaaa(y)) => Some(x.cmp(y)),
+        (RecordData::Ds { key_tag: x, .. }, RecordData::Ds { key_tag: y, .. })
+        | (RecordData::Dnskey { key_tag: x, .. }, RecordData::Dnskey { key_tag: y, .. }) => Some(x.cmp(y)),
+        _ => priority(a, b),
+    };
+
+    answers.sort_by(|a, b| {
+        let chain_first = (a.record_type != RecordType::CNAME).cmp(&(b.record_type != RecordType::CNAME));
+        chain_first.then_with(|| match order {
+            RecordOrder::Alpha => alpha(a, b),
+            RecordOrder::Numeric => numeric(a, b).unwrap_or_else(|| alpha(a, b)),
+            RecordOrder::Priority => priority(a, b).unwrap_or(std::cmp::Ordering::Equal),
+            RecordOrder::Default => std::cmp::Ordering::Equal,
+        })
+    });
+}
+
+/// Prints the records in `output.format` for scripts: a `records` document
+/// for `json` and `yaml`, or one line per record for `jsonl`, as each answer
+/// arrives.
+fn emit_dns_records(target: Option<&str>, answers: &[DnsAnswer], output: DnsOutput) -> Result<()> {
+    let mut answers = answers.to_vec();
+    sort_records(&mut answers, output.order);
+    let records: Vec<RecordLine> = answers
+        .iter()
+        .map(|answer| RecordLine {
+            target,
+            name: &answer.name,
+            record_type: answer.record_type.to_string(),
+            ttl: answer.ttl,
+            data: match &answer.data {
+                RecordData::Txt(strings) if output.raw_txt => dns::quote_txt(strings),
+                other => other.to_string(),
+            },
+        })
+        .collect();
+    match output.format {
+        OutputFormat::Text | OutputFormat::Jsonl => {
+            for record in records {
+                println!("{}", serde_json::to_string(&Versioned::new(record))?);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&Versioned::new(DnsRecords { records }))?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&Versioned::new(DnsRecords { records }))?),
+    }
+    Ok(())
+}
+
+fn print_dns_records(answers: &[DnsAnswer], output: DnsOutput) {
+    if output.summarize_cidr {
+        let (addresses, others): (Vec<DnsAnswer>, Vec<DnsAnswer>) = answers
+            .iter()
+            .cloned()
+            .partition(|answer| matches!(answer.data, RecordData::A(_) | RecordData::Aaaa(_)));
+        let output = DnsOutput { summarize_cidr: false, ..output };
+        if !others.is_empty() || addresses.is_empty() {
+            print_dns_records(&others, output);
+        }
+        print_networks(&addresses);
+        return;
+    }
+    let mut answers = answers.to_vec();
+    sort_records(&mut answers, output.order);
+    let data = |answer: &DnsAnswer| match &answer.data {
+        RecordData::Txt(strings) if output.raw_txt => dns::quote_txt(strings),
+        other => other.to_string(),
+    };
+    if output.only_record_data {
+        for answer in &answers {
+            if output.with_ttl {
+                println!("{}\t{}\t{}", answer.record_type, answer.ttl, idn::display(&data(answer)));
+            } else {
+                println!("{}\t{}", answer.record_type, idn::display(&data(answer)));
+            }
+        }
+        return;
+    }
+    if diag::is_quiet() {
+        for answer in &answers {
+            println!("{}", idn::display(&data(answer)));
+        }
+        return;
+    }
 
     println!("{}", "DNS Records:".green().bold());
     println!("{}", "-".repeat(50));
 
-    for record in response.iter() {
-        match record {
-            trust_dns_resolver::proto::rr::record_data::RData::A(ip) => {
-                println!("A Record: {}", ip);
+    for answer in &answers {
+        let line = match &answer.data {
+            RecordData::A(ip) => format!("A Record: {}", ip),
+            RecordData::Aaaa(ip) => format!("AAAA Record: {}", ip),
+            RecordData::Mx { preference, exchange } => {
+                format!("MX Record: {} (priority: {})", exchange, preference)
             }
-            trust_dns_resolver::proto::rr::record_data::RData::AAAA(ip) => {
-                println!("AAAA Record: {}", ip);
+            RecordData::Txt(_) => format!("TXT Record: {}", data(answer)),
+            RecordData::Ns(ns) => format!("NS Record: {}", ns),
+            RecordData::Cname(cname) => format!("CNAME Record: {}", cname),
+            RecordData::Soa { mname, rname, serial, .. } => {
+                format!("SOA Record: {} {} (serial: {})", mname, rname, serial)
             }
-            trust_dns_resolver::proto::rr::record_data::RData::MX(mx) => {
-                println!("MX Record: {} (priority: {})", mx.exchange(), mx.preference());
+            RecordData::Dnskey { flags, algorithm, key_tag, .. } => {
+                let role = if flags & 1 == 1 { "KSK" } else { "ZSK" };
+                format!(
+                    "DNSKEY Record: key tag {}, {} ({}), flags {}",
+                    key_tag,
+                    role,
+                    Algorithm::from_u8(*algorithm),
+                    flags
+                )
             }
-            trust_dns_resolver::proto::rr::record_data::RData::TXT(txt) => {
-                println!("TXT Record: {}", txt.txt_data().iter()
-                    .map(|bytes| String::from_utf8_lossy(bytes))
-                    .collect::<Vec<_>>()
-                    .join(" "));
+            RecordData::Ds { key_tag, algorithm, digest_type, digest } => format!(
+                "DS Record: key tag {}, {}, digest type {}: {}",
+                key_tag,
+                Algorithm::from_u8(*algorithm),
+                digest_type_name(*digest_type),
+                digest
+            ),
+            RecordData::Rrsig { type_covered, algorithm, key_tag, signer_name, expiration, .. } => format!(
+                "RRSIG Record: covers {}, {}, key tag {} by {} (expires {})",
+                type_covered,
+                Algorithm::from_u8(*algorithm),
+                key_tag,
+                signer_name,
+                dns::format_sig_time(*expiration)
+            ),
+            RecordData::Nsec { next_name, types } => {
+                format!("NSEC Record: next {} (types: {})", next_name, types.join(" "))
             }
-            trust_dns_resolver::proto::rr::record_data::RData::NS(ns) => {
-                println!("NS Record: {}", ns);
+            RecordData::Nsec3 { iterations, salt, next_hashed_owner, types, .. } => format!(
+                "NSEC3 Record: next {} (iterations: {}, salt: {}, types: {})",
+                next_hashed_owner,
+                iterations,
+                if salt.is_empty() { "-" } else { salt },
+                types.join(" ")
+            ),
+            RecordData::Naptr { order, preference, flags, services, regexp, replacement } => format!(
+                "NAPTR Record: order {}, preference {}, flags {:?}, service {:?}, regexp {:?}, replacement {}",
+                order, preference, flags, services, regexp, replacement
+            ),
+            RecordData::Tlsa { usage, selector, matching_type, cert_data } => format!(
+                "TLSA Record: usage {}, selector {}, matching type {}: {}",
+                tlsa_usage_name(*usage),
+                tlsa_selector_name(*selector),
+                tlsa_matching_name(*matching_type),
+                cert_data
+            ),
+            RecordData::Other(data) => format!("Other Record: {} {}", answer.record_type, data),
+        };
+        println!("{}", idn::display(&line));
+    }
+}
+
+/// Prints the networks of the addresses for `dns --summarize-cidr`: each
+/// /24 or /48 with the number of unique addresses in it, largest first.
+/// CDNs answer with large sets that change between queries, and the
+/// networks say more about their footprint than the addresses do.
+fn print_networks(answers: &[DnsAnswer]) {
+    let addresses: BTreeSet<IpAddr> = answers
+        .iter()
+        .filter_map(|answer| match answer.data {
+            RecordData::A(ip) => Some(IpAddr::V4(ip)),
+            RecordData::Aaaa(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })
+        .collect();
+    let mut networks: BTreeMap<String, usize> = BTreeMap::new();
+    for address in &addresses {
+        let network = match address {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                format!("{}.{}.{}.0/24", a, b, c)
             }
-            trust_dns_resolver::proto::rr::record_data::RData::CNAME(cname) => {
-                println!("CNAME Record: {}", cname);
+            IpAddr::V6(ip) => {
+                let segments = ip.segments();
+                let network = Ipv6Addr::new(segments[0], segments[1], segments[2], 0, 0, 0, 0, 0);
+                format!("{}/48", network)
             }
-            _ => println!("Other Record: {:?}", record),
+        };
+        *networks.entry(network).or_default() += 1;
+    }
+    let mut networks: Vec<(String, usize)> = networks.into_iter().collect();
+    networks.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    if diag::is_quiet() {
+        for (network, count) in &networks {
+            println!("{} {}", network, count);
         }
+        return;
+    }
+    if addresses.is_empty() {
+        return;
+    }
+    println!("{}", "Networks:".green().bold());
+    println!("{}", "-".repeat(50));
+    let plural = |count: usize, one: &'static str, many: &'static str| if count == 1 { one } else { many };
+    println!(
+        "{} unique {} in {} {}",
+        addresses.len(),
+        plural(addresses.len(), "address", "addresses"),
+        networks.len(),
+        plural(networks.len(), "network", "networks")
+    );
+    for (network, count) in &networks {
+        println!("{:<24} {} {}", network, count, plural(*count, "address", "addresses"));
+    }
+}
+
+/// Names DS digest types by their hash, as assigned by IANA.
+fn digest_type_name(digest_type: u8) -> String {
+    match digest_type {
+        1 => "SHA-1".to_string(),
+        2 => "SHA-256".to_string(),
+        4 => "SHA-384".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// The certificate usage of a TLSA record (RFC 7218 mnemonics).
+fn tlsa_usage_name(usage: u8) -> String {
+    match usage {
+        0 => "PKIX-TA".to_string(),
+        1 => "PKIX-EE".to_string(),
+        2 => "DANE-TA".to_string(),
+        3 => "DANE-EE".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Whether a TLSA record matches the whole certificate or its public key.
+fn tlsa_selector_name(selector: u8) -> String {
+    match selector {
+        0 => "Cert".to_string(),
+        1 => "SPKI".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn tlsa_matching_name(matching_type: u8) -> String {
+    match matching_type {
+        0 => "Full".to_string(),
+        1 => "SHA-256".to_string(),
+        2 => "SHA-512".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Looks up the NS records published by the zone, lowercased and without the
+/// trailing dot so they compare directly with the WHOIS name servers.
+async fn lookup_name_servers(resolver: &TokioAsyncResolver, domain: &str) -> Result<Vec<String>> {
+    let response = resolver.ns_lookup(domain).await?;
+    Ok(response
+        .iter()
+        .map(|ns| ns.to_string().trim_end_matches('.').to_lowercase())
+        .collect())
+}
+
+/// Compares the name servers the registry has on file with the NS records
+/// the zone actually publishes, which catches lame or stale delegations.
+async fn check_name_servers(domain: &str, response: &str) -> Result<()> {
+    let registry = parse::name_servers(&parse::parse_whois_fields(response));
+    let resolver = create_resolver();
+    let live = lookup_name_servers(&resolver, domain).await?;
+
+    let mut rows: Vec<String> = registry.clone();
+    rows.extend(live.iter().filter(|ns| !registry.contains(ns)).cloned());
+
+    println!("{}", "Name Server Check:".green().bold());
+    println!("{}", "-".repeat(50));
+    println!("{:<30} {:<30} Status", "Registry NS", "Live NS");
+
+    for ns in &rows {
+        let in_registry = registry.contains(ns);
+        let in_zone = live.contains(ns);
+        let resolves = family::all_addresses(&resolver, ns).await.is_ok();
+
+        let status = match (in_registry, in_zone) {
+            (true, true) if resolves => "match".green(),
+            (true, true) => "match (does not resolve)".yellow(),
+            (true, false) if resolves => "registry only".yellow(),
+            (true, false) => "registry only (does not resolve)".red(),
+            _ => "zone only".yellow(),
+        };
+
+        println!(
+            "{:<30} {:<30} {}",
+            if in_registry { ns.as_str() } else { "-" },
+            if in_zone { ns.as_str() } else { "-" },
+            status
+        );
+    }
+
+    if registry.is_empty() {
+        println!("{}", "No name servers found in the WHOIS response".yellow());
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tld_ignores_a_trailing_dot_and_case() {
+        assert_eq!(get_tld("example.com.").as_deref(), Some("com"));
+        assert_eq!(get_tld("EXAMPLE.COM").as_deref(), Some("com"));
+        assert_eq!(get_tld("a.b.example.co.uk").as_deref(), Some("uk"));
+        assert_eq!(get_tld("."), None);
+    }
+
+    #[test]
+    fn targets_are_lowercased_without_the_trailing_dot() {
+        assert_eq!(normalize_target("example.com."), "example.com");
+        assert_eq!(normalize_target("EXAMPLE.COM"), "example.com");
+        assert_eq!(normalize_target("a.b.Example.co.uk"), "a.b.example.co.uk");
+        assert_eq!(normalize_target(" https://Example.com./path "), "example.com");
+        assert_eq!(normalize_target("2001:DB8::/32"), "2001:db8::/32");
+    }
+
+    #[test]
+    fn dnssec_record_types_parse_in_any_case() {
+        for (text, record_type) in [
+            ("DNSKEY", RecordType::DNSKEY),
+            ("ds", RecordType::DS),
+            ("Rrsig", RecordType::RRSIG),
+            ("nsec", RecordType::NSEC),
+            ("NSEC3", RecordType::NSEC3),
+        ] {
+            assert_eq!(parse_record_type(text).unwrap(), record_type, "{}", text);
+        }
+        assert!(parse_record_type("NSEC3PARAM").is_err());
+    }
+
+    #[test]
+    fn output_files_keep_disclaimers_without_colors() {
+        let response = "Domain Name: EXAMPLE.COM\nTERMS OF USE: You are not authorized to access or query our\n\
+                        Whois database through the use of electronic processes.\nRegistrar: Example Registrar\n";
+        let path = std::env::temp_dir().join(format!("whois-dns-output-{}.txt", std::process::id()));
+        colored::control::set_override(true);
+        let written = write_response(&path, response, encoding::OutputEncoding::Utf8).map(|_| std::fs::read(&path));
+        colored::control::unset_override();
+        let _ = std::fs::remove_file(&path);
+
+        let contents = written.unwrap().unwrap();
+        assert!(!contents.contains(&0x1b));
+        assert_eq!(String::from_utf8(contents).unwrap(), response);
+    }
+
+    #[test]
+    fn naptr_and_tlsa_record_types_parse() {
+        assert_eq!(parse_record_type("NAPTR").unwrap(), RecordType::NAPTR);
+        assert_eq!(parse_record_type("naptr").unwrap(), RecordType::NAPTR);
+        assert_eq!(parse_record_type("tlsa").unwrap(), RecordType::TLSA);
+    }
+
+    #[test]
+    fn tlsa_fields_use_the_rfc_7218_mnemonics() {
+        assert_eq!(tlsa_usage_name(3), "DANE-EE");
+        assert_eq!(tlsa_usage_name(0), "PKIX-TA");
+        assert_eq!(tlsa_selector_name(1), "SPKI");
+        assert_eq!(tlsa_matching_name(1), "SHA-256");
+        assert_eq!(tlsa_usage_name(255), "255");
+    }
+}
@@ -0,0 +1,114 @@
+//! WHOIS responses taller than the terminal shown through a pager,
+//! controlled by the global `--no-pager` flag. Only the response of a
+//! single `whois` lookup is paged; `batch` prints every result directly.
+
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use terminal_size::{Height, Width};
+
+use crate::diag;
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn init(disabled: bool) {
+    DISABLED.store(disabled, Ordering::Relaxed);
+}
+
+/// `$PAGER`, split like a shell would, or `less`. An empty `$PAGER` or
+/// `cat` turns paging off.
+fn pager_command() -> Option<Vec<String>> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    let words = shell_words::split(&pager).ok()?;
+    words.first().filter(|program| program.as_str() != "cat")?;
+    Some(words)
+}
+
+/// Prints `text` through the pager when stdout is a terminal and the text
+/// is taller than it, or directly otherwise. `less` gets `-FRX` unless
+/// `$LESS` says otherwise: it keeps the colors and leaves the text on the
+/// screen after quitting. A pager that cannot be started falls back to
+/// printing directly. The pager is waited for on a blocking thread.
+pub async fn print(text: String) {
+    if DISABLED.load(Ordering::Relaxed) || !std::io::stdout().is_terminal() {
+        println!("{}", text);
+        return;
+    }
+    let Some((Width(columns), Height(rows))) = terminal_size::terminal_size() else {
+        println!("{}", text);
+        return;
+    };
+    if fits(&text, columns.into(), rows.into()) {
+        println!("{}", text);
+        return;
+    }
+    let Some(words) = pager_command() else {
+        println!("{}", text);
+        return;
+    };
+    let _ = tokio::task::spawn_blocking(move || run_pager(&words, &text)).await;
+}
+
+/// Whether `text` takes fewer rows than the terminal has, leaving one for
+/// the prompt. Long lines count once per row they wrap onto, and color
+/// escapes take no room.
+fn fits(text: &str, columns: usize, rows: usize) -> bool {
+    let columns = columns.max(1);
+    let needed: usize = text.lines().map(|line| visible_width(line).div_ceil(columns).max(1)).sum();
+    needed < rows
+}
+
+fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in line.chars() {
+        match (in_escape, c) {
+            (false, '\x1b') => in_escape = true,
+            (true, 'm') => in_escape = false,
+            (true, _) => {}
+            (false, _) => width += 1,
+        }
+    }
+    width
+}
+
+fn run_pager(words: &[String], text: &str) {
+    let mut command = Command::new(&words[0]);
+    command.args(&words[1..]).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            diag::verbose(&format!("Could not start the pager {}: {}", words[0], e));
+            println!("{}", text);
+            return;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe; that is not an error
+        let _ = writeln!(stdin, "{}", text);
+    }
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_fits_and_long_text_does_not() {
+        let three_lines = "Domain Name: EXAMPLE.COM\nRegistrar: Example\nStatus: ok";
+        assert!(fits(three_lines, 80, 24));
+        let long: String = (0..30).map(|n| format!("line {}\n", n)).collect();
+        assert!(!fits(&long, 80, 24));
+        assert!(!fits(&"x".repeat(80 * 24), 80, 24));
+    }
+
+    #[test]
+    fn color_escapes_take_no_room() {
+        assert_eq!(visible_width("\x1b[1;32mWHOIS Information:\x1b[0m"), 18);
+        assert!(fits(&format!("\x1b[34m{}\x1b[0m", "x".repeat(80)), 80, 3));
+    }
+}
@@ -0,0 +1,835 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const CREATION_FIELDS: &[&str] = &[
+    "Creation Date",
+    "Created",
+    "created",
+    "Registered on",
+    "Registration Time",
+];
+
+const UPDATED_FIELDS: &[&str] = &[
+    "Updated Date",
+    "Last Updated",
+    "Last updated",
+    "Last Modified",
+    "Last Update",
+    "changed",
+    "last-update",
+];
+
+const EXPIRY_FIELDS: &[&str] = &[
+    "Registry Expiry Date",
+    "Registrar Registration Expiration Date",
+    "Expiry Date",
+    "Expiration Date",
+    "Expire Date",
+    "Expires",
+    "paid-till",
+];
+
+/// How registries start the line saying a domain is not registered, in
+/// lower case: Verisign and EDUCAUSE (`No match for`), auDA (`NOT FOUND`),
+/// DENIC (`Status: free`), NIC.it (`Status: AVAILABLE`), the RIPE-style
+/// databases (`%ERROR:101: no entries found`) and CentralNic-run gTLDs.
+const NOT_FOUND_MARKERS: &[&str] = &[
+    "no match",
+    "not found",
+    "domain not found",
+    "no data found",
+    "no entries found",
+    "error:101: no entries found",
+    "no matching record",
+    "status: free",
+    "status: available",
+    "the queried object does not exist",
+];
+
+/// Strips a leading UTF-8 byte order mark and turns CRLF and bare CR line
+/// endings into LF. Servers mix them, and a BOM would otherwise become part
+/// of the first key.
+pub fn normalize_response(response: &str) -> String {
+    let response = response.strip_prefix('\u{feff}').unwrap_or(response);
+    response.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Whether the response says the queried domain is not registered. Only
+/// the start of a line counts, so terms of use that mention "not found"
+/// in passing do not. SIDN's `example.nl is free` is matched at the end.
+pub fn classify_whois_not_found(response: &str) -> bool {
+    normalize_response(response).lines().any(|line| {
+        let line = line.trim().trim_start_matches(['%', '#', ' ']).to_lowercase();
+        NOT_FOUND_MARKERS.iter().any(|marker| line.starts_with(marker)) || line.ends_with(" is free")
+    })
+}
+
+/// Phrases of the notices servers send instead of a record once a client
+/// exceeds their query limits, in lower case.
+const RATE_LIMIT_MARKERS: &[&str] = &[
+    "rate limit",
+    "limit exceeded",
+    "exceeded the maximum",
+    "too many queries",
+    "too many requests",
+    "query limit",
+    "access control limit",
+    "try again later",
+];
+
+/// Whether the response is a rate-limit notice rather than a record. Only
+/// short responses count, since full records often cite the limits in
+/// their terms of use.
+pub fn is_rate_limited(response: &str) -> bool {
+    let response = response.to_lowercase();
+    response.lines().filter(|line| !line.trim().is_empty()).count() <= 5
+        && RATE_LIMIT_MARKERS.iter().any(|marker| response.contains(marker))
+}
+
+/// Splits a raw WHOIS response into `(key, value)` pairs in the order they
+/// appear. Comment lines (`%`, `#`) are skipped. A key with an empty value
+/// followed by more deeply indented lines, as used by Nominet for
+/// `Name servers:`, yields one pair per indented line.
+pub fn parse_whois_fields(response: &str) -> Vec<(String, String)> {
+    let response = normalize_response(response);
+    let mut fields = Vec::new();
+    let mut block: Option<(String, usize)> = None;
+
+    for line in response.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            block = None;
+            continue;
+        }
+        if trimmed.starts_with('%') || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        if let Some((key, key_indent)) = &block {
+            if indent > *key_indent {
+                fields.push((key.clone(), trimmed.to_string()));
+                continue;
+            }
+            block = None;
+        }
+
+        if let Some((key, value)) = trimmed.split_once(':') {
+            let key = key.trim();
+            if key.is_empty() || key.contains("  ") {
+                continue;
+            }
+            let value = value.trim();
+            if value.is_empty() {
+                block = Some((key.to_string(), indent));
+            } else {
+                fields.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+
+    fields
+}
+
+/// The fields that point elsewhere, as `(rel, href)`: `Related:` lines and
+/// any field whose value is a web address, such as `Registrar URL`. The key
+/// serves as the relation. Repeated pairs are listed once.
+pub fn related_links(fields: &[(String, String)]) -> Vec<(String, String)> {
+    let mut links: Vec<(String, String)> = Vec::new();
+    for (key, value) in fields {
+        let is_link = key.eq_ignore_ascii_case("related")
+            || value.starts_with("http://")
+            || value.starts_with("https://");
+        let link = (key.clone(), value.clone());
+        if is_link && !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// Groups the parsed fields by key for structured output. Keys that repeat,
+/// such as `Name Server`, keep all their values in order.
+pub fn fields_to_map(fields: &[(String, String)]) -> BTreeMap<String, Vec<String>> {
+    let mut map: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, value) in fields {
+        map.entry(key.clone()).or_default().push(value.clone());
+    }
+    map
+}
+
+/// Returns every value whose key matches one of `names`, ignoring case.
+pub fn field_values<'a>(fields: &'a [(String, String)], names: &[&str]) -> Vec<&'a str> {
+    fields
+        .iter()
+        .filter(|(key, _)| names.iter().any(|name| key.eq_ignore_ascii_case(name)))
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+/// Returns the sponsoring registrar's name.
+pub fn registrar(fields: &[(String, String)]) -> Option<&str> {
+    field_values(fields, &["Registrar", "Sponsoring Registrar"]).into_iter().next()
+}
+
+/// Returns the EPP status codes or registry states, in order.
+pub fn statuses(fields: &[(String, String)]) -> Vec<&str> {
+    field_values(fields, &["Domain Status", "Status", "state"])
+}
+
+/// The standard EPP domain status codes (RFC 5731 and RFC 3915) with a short
+/// explanation of each.
+const EPP_STATUS_CODES: &[(&str, &str)] = &[
+    ("ok", "no pending operations or restrictions"),
+    ("active", "no pending operations or restrictions"),
+    ("inactive", "no name servers delegated, the domain does not resolve"),
+    ("addPeriod", "recently registered, can be deleted for a refund"),
+    ("autoRenewPeriod", "automatically renewed at expiry, can still be deleted for a refund"),
+    ("renewPeriod", "recently renewed"),
+    ("transferPeriod", "recently transferred to a new registrar"),
+    ("redemptionPeriod", "deleted, the registrant can still restore it"),
+    ("pendingCreate", "registration requested but not completed"),
+    ("pendingDelete", "will be purged and released within days"),
+    ("pendingRenew", "renewal requested but not completed"),
+    ("pendingRestore", "restore from redemption requested but not completed"),
+    ("pendingTransfer", "transfer to another registrar in progress"),
+    ("pendingUpdate", "update requested but not completed"),
+    ("clientDeleteProhibited", "the registrar blocks deletion"),
+    ("clientHold", "the registrar suspended it, the domain does not resolve"),
+    ("clientRenewProhibited", "the registrar blocks renewal"),
+    ("clientTransferProhibited", "the registrar blocks transfers (transfer lock)"),
+    ("clientUpdateProhibited", "the registrar blocks changes to contacts and name servers"),
+    ("serverDeleteProhibited", "the registry blocks deletion"),
+    ("serverHold", "the registry suspended it, the domain does not resolve"),
+    ("serverRenewProhibited", "the registry blocks renewal"),
+    ("serverTransferProhibited", "the registry blocks transfers"),
+    ("serverUpdateProhibited", "the registry blocks changes (registry lock)"),
+];
+
+/// Splits a status line such as `clientTransferProhibited
+/// https://icann.org/epp#clientTransferProhibited` into the code and, for a
+/// standard EPP code, its explanation. Spelled-out forms like
+/// `client transfer prohibited` are recognized too.
+pub fn epp_status(status: &str) -> (&str, Option<&'static str>) {
+    let code = status.split(" http").next().unwrap_or(status).trim();
+    let compact: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let description = EPP_STATUS_CODES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(&compact))
+        .map(|(_, description)| *description);
+    (code, description)
+}
+
+/// Extracts the delegated name servers, lowercased and without a trailing
+/// dot. Registries that list glue next to the host (`ns1.example.com
+/// 192.0.2.1`) only contribute the host name.
+pub fn name_servers(fields: &[(String, String)]) -> Vec<String> {
+    let mut servers: Vec<String> = Vec::new();
+    for value in field_values(fields, &["Name Server", "Name servers", "nserver"]) {
+        if let Some(host) = value.split_whitespace().next() {
+            let host = host.trim_end_matches('.').to_lowercase();
+            if !servers.contains(&host) {
+                servers.push(host);
+            }
+        }
+    }
+    servers
+}
+
+/// Returns the WHOIS server a response refers to, without a `whois://`
+/// scheme or port: the registrar server of a thin registry, an ARIN
+/// `ReferralServer` or the `refer` line of IANA.
+pub fn referral_server(fields: &[(String, String)]) -> Option<String> {
+    let value = field_values(fields, &["Registrar WHOIS Server", "ReferralServer", "refer"])
+        .into_iter()
+        .next()?;
+    let host = value.split_once("://").map_or(value, |(_, rest)| rest);
+    let host = host.split([':', '/']).next()?.trim().to_lowercase();
+    (!host.is_empty()).then_some(host)
+}
+
+/// Returns the first follow-up query a response asks for with a line like
+/// `To get more info query "NET-192-0-2-0-1"`, ignoring those in `sent`.
+/// Comment lines count, since servers usually print the hint as one.
+pub fn continuation_query(response: &str, sent: &[String]) -> Option<String> {
+    response.lines().find_map(|line| {
+        let lower = line.to_ascii_lowercase();
+        if !lower.contains("more") {
+            return None;
+        }
+        let after = &line[lower.find("query ")? + "query ".len()..];
+        let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let query = after[1..].split(quote).next()?.trim();
+        (!query.is_empty() && !sent.iter().any(|s| s == query)).then(|| query.to_string())
+    })
+}
+
+/// Abuse contacts published in a WHOIS response.
+#[derive(Default)]
+pub struct AbuseContact {
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+}
+
+impl AbuseContact {
+    pub fn is_empty(&self) -> bool {
+        self.emails.is_empty() && self.phones.is_empty()
+    }
+}
+
+/// Collects the abuse contact across the field names used by registrars
+/// (`Registrar Abuse Contact Email`), RIPE-style databases (`abuse-mailbox`)
+/// and ARIN (`OrgAbuseEmail`). RIPE also states the contact in a comment
+/// line, `% Abuse contact for '...' is 'abuse@example.net'`.
+pub fn abuse_contact(response: &str) -> AbuseContact {
+    let fields = parse_whois_fields(response);
+    let mut contact = AbuseContact::default();
+
+    for email in field_values(
+        &fields,
+        &["Registrar Abuse Contact Email", "abuse-mailbox", "OrgAbuseEmail", "Abuse Email"],
+    ) {
+        push_unique(&mut contact.emails, email);
+    }
+    for line in response.lines() {
+        if let Some(rest) = line.trim().strip_prefix("% Abuse contact for") {
+            if let Some(email) = rest.rsplit("' is '").next() {
+                push_unique(&mut contact.emails, email.trim_end_matches('\''));
+            }
+        }
+    }
+    for phone in field_values(&fields, &["Registrar Abuse Contact Phone", "OrgAbusePhone", "Abuse Phone"]) {
+        push_unique(&mut contact.phones, phone);
+    }
+
+    contact
+}
+
+fn push_unique(list: &mut Vec<String>, value: &str) {
+    let value = value.trim();
+    if !value.is_empty() && !list.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        list.push(value.to_string());
+    }
+}
+
+/// How the paragraphs of registry boilerplate usually begin, lowercased.
+const DISCLAIMER_MARKERS: &[&str] = &[
+    "terms of use",
+    "terms and conditions",
+    "notice:",
+    "legal notice",
+    "disclaimer",
+    "important:",
+    "please note",
+    "the data in",
+    "the data contained",
+    "the whois information",
+    "this information is provided",
+    "access to",
+    "by submitting",
+    "by querying",
+    "by using",
+    "you agree",
+    "copyright",
+    "all rights reserved",
+    "the registrar of record",
+    "for more information on whois status codes",
+];
+
+/// Marks the lines of a response that belong to the registry's disclaimer
+/// rather than its data, one flag per line of `response.lines()`. Hidden are:
+///
+/// - runs of two or more `%` or `#` comment lines with some prose in them,
+///   which leaves single headers and ARIN's `# start` markers alone
+/// - paragraphs starting with a marker such as `TERMS OF USE:` or `NOTICE:`
+/// - the prose paragraphs after the `>>>` footer or after another hidden
+///   paragraph, as long as they hold no `key: value` line
+pub fn disclaimer_lines(response: &str) -> Vec<bool> {
+    let lines: Vec<&str> = response.lines().map(str::trim).collect();
+    let is_comment = |line: &str| line.starts_with('%') || line.starts_with('#');
+    let mut hidden = vec![false; lines.len()];
+
+    let mut start = 0;
+    while start < lines.len() {
+        if !is_comment(lines[start]) {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end + 1 < lines.len() && is_comment(lines[end + 1]) {
+            end += 1;
+        }
+        let run = &lines[start..=end];
+        let prose = run.iter().any(|line| line.split_whitespace().count() >= 4);
+        if run.len() >= 2 && prose {
+            hidden[start..=end].fill(true);
+        }
+        start = end + 1;
+    }
+
+    let mut after_footer = false;
+    let mut in_disclaimer = false;
+    let mut start = 0;
+    while start < lines.len() {
+        if lines[start].is_empty() || is_comment(lines[start]) {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end + 1 < lines.len() && !lines[end + 1].is_empty() && !is_comment(lines[end + 1]) {
+            end += 1;
+        }
+        let paragraph = &lines[start..=end];
+        let first = paragraph[0].to_lowercase();
+        let marked = DISCLAIMER_MARKERS.iter().any(|marker| first.starts_with(marker));
+        let prose = !paragraph.iter().any(|line| is_data_line(line));
+        in_disclaimer = marked || ((after_footer || in_disclaimer) && prose);
+        if in_disclaimer {
+            hidden[start..=end].fill(true);
+        }
+        after_footer |= paragraph.iter().any(|line| line.starts_with(">>>"));
+        start = end + 1;
+    }
+
+    // Blank lines between two hidden blocks, or after the last one, go with
+    // them so every disclaimer collapses into one place
+    let mut index = 0;
+    while index < lines.len() {
+        if !lines[index].is_empty() || hidden[index] {
+            index += 1;
+            continue;
+        }
+        let mut end = index;
+        while end < lines.len() && lines[end].is_empty() {
+            end += 1;
+        }
+        let before = index > 0 && hidden[index - 1];
+        if before && (end == lines.len() || hidden[end]) {
+            hidden[index..end].fill(true);
+        }
+        index = end;
+    }
+    hidden
+}
+
+/// Whether a line reads as a `key: value` field rather than prose: a short
+/// key of at most four words, with a value after it.
+fn is_data_line(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, value)) => {
+            let words = key.split_whitespace().count();
+            (1..=4).contains(&words) && !value.trim().is_empty()
+        }
+        None => false,
+    }
+}
+
+/// Splits a response that lists several objects into the individual domain
+/// records, as `(domain name, record text)`. Verisign does this when a query
+/// matches more than one object; each record starts at a `Domain Name:` or
+/// `Server Name:` line and runs until the next one or the `>>>` footer.
+pub fn domain_records(response: &str) -> Vec<(&str, &str)> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    for line in response.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("Domain Name:")
+            || trimmed.starts_with("Server Name:")
+            || trimmed.starts_with(">>>")
+        {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    starts.push(response.len());
+
+    starts
+        .windows(2)
+        .filter_map(|bounds| {
+            let record = &response[bounds[0]..bounds[1]];
+            let first_line = record.lines().next()?.trim();
+            let name = first_line.strip_prefix("Domain Name:")?.trim();
+            Some((name, record.trim_end()))
+        })
+        .collect()
+}
+
+/// Returns the raw creation date as the registry wrote it.
+pub fn creation_date(fields: &[(String, String)]) -> Option<&str> {
+    field_values(fields, CREATION_FIELDS).into_iter().next()
+}
+
+/// Returns the raw expiry date as the registry wrote it.
+pub fn expiry_date(fields: &[(String, String)]) -> Option<&str> {
+    field_values(fields, EXPIRY_FIELDS).into_iter().next()
+}
+
+/// Returns the raw date of the last update as the registry wrote it.
+pub fn updated_date(fields: &[(String, String)]) -> Option<&str> {
+    field_values(fields, UPDATED_FIELDS).into_iter().next()
+}
+
+/// Timestamp layouts seen in WHOIS responses, tried after RFC 3339. Times
+/// without a zone are taken as UTC.
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y.%m.%d %H:%M:%S",
+    "%Y/%m/%d %H:%M:%S",
+    "%d-%b-%Y %H:%M:%S",
+    "%d.%m.%Y %H:%M:%S",
+    "%a %b %d %H:%M:%S %Y",
+];
+
+/// Date layouts seen in WHOIS responses, such as `13-Aug-2024` (older
+/// gTLD servers), `2024/08/13` (.jp), `2024. 08. 13.` (.kr), `13.08.2024`
+/// (.cz) and `20240813` (.br).
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y.%m.%d",
+    "%Y. %m. %d.",
+    "%Y/%m/%d",
+    "%Y%m%d",
+    "%d-%b-%Y",
+    "%d.%m.%Y",
+    "%d/%m/%Y",
+    "%d %b %Y",
+    "%B %d %Y",
+    "%b %d %Y",
+];
+
+/// A WHOIS timestamp, with or without the time of day.
+enum Timestamp {
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+fn parse_timestamp(raw: &str) -> Option<Timestamp> {
+    let raw = raw.trim();
+    // Nominet writes `before Aug-1996` for registrations older than its records
+    let raw = raw.strip_prefix("before ").unwrap_or(raw);
+    if let Some(timestamp) = DateTime::parse_from_rfc3339(raw)
+        .ok()
+        .or_else(|| DateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S%.f%z").ok())
+        .or_else(|| DateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S%.f%z").ok())
+    {
+        return Some(Timestamp::DateTime(timestamp.with_timezone(&Utc)));
+    }
+
+    // The remaining layouts have no offset; drop the zone names some
+    // registries write anyway, wherever they appear
+    let raw = raw.strip_suffix('Z').unwrap_or(raw).replace(',', "");
+    let raw = raw
+        .split_whitespace()
+        .filter(|word| !matches!(*word, "UTC" | "GMT" | "(UTC)" | "(GMT)"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if let Some(time) = DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&raw, format).ok())
+    {
+        return Some(Timestamp::DateTime(time.and_utc()));
+    }
+    if let Some(date) = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(&raw, format).ok())
+    {
+        return Some(Timestamp::Date(date));
+    }
+    // A date followed by a time in a layout not listed, such as a local zone
+    if let Some(date) = raw.get(..10).and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()) {
+        return Some(Timestamp::Date(date));
+    }
+    NaiveDate::parse_from_str(&format!("01-{}", raw), "%d-%b-%Y")
+        .ok()
+        .map(Timestamp::Date)
+}
+
+/// Parses the date part of a WHOIS timestamp in any of the layouts
+/// `normalize_date` knows.
+pub fn parse_date(raw: &str) -> Option<NaiveDate> {
+    match parse_timestamp(raw)? {
+        Timestamp::Date(date) => Some(date),
+        Timestamp::DateTime(time) => Some(time.date_naive()),
+    }
+}
+
+/// Rewrites a WHOIS timestamp as ISO 8601: `2024-08-13T04:00:00Z` when the
+/// registry gives a time, converted to UTC, or `2024-08-13` when it only
+/// gives the date.
+pub fn normalize_date(raw: &str) -> Option<String> {
+    match parse_timestamp(raw)? {
+        Timestamp::Date(date) => Some(date.format("%Y-%m-%d").to_string()),
+        Timestamp::DateTime(time) => Some(time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    }
+}
+
+/// The registration dates in ISO 8601, included in JSON output next to the
+/// raw fields. Dates in a layout that is not recognized are left out.
+#[derive(Default, Serialize)]
+pub struct Dates {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+}
+
+pub fn dates(fields: &[(String, String)]) -> Dates {
+    Dates {
+        created: creation_date(fields).and_then(normalize_date),
+        updated: updated_date(fields).and_then(normalize_date),
+        expires: expiry_date(fields).and_then(normalize_date),
+    }
+}
+
+/// A domain status with the explanation of a standard EPP code, as included
+/// in JSON output.
+#[derive(Serialize)]
+pub struct StatusCode {
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'static str>,
+}
+
+pub fn status_codes(fields: &[(String, String)]) -> Vec<StatusCode> {
+    statuses(fields)
+        .into_iter()
+        .map(|status| {
+            let (code, description) = epp_status(status);
+            StatusCode { code: code.to_string(), description }
+        })
+        .collect()
+}
+
+/// IANA's record of a top-level domain, as printed by `tld --format json`.
+#[derive(Default, Serialize)]
+pub struct IanaRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// The sponsoring organisation, not those of the contacts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organisation: Option<String>,
+    /// The registry's WHOIS server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whois: Option<String>,
+    /// The server IANA refers the query to, usually the same as `whois`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    pub name_servers: Vec<IanaNameServer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct IanaNameServer {
+    pub name: String,
+    pub addresses: Vec<String>,
+}
+
+impl IanaRecord {
+    /// The server to ask next: the registry's WHOIS server, or the one IANA
+    /// refers to when it lists none.
+    pub fn referral(&self) -> Option<&str> {
+        self.whois.as_deref().or(self.refer.as_deref())
+    }
+}
+
+/// Parses a response of `whois.iana.org`. Its records are one `key: value`
+/// per line, with the sponsoring organisation ahead of the contact blocks
+/// that repeat `organisation:`, and `nserver:` lines of a host name followed
+/// by its addresses.
+pub fn parse_iana_response(raw: &str) -> IanaRecord {
+    let mut record = IanaRecord::default();
+    for line in normalize_response(raw).lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() || line.starts_with('%') {
+            continue;
+        }
+        let first = |slot: &mut Option<String>| {
+            slot.get_or_insert_with(|| value.to_string());
+        };
+        match key.trim() {
+            "domain" => first(&mut record.domain),
+            "organisation" => first(&mut record.organisation),
+            "whois" => record.whois = Some(value.to_lowercase()),
+            "refer" => record.refer = Some(value.to_lowercase()),
+            "status" => first(&mut record.status),
+            "created" => first(&mut record.created),
+            "changed" => first(&mut record.changed),
+            "nserver" => {
+                let mut parts = value.split_whitespace();
+                if let Some(name) = parts.next() {
+                    record.name_servers.push(IanaNameServer {
+                        name: name.trim_end_matches('.').to_lowercase(),
+                        addresses: parts.map(str::to_string).collect(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn observed_date_layouts_normalize_to_iso_8601() {
+        for (raw, expected) in [
+            ("1995-08-14T04:00:00Z", "1995-08-14T04:00:00Z"),
+            ("2024-08-13T04:00:00.123Z", "2024-08-13T04:00:00Z"),
+            ("2024-08-13T06:00:00+02:00", "2024-08-13T04:00:00Z"),
+            ("2024-08-13 04:00:00+0200", "2024-08-13T02:00:00Z"),
+            ("2024-08-13 04:00:00 UTC", "2024-08-13T04:00:00Z"),
+            ("2024.08.13 04:00:00", "2024-08-13T04:00:00Z"),
+            ("13-Aug-2024 04:00:00 UTC", "2024-08-13T04:00:00Z"),
+            ("Tue Aug 13 04:00:00 GMT 2024", "2024-08-13T04:00:00Z"),
+            ("2024-08-13", "2024-08-13"),
+            ("13-aug-2024", "2024-08-13"),
+            ("before 13-Aug-1996", "1996-08-13"),
+            ("before Aug-1996", "1996-08-01"),
+            ("2024/08/13", "2024-08-13"),
+            ("2024. 08. 13.", "2024-08-13"),
+            ("13.08.2024", "2024-08-13"),
+            ("20240813", "2024-08-13"),
+            ("August 13 2024", "2024-08-13"),
+            ("13 Aug 2024", "2024-08-13"),
+        ] {
+            assert_eq!(normalize_date(raw).as_deref(), Some(expected), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn unknown_layouts_are_not_normalized() {
+        assert_eq!(normalize_date("next tuesday"), None);
+        assert_eq!(normalize_date(""), None);
+    }
+
+    #[test]
+    fn registration_dates_are_normalized_next_to_the_raw_fields() {
+        let fields = fields(&[
+            ("Creation Date", "1995-08-14T04:00:00Z"),
+            ("Updated Date", "14-Aug-2024"),
+            ("Registry Expiry Date", "not disclosed"),
+        ]);
+        let dates = dates(&fields);
+        assert_eq!(dates.created.as_deref(), Some("1995-08-14T04:00:00Z"));
+        assert_eq!(dates.updated.as_deref(), Some("2024-08-14"));
+        assert_eq!(dates.expires, None);
+        assert_eq!(expiry_date(&fields), Some("not disclosed"));
+    }
+
+    /// The same record with a byte order mark and CRLF, bare CR and LF line
+    /// endings mixed, as some servers send through proxies.
+    const MIXED_ENDINGS: &str = "\u{feff}Domain Name: EXAMPLE.COM\r\nRegistrar: Example Registrar\rName Server: NS1.EXAMPLE.COM\r\n\r\nName Server: NS2.EXAMPLE.COM\n";
+
+    #[test]
+    fn bom_and_line_endings_are_normalized() {
+        assert_eq!(
+            normalize_response(MIXED_ENDINGS),
+            "Domain Name: EXAMPLE.COM\nRegistrar: Example Registrar\nName Server: NS1.EXAMPLE.COM\n\nName Server: NS2.EXAMPLE.COM\n"
+        );
+        assert_eq!(normalize_response("no bom\n"), "no bom\n");
+    }
+
+    #[test]
+    fn fields_of_a_crlf_response_with_a_bom_have_clean_keys_and_values() {
+        let parsed = parse_whois_fields(MIXED_ENDINGS);
+        assert_eq!(
+            parsed,
+            fields(&[
+                ("Domain Name", "EXAMPLE.COM"),
+                ("Registrar", "Example Registrar"),
+                ("Name Server", "NS1.EXAMPLE.COM"),
+                ("Name Server", "NS2.EXAMPLE.COM"),
+            ])
+        );
+        assert!(parsed.iter().all(|(key, value)| !key.contains(['\r', '\u{feff}']) && !value.contains('\r')));
+    }
+
+    #[test]
+    fn not_found_is_recognized_in_a_crlf_response() {
+        assert!(classify_whois_not_found("\u{feff}No match for \"EXAMPLE.TEST\".\r\n>>> Last update <<<\r\n"));
+        assert!(!classify_whois_not_found(MIXED_ENDINGS));
+    }
+
+    /// `whois -h whois.iana.org com`, with the contact blocks shortened.
+    const IANA_COM: &str = "% IANA WHOIS server
+% for more information on IANA, visit http://www.iana.org
+% This query returned 1 object
+
+refer:        whois.verisign-grs.com
+
+domain:       COM
+
+organisation: VeriSign Global Registry Services
+address:      12061 Bluemont Way
+address:      Reston VA 20190
+address:      United States of America (the)
+
+contact:      administrative
+name:         Registry Customer Service
+organisation: VeriSign Global Registry Services
+address:      12061 Bluemont Way
+e-mail:       info@verisign-grs.com
+
+nserver:      A.GTLD-SERVERS.NET 192.5.6.30 2001:503:a83e:0:0:0:2:30
+nserver:      B.GTLD-SERVERS.NET 192.33.14.30 2001:503:231d:0:0:0:2:30
+ds-rdata:     19718 13 2 8acbb0cd28f41250a80a491389424d341522d946b0da0c0291f2d3d771d7805a
+
+whois:        whois.verisign-grs.com
+
+status:       ACTIVE
+remarks:      Registration information: http://www.verisigninc.com
+
+created:      1985-01-01
+changed:      2023-12-07
+source:       IANA
+";
+
+    #[test]
+    fn iana_record_is_parsed_into_its_fields() {
+        let record = parse_iana_response(IANA_COM);
+        assert_eq!(record.domain.as_deref(), Some("COM"));
+        assert_eq!(record.organisation.as_deref(), Some("VeriSign Global Registry Services"));
+        assert_eq!(record.whois.as_deref(), Some("whois.verisign-grs.com"));
+        assert_eq!(record.refer.as_deref(), Some("whois.verisign-grs.com"));
+        assert_eq!(record.status.as_deref(), Some("ACTIVE"));
+        assert_eq!(record.created.as_deref(), Some("1985-01-01"));
+        assert_eq!(record.changed.as_deref(), Some("2023-12-07"));
+        assert_eq!(record.name_servers.len(), 2);
+        assert_eq!(record.name_servers[0].name, "a.gtld-servers.net");
+        assert_eq!(record.name_servers[0].addresses, ["192.5.6.30", "2001:503:a83e:0:0:0:2:30"]);
+        assert_eq!(record.referral(), Some("whois.verisign-grs.com"));
+    }
+
+    #[test]
+    fn iana_referral_falls_back_to_refer() {
+        let record = parse_iana_response("refer: WHOIS.NIC.EXAMPLE\r\n\r\ndomain: EXAMPLE\r\n");
+        assert_eq!(record.whois, None);
+        assert_eq!(record.referral(), Some("whois.nic.example"));
+        assert_eq!(parse_iana_response("% no object found\n").referral(), None);
+    }
+
+    #[test]
+    fn iana_record_serializes_without_missing_fields() {
+        let json = serde_json::to_value(parse_iana_response("domain: EXAMPLE\n")).unwrap();
+        assert_eq!(json, serde_json::json!({ "domain": "EXAMPLE", "name_servers": [] }));
+    }
+}
@@ -0,0 +1,171 @@
+//! Historical resolutions from a passive DNS source, shown by `dns
+//! --passive-dns`. No vendor is built in: the source is any HTTP endpoint
+//! returning JSON, configured with `PASSIVE_DNS_URL` and `PASSIVE_DNS_KEY`.
+
+use anyhow::Result;
+use chrono::DateTime;
+use colored::*;
+use serde_json::Value;
+
+use crate::{diag, normalize_target, rdap};
+
+const URL_VAR: &str = "PASSIVE_DNS_URL";
+const KEY_VAR: &str = "PASSIVE_DNS_KEY";
+const KEY_HEADER_VAR: &str = "PASSIVE_DNS_KEY_HEADER";
+
+/// The endpoint, with `{query}` where the domain or address goes,
+/// and how to authenticate to it.
+struct Source {
+    url: String,
+    /// `(header, value)`: `Authorization: Bearer <key>` unless
+    /// `PASSIVE_DNS_KEY_HEADER` names another header for the bare key
+    auth: Option<(String, String)>,
+}
+
+fn source() -> Result<Source> {
+    let url = std::env::var(URL_VAR).ok().filter(|url| !url.trim().is_empty()).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Passive DNS needs an external source: set {} to an endpoint returning JSON, with {{query}} \
+             where the domain or address goes (and {} if it needs a key)",
+            URL_VAR,
+            KEY_VAR
+        )
+    })?;
+    if !url.contains("{query}") {
+        return Err(anyhow::anyhow!("{} must contain {{query}}, e.g. https://pdns.example/api/{{query}}", URL_VAR));
+    }
+    let auth = std::env::var(KEY_VAR).ok().filter(|key| !key.is_empty()).map(|key| {
+        match std::env::var(KEY_HEADER_VAR).ok().filter(|header| !header.is_empty()) {
+            Some(header) => (header, key),
+            None => ("Authorization".to_string(), format!("Bearer {}", key)),
+        }
+    });
+    Ok(Source { url, auth })
+}
+
+/// One resolution seen by the source.
+struct PassiveRecord {
+    name: String,
+    record_type: String,
+    data: String,
+    first_seen: Option<String>,
+    last_seen: Option<String>,
+    count: Option<u64>,
+}
+
+/// Prints the resolutions the passive DNS source has seen for `target`, a
+/// domain or an IP address, most recently seen first.
+pub async fn perform_passive_dns(target: &str, dry_run: bool) -> Result<()> {
+    let query = normalize_target(target);
+    let source = source()?;
+    let url = source.url.replace("{query}", &query);
+
+    if dry_run {
+        println!("{}", "Passive DNS Plan:".green().bold());
+        println!("{}", "-".repeat(50));
+        println!("Target: {}", query);
+        println!("Source: {}", url.blue());
+        if let Some((header, _)) = &source.auth {
+            println!("Key: sent in {}", header);
+        }
+        return Ok(());
+    }
+
+    diag::verbose(&format!("Fetching {}", url));
+    let mut request = rdap::create_client(false)?.get(&url).header(reqwest::header::ACCEPT, "application/json");
+    if let Some((header, value)) = &source.auth {
+        request = request.header(header.as_str(), value.as_str());
+    }
+    let response = request.send().await?;
+    // Several sources answer 404 for a name they have never seen
+    let status = response.status();
+    let mut records = match status {
+        reqwest::StatusCode::NOT_FOUND => Vec::new(),
+        status if status.is_success() => parse_records(&response.text().await?)?,
+        status => return Err(anyhow::anyhow!("Passive DNS source returned {}", status)),
+    };
+    records.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+
+    if !diag::is_quiet() {
+        println!("{}", "Passive DNS:".green().bold());
+        println!("{}", "-".repeat(50));
+        println!("Source: {}", url.blue());
+        println!(
+            "{:<20} {:<20} {:<6} {:<7} {:<30} Data",
+            "First seen", "Last seen", "Type", "Count", "Name"
+        );
+    }
+    if records.is_empty() {
+        diag::notice(&format!("The source has no resolutions for {}", query));
+    }
+    for record in &records {
+        println!(
+            "{:<20} {:<20} {:<6} {:<7} {:<30} {}",
+            record.first_seen.as_deref().unwrap_or("-"),
+            record.last_seen.as_deref().unwrap_or("-"),
+            record.record_type,
+            record.count.map_or("-".to_string(), |count| count.to_string()),
+            record.name,
+            record.data
+        );
+    }
+    Ok(())
+}
+
+/// Reads the records from a JSON array, from an object holding one under
+/// `records`, `results` or `data`, or from one JSON object per line as in
+/// the passive DNS Common Output Format.
+fn parse_records(body: &str) -> Result<Vec<PassiveRecord>> {
+    let items = match serde_json::from_str::<Value>(body) {
+        Ok(Value::Array(items)) => items,
+        Ok(Value::Object(mut object)) => match ["records", "results", "data"]
+            .iter()
+            .find_map(|key| object.remove(*key).filter(Value::is_array))
+        {
+            Some(Value::Array(items)) => items,
+            _ => vec![Value::Object(object)],
+        },
+        Ok(_) => return Err(anyhow::anyhow!("Passive DNS source did not return records")),
+        Err(_) => body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .map_err(|e| anyhow::anyhow!("Passive DNS source did not return JSON: {}", e))?,
+    };
+    Ok(items.iter().filter_map(parse_record).collect())
+}
+
+/// Accepts the Common Output Format names (`rrname`, `rrtype`, `rdata`,
+/// `time_first`, `time_last`, `count`) and the usual alternatives. Items
+/// without data are skipped.
+fn parse_record(item: &Value) -> Option<PassiveRecord> {
+    let field = |names: &[&str]| names.iter().find_map(|name| item.get(*name)).filter(|value| !value.is_null());
+    let text = |value: &Value| match value {
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().map_or(value.to_string(), str::to_string))
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    };
+
+    let data = field(&["rdata", "value", "data", "answer"]).map(text)?;
+    Some(PassiveRecord {
+        name: field(&["rrname", "name", "query", "domain"]).map(text).unwrap_or_default(),
+        record_type: field(&["rrtype", "type", "record_type"]).map(text).unwrap_or_else(|| "-".to_string()),
+        data,
+        first_seen: field(&["time_first", "first_seen", "first"]).map(timestamp),
+        last_seen: field(&["time_last", "last_seen", "last"]).map(timestamp),
+        count: field(&["count"]).and_then(Value::as_u64),
+    })
+}
+
+/// Epoch seconds as UTC dates; strings are kept as the source wrote them.
+fn timestamp(value: &Value) -> String {
+    match value.as_i64().and_then(|secs| DateTime::from_timestamp(secs, 0)) {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => value.as_str().map_or(value.to_string(), str::to_string),
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{NaiveDate, Utc};
+use colored::*;
+
+use crate::{diag, idn, parse};
+
+const BAR_WIDTH: usize = 20;
+
+/// Prints the highlights of a WHOIS response as an aligned summary instead
+/// of the raw registry text.
+pub fn print_pretty_whois(target: &str, server: &str, response: &str) {
+    let fields = parse::parse_whois_fields(response);
+
+    println!("{}", "WHOIS Summary:".green().bold());
+    println!("{}", "-".repeat(50));
+    print_row("Domain", target);
+    print_row("Server", &server.blue().to_string());
+
+    if let Some(registrar) = parse::registrar(&fields) {
+        print_row("Registrar", registrar);
+    }
+    if let Some(created) = parse::creation_date(&fields) {
+        print_row("Created", created);
+    }
+    if let Some(expires) = parse::expiry_date(&fields) {
+        print_row("Expires", &format_expiry(&fields, expires));
+    }
+    for status in parse::statuses(&fields) {
+        match parse::epp_status(status) {
+            (code, Some(description)) => print_row("Status", &format!("{} ({})", code, description.dimmed())),
+            (code, None) => print_row("Status", code),
+        }
+    }
+    for ns in parse::name_servers(&fields) {
+        print_row("Name Server", &ns);
+    }
+}
+
+/// Prints the highlights on a single line, such as
+/// `example.com | Example Registrar | expires 2026-04-01 | clientTransferProhibited | 4 NS`,
+/// for scanning many domains. Missing fields are shown as `-`.
+pub fn print_oneline_whois(target: &str, response: &str) {
+    let fields = parse::parse_whois_fields(response);
+
+    let expires = parse::expiry_date(&fields).map(|raw| {
+        let date = parse::parse_date(raw).map(|date| date.to_string());
+        format!("expires {}", date.as_deref().unwrap_or(raw))
+    });
+    let statuses: Vec<&str> = parse::statuses(&fields)
+        .into_iter()
+        .map(|status| parse::epp_status(status).0)
+        .collect();
+    let name_servers = parse::name_servers(&fields).len();
+
+    let columns = [
+        Some(target.to_string()),
+        parse::registrar(&fields).map(str::to_string),
+        expires,
+        (!statuses.is_empty()).then(|| statuses.join(",")),
+        (name_servers > 0).then(|| format!("{} NS", name_servers)),
+    ];
+    let columns: Vec<String> = columns
+        .into_iter()
+        .map(|column| column.unwrap_or_else(|| "-".to_string()))
+        .collect();
+    println!("{}", idn::display(&columns.join(" | ")));
+}
+
+/// Prints `rel: href` pairs for `--show-links`, one per line.
+pub fn print_links(links: &[(String, String)]) {
+    if links.is_empty() {
+        diag::notice("No links in the response");
+        return;
+    }
+    if !diag::is_quiet() {
+        println!();
+        println!("{}", "Links:".green().bold());
+        println!("{}", "-".repeat(50));
+    }
+    for (rel, href) in links {
+        println!("{}: {}", rel.bold(), href);
+    }
+}
+
+pub fn print_row(label: &str, value: &str) {
+    println!("{:<13}{}", format!("{}:", label).bold(), idn::display(value));
+}
+
+/// Appends the remaining registration period to the expiry date, as a bar
+/// when color is enabled and as plain text otherwise.
+fn format_expiry(fields: &[(String, String)], raw: &str) -> String {
+    let Some(expires) = parse::parse_date(raw) else {
+        return raw.to_string();
+    };
+    let today = Utc::now().date_naive();
+    let days_left = (expires - today).num_days();
+
+    let created = parse::creation_date(fields).and_then(parse::parse_date);
+    let bar = created
+        .filter(|_| colored::control::SHOULD_COLORIZE.should_colorize())
+        .and_then(|created| remaining_bar(created, expires, today));
+
+    match bar {
+        Some(bar) => format!("{} {} {} days left", raw, bar, days_left),
+        None => format!("{} ({} days left)", raw, days_left),
+    }
+}
+
+/// Draws how much of the registration period between `created` and
+/// `expires` is left, green while plenty remains and red close to expiry.
+fn remaining_bar(created: NaiveDate, expires: NaiveDate, today: NaiveDate) -> Option<ColoredString> {
+    let total = (expires - created).num_days();
+    if total <= 0 {
+        return None;
+    }
+    let left = (expires - today).num_days().clamp(0, total);
+    let fraction = left as f64 / total as f64;
+    let filled = match (fraction * BAR_WIDTH as f64).round() as usize {
+        0 if left > 0 => 1,
+        filled => filled,
+    };
+
+    let bar = format!("{}{}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled));
+    Some(if fraction > 0.5 {
+        bar.green()
+    } else if fraction > 0.2 {
+        bar.yellow()
+    } else {
+        bar.red()
+    })
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Token bucket shared by the concurrent lookups of a batch. It caps the
+/// number of queries started per second independently of how many are in
+/// flight.
+pub struct RateLimiter {
+    rate: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `rate` queries per second. The bucket holds
+    /// a single token so queries are spread evenly instead of bursting.
+    pub fn new(rate: f64) -> Self {
+        RateLimiter {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: 1.0,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a query may be sent.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.updated).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.rate).min(1.0);
+                state.updated = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.tokens) / self.rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn concurrent_acquires_follow_the_configured_rate() {
+        let limiter = Arc::new(RateLimiter::new(20.0));
+        let start = Instant::now();
+        // The first token is there at once, the next ten take 50ms each
+        let tasks: Vec<_> = (0..11)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move { limiter.acquire().await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(450), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(900), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn the_first_query_does_not_wait() {
+        let limiter = RateLimiter::new(0.5);
+        let start = Instant::now();
+        limiter.acquire().await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use colored::*;
+use serde_json::Value;
+use std::sync::RwLock;
+
+use crate::whois::{classify_target, TargetKind};
+use crate::{diag, normalize_target, pretty};
+
+/// Redirects each query to the authoritative RDAP server for the object.
+const RDAP_BOOTSTRAP: &str = "https://rdap.org";
+
+/// Builds the RDAP URL for a target, e.g. `https://rdap.org/domain/example.com`.
+fn rdap_url(target: &str) -> String {
+    let target = normalize_target(target);
+    let path = match classify_target(&target) {
+        TargetKind::Domain => format!("domain/{}", target),
+        TargetKind::Ip | TargetKind::Network => format!("ip/{}", target),
+        TargetKind::Asn => format!("autnum/{}", &target[2..]),
+    };
+    format!("{}/{}", RDAP_BOOTSTRAP, path)
+}
+
+/// Sent as `User-Agent` unless `--user-agent` replaces it.
+const DEFAULT_USER_AGENT: &str = concat!("whois-dns/", env!("CARGO_PKG_VERSION"));
+
+static USER_AGENT: RwLock<Option<String>> = RwLock::new(None);
+
+/// Records the global `--user-agent` for every HTTP request of this command.
+pub fn init(user_agent: Option<String>) {
+    *USER_AGENT.write().unwrap() = user_agent;
+}
+
+/// Rejects a `--user-agent` that cannot be sent as a header, such as one
+/// with a newline.
+pub fn parse_user_agent(value: &str) -> Result<String, String> {
+    reqwest::header::HeaderValue::from_str(value)
+        .map(|_| value.to_string())
+        .map_err(|_| "must be printable ASCII without line breaks".to_string())
+}
+
+/// Builds the HTTPS client used for RDAP and the public suffix list
+/// download. It identifies itself with `whois-dns/<version>` or the
+/// `--user-agent`; some RDAP servers rate-limit unknown clients harder.
+/// With `insecure` certificates are not checked, for testing against
+/// servers with self-signed certificates or behind an intercepting proxy.
+pub fn create_client(insecure: bool) -> Result<reqwest::Client> {
+    let user_agent = USER_AGENT.read().unwrap().clone();
+    let user_agent = user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+    diag::verbose(&format!("User-Agent: {}", user_agent));
+    // gzip and deflate bodies are requested via Accept-Encoding and decoded
+    // before the JSON is parsed; many RDAP servers compress large responses.
+    let builder = reqwest::Client::builder().gzip(true).deflate(true).user_agent(user_agent);
+    if insecure {
+        // Not silenced by --quiet: the answer can no longer be trusted
+        eprintln!(
+            "{} TLS certificate verification is disabled (--insecure); responses may come from anyone on the path",
+            "WARNING:".red().bold()
+        );
+    }
+    Ok(builder.danger_accept_invalid_certs(insecure).build()?)
+}
+
+pub async fn fetch_rdap(target: &str, insecure: bool) -> Result<Value> {
+    fetch_json(&rdap_url(target), insecure).await
+}
+
+async fn fetch_json(url: &str, insecure: bool) -> Result<Value> {
+    diag::verbose(&format!("Fetching {}", url));
+
+    let response = create_client(insecure)?
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/rdap+json, application/json")
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("RDAP lookup failed: {} returned {}", url, status));
+    }
+
+    Ok(response.json().await?)
+}
+
+pub async fn perform_rdap(target: &str, insecure: bool, show_links: bool) -> Result<()> {
+    let record = fetch_rdap(target, insecure).await?;
+
+    if !diag::is_quiet() {
+        println!("{}", "RDAP Information:".green().bold());
+        println!("{}", "-".repeat(50));
+    }
+    println!("{}", serde_json::to_string_pretty(&record)?);
+    if show_links {
+        pretty::print_links(&links(&record));
+    }
+    Ok(())
+}
+
+/// Collects the `links` of the object and of everything nested in it, such
+/// as the registrar entity, as `(rel, href)` in document order. Repeated
+/// pairs are listed once.
+fn links(record: &Value) -> Vec<(String, String)> {
+    fn walk(value: &Value, links: &mut Vec<(String, String)>) {
+        match value {
+            Value::Object(object) => {
+                for link in object.get("links").and_then(Value::as_array).into_iter().flatten() {
+                    let Some(href) = link.get("href").and_then(Value::as_str) else {
+                        continue;
+                    };
+                    let rel = link.get("rel").and_then(Value::as_str).unwrap_or("related");
+                    let link = (rel.to_string(), href.to_string());
+                    if !links.contains(&link) {
+                        links.push(link);
+                    }
+                }
+                object
+                    .iter()
+                    .filter(|(key, _)| *key != "links")
+                    .for_each(|(_, nested)| walk(nested, links));
+            }
+            Value::Array(items) => items.iter().for_each(|item| walk(item, links)),
+            _ => {}
+        }
+    }
+
+    let mut links = Vec::new();
+    walk(record, &mut links);
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const DOMAIN: &str = r#"{"objectClassName":"domain","ldhName":"EXAMPLE.COM","links":[{"rel":"self","href":"https://rdap.example/domain/EXAMPLE.COM"}]}"#;
+
+    /// Serves `body` once with the given `Content-Encoding` and returns the
+    /// URL together with the request headers it received.
+    async fn serve(encoding: &'static str, body: Vec<u8>) -> (String, tokio::sync::oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/domain/example.com", listener.local_addr().unwrap());
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            let _ = sender.send(String::from_utf8_lossy(&request[..read]).to_lowercase());
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/rdap+json\r\nContent-Encoding: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                encoding,
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(&body).await.unwrap();
+        });
+        (url, receiver)
+    }
+
+    #[tokio::test]
+    async fn gzip_responses_are_decoded_before_parsing() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(DOMAIN.as_bytes()).unwrap();
+        let (url, request) = serve("gzip", encoder.finish().unwrap()).await;
+
+        let record = fetch_json(&url, false).await.unwrap();
+        assert_eq!(record["ldhName"], "EXAMPLE.COM");
+        let request = request.await.unwrap();
+        let accept = request.lines().find(|line| line.starts_with("accept-encoding:")).unwrap();
+        assert!(accept.contains("gzip") && accept.contains("deflate"), "{}", accept);
+    }
+
+    #[tokio::test]
+    async fn deflate_responses_are_decoded_before_parsing() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(DOMAIN.as_bytes()).unwrap();
+        let (url, _) = serve("deflate", encoder.finish().unwrap()).await;
+
+        let record = fetch_json(&url, false).await.unwrap();
+        assert_eq!(links(&record), [("self".to_string(), "https://rdap.example/domain/EXAMPLE.COM".to_string())]);
+    }
+}
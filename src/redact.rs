@@ -0,0 +1,77 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+const REDACTED: &str = "[redacted]";
+
+struct Patterns {
+    email: Regex,
+    /// International numbers as registries write them, `+1.4155550100` or
+    /// `+44 20 7946 0000`. The leading `+` keeps dates and IDs out.
+    phone: Regex,
+    /// Keys whose whole value is personal: street addresses, postal codes,
+    /// phone and fax numbers
+    personal_key: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        email: Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap(),
+        phone: Regex::new(r"\+\d{1,3}[ .]?\(?\d[\d .()-]{4,}\d").unwrap(),
+        personal_key: Regex::new(r"(?i)\b(street|address|postal ?code|zip|phone|fax)\b").unwrap(),
+    })
+}
+
+/// Masks email addresses, phone numbers and street addresses in a WHOIS
+/// response so it can be shared. Registrar, dates, name servers and status
+/// lines are left alone. Values of address and phone fields are replaced as
+/// a whole, including the indented continuation lines Nominet uses for
+/// `Registrant's address:`.
+pub fn redact(response: &str) -> String {
+    let patterns = patterns();
+    let mut output = Vec::new();
+    let mut block_indent: Option<usize> = None;
+
+    for line in response.lines() {
+        let indent = line.len() - line.trim_start().len();
+        if let Some(key_indent) = block_indent {
+            if indent > key_indent && !line.trim().is_empty() {
+                output.push(format!("{}{}", &line[..indent], REDACTED));
+                continue;
+            }
+            block_indent = None;
+        }
+
+        let redacted = match line.split_once(':') {
+            Some((key, value)) if is_personal_key(key) => {
+                if value.trim().is_empty() {
+                    block_indent = Some(indent);
+                    line.to_string()
+                } else {
+                    format!("{}: {}", key, REDACTED)
+                }
+            }
+            _ => line.to_string(),
+        };
+
+        let redacted = patterns.email.replace_all(&redacted, REDACTED);
+        let redacted = patterns.phone.replace_all(&redacted, REDACTED);
+        output.push(redacted.into_owned());
+    }
+
+    let mut text = output.join("\n");
+    if response.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// Address and phone keys, but not `IP Address` or the registrar's
+/// `Registrar WHOIS Server`.
+fn is_personal_key(key: &str) -> bool {
+    let key = key.trim();
+    !key.is_empty()
+        && !key.contains("  ")
+        && !key.to_ascii_lowercase().contains("ip address")
+        && patterns().personal_key.is_match(key)
+}
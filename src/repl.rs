@@ -0,0 +1,148 @@
+use anyhow::Result;
+use clap::Parser;
+use colored::*;
+use rustyline::error::ReadlineError;
+use rustyline::{Config, DefaultEditor};
+
+use crate::{concurrency, diag, dnscache, failure, family, history, idn, init_color, logging, pager, rdap, run_command, Cli, Commands};
+
+const PROMPT: &str = "whois-dns> ";
+
+const HELP: &str = "Commands are the same as on the command line, e.g.:
+  whois example.com
+  dns example.com MX
+  set timeout 5      default WHOIS timeout in seconds for later commands
+  history            list recent lookups (history clear deletes them)
+  help               show this help
+  exit               leave the REPL (or press Ctrl-D)";
+
+/// Defaults changed with `set`, applied to later commands that do not
+/// give the option themselves.
+#[derive(Default)]
+struct Settings {
+    timeout: Option<u64>,
+}
+
+impl Settings {
+    fn set(&mut self, args: &[String]) -> Result<()> {
+        match args {
+            [name, value] if name == "timeout" => {
+                let seconds = value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid timeout: {}", value))?;
+                self.timeout = Some(seconds);
+                Ok(())
+            }
+            [name, ..] => Err(anyhow::anyhow!("Unknown setting: {}", name)),
+            [] => Err(anyhow::anyhow!("Usage: set <name> <value>")),
+        }
+    }
+
+    fn apply(&self, command: &mut Commands) {
+        let options = match command {
+            Commands::Whois { options, .. }
+            | Commands::Abuse { options, .. }
+            | Commands::Compare { options, .. }
+            | Commands::Summary { options, .. } => options,
+            Commands::Batch { whois, .. } => whois,
+            _ => return,
+        };
+        if options.timeout.is_none() {
+            options.timeout = self.timeout;
+        }
+    }
+}
+
+/// Lines kept for up-arrow recall across sessions.
+const MAX_LINES: usize = 1000;
+
+/// Reads commands from a prompt until `exit` or Ctrl-D. Each line is parsed
+/// like a command line and dispatched through `run_command`, so the resolver
+/// and caches stay warm between commands. The lines entered are saved for
+/// the next session.
+pub async fn run_repl() -> Result<()> {
+    let mut editor = DefaultEditor::with_config(Config::builder().max_history_size(MAX_LINES)?.build())?;
+    let history_path = history::repl_history_path();
+    if let Some(path) = history_path.as_ref().filter(|path| path.exists()) {
+        if let Err(e) = editor.load_history(path) {
+            diag::verbose(&format!("Could not read {}: {}", path.display(), e));
+        }
+    }
+    let mut settings = Settings::default();
+    let cache_disabled = !dnscache::is_enabled();
+
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+
+        let words = match shell_words::split(line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("{} {}", "Error:".red().bold(), e);
+                continue;
+            }
+        };
+
+        match words[0].as_str() {
+            "exit" | "quit" => break,
+            "help" => {
+                println!("{}", HELP);
+                continue;
+            }
+            "set" => {
+                if let Err(e) = settings.set(&words[1..]) {
+                    eprintln!("{} {}", "Error:".red().bold(), e);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let cli = match Cli::try_parse_from(std::iter::once("whois-dns".to_string()).chain(words)) {
+            Ok(cli) => cli,
+            Err(e) => {
+                e.print()?;
+                continue;
+            }
+        };
+
+        diag::init(cli.quiet, cli.verbose);
+        logging::init(cli.log_format);
+        init_color(cli.no_color);
+        dnscache::init(cache_disabled || cli.no_dns_cache);
+        idn::init(cli.unicode);
+        rdap::init(cli.user_agent);
+        pager::init(cli.no_pager);
+        concurrency::init(cli.concurrency.map(|limit| limit as usize));
+        family::init(cli.ipv4, cli.ipv6);
+        let mut command = cli.command;
+        settings.apply(&mut command);
+
+        match run_command(command, cli.dry_run).await {
+            Err(e) if !failure::is_silent(&e) => eprintln!("{} {}", "Error:".red().bold(), e),
+            _ => {}
+        }
+    }
+
+    if let Some(path) = history_path {
+        let saved = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir).map_err(ReadlineError::from),
+            None => Ok(()),
+        }
+        .and_then(|()| editor.save_history(&path));
+        if let Err(e) = saved {
+            diag::verbose(&format!("Could not save {}: {}", path.display(), e));
+        }
+    }
+    Ok(())
+}
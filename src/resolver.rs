@@ -0,0 +1,139 @@
+//! Where DNS lookups are sent, chosen by the global `--system-resolver` and
+//! `--public-resolver` flags.
+
+use anyhow::Result;
+use colored::*;
+use std::sync::OnceLock;
+use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::proto::rr::RecordType;
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::{concurrency, create_resolver, diag, dnscache};
+
+type Preset = fn() -> ResolverConfig;
+
+/// Public resolvers tried in order by `--resolver-failover` when the
+/// configured one cannot be reached.
+const FAILOVER_RESOLVERS: [(&str, Preset); 3] = [
+    ("Cloudflare", ResolverConfig::cloudflare),
+    ("Google", ResolverConfig::google),
+    ("Quad9", ResolverConfig::quad9),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResolverChoice {
+    /// The system configuration, or the public resolver when it is unusable
+    Auto,
+    /// Only the system configuration
+    System,
+    /// Cloudflare's public resolver, ignoring the system configuration
+    Public,
+}
+
+static CHOICE: OnceLock<ResolverChoice> = OnceLock::new();
+static CONFIG: OnceLock<ResolverConfig> = OnceLock::new();
+
+/// Records the choice for this run. With `--system-resolver` the system
+/// configuration is read right away, so an unusable one fails before any
+/// lookup instead of being replaced by the public resolver.
+pub fn init(choice: ResolverChoice) -> Result<()> {
+    let _ = CHOICE.set(choice);
+    if choice == ResolverChoice::System && CONFIG.get().is_none() {
+        let config = system_config()
+            .map_err(|e| anyhow::anyhow!("{}. Drop --system-resolver, or pass --public-resolver or --server", e))?;
+        report("system configuration (--system-resolver)", &config);
+        let _ = CONFIG.set(config);
+    }
+    Ok(())
+}
+
+/// The name servers from the system configuration (`/etc/resolv.conf` on
+/// Unix).
+fn system_config() -> Result<ResolverConfig> {
+    match trust_dns_resolver::system_conf::read_system_conf() {
+        Ok((config, _)) if !config.name_servers().is_empty() => Ok(config),
+        Ok(_) => Err(anyhow::anyhow!("The system resolver configuration lists no name servers")),
+        Err(e) => Err(anyhow::anyhow!("Could not read the system resolver configuration: {}", e)),
+    }
+}
+
+/// The resolver configuration of this run. Without a choice the system
+/// configuration is used; containers and minimal systems may have none, in
+/// which case the lookups go to Cloudflare and a warning says how to pick
+/// another resolver.
+pub fn config() -> ResolverConfig {
+    CONFIG
+        .get_or_init(|| match CHOICE.get().copied().unwrap_or(ResolverChoice::Auto) {
+            ResolverChoice::Public => {
+                let config = ResolverConfig::cloudflare();
+                report("Cloudflare public resolver (--public-resolver)", &config);
+                config
+            }
+            _ => match system_config() {
+                Ok(config) => {
+                    report("system configuration", &config);
+                    config
+                }
+                Err(e) => {
+                    diag::notice(&format!(
+                        "{} {}. Falling back to Cloudflare (1.1.1.1); pass --server to query another resolver.",
+                        "Warning:".yellow(),
+                        e
+                    ));
+                    let config = ResolverConfig::cloudflare();
+                    report("Cloudflare public resolver (fallback)", &config);
+                    config
+                }
+            },
+        })
+        .clone()
+}
+
+fn report(source: &str, config: &ResolverConfig) {
+    let mut servers: Vec<String> = config.name_servers().iter().map(|ns| ns.socket_addr.to_string()).collect();
+    servers.dedup();
+    diag::verbose(&format!("Resolver configuration: {}, {}", source, servers.join(", ")));
+}
+
+/// Looks up `domain` with the shared resolver and, when it times out or
+/// cannot be reached, with each public resolver in turn until one answers.
+/// An answer that the name does not exist is final and not retried.
+pub async fn lookup_with_failover(domain: &str, record_type: RecordType) -> Result<Lookup> {
+    let configured = config().name_servers().first().map(|ns| ns.socket_addr);
+    let mut resolver_name = "the configured resolver";
+    let mut result = concurrency::limited(create_resolver().lookup(domain, record_type)).await;
+
+    for (name, failover_config) in FAILOVER_RESOLVERS {
+        let error = match &result {
+            Err(e) if is_unreachable(e) => e,
+            _ => break,
+        };
+        let failover_config = failover_config();
+        // --public-resolver already sent the query to Cloudflare
+        if failover_config.name_servers().first().map(|ns| ns.socket_addr) == configured {
+            continue;
+        }
+        diag::notice(&format!("Query to {} failed: {}. Trying {}...", resolver_name, error, name));
+
+        let mut opts = ResolverOpts::default();
+        if !dnscache::is_enabled() {
+            opts.cache_size = 0;
+        }
+        result = concurrency::limited(TokioAsyncResolver::tokio(failover_config, opts).lookup(domain, record_type)).await;
+        resolver_name = name;
+    }
+
+    if result.is_ok() {
+        diag::verbose(&format!("Answered by {}", resolver_name));
+    }
+    Ok(result?)
+}
+
+fn is_unreachable(error: &ResolveError) -> bool {
+    matches!(
+        error.kind(),
+        ResolveErrorKind::Timeout | ResolveErrorKind::NoConnections | ResolveErrorKind::Io(_)
+    )
+}
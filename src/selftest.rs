@@ -0,0 +1,178 @@
+//! `selftest`: one lookup of a registered domain on every server of the
+//! built-in table, to spot servers that moved, went away or changed format.
+
+use anyhow::Result;
+use colored::*;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::failure::Exit;
+use crate::whois::{self, WhoisOptions, WhoisServer};
+use crate::{diag, parse, OutputFormat, Versioned};
+
+/// Domains known to be registered, one per TLD of the table. TLDs added
+/// with `WHOIS_SERVER_<TLD>` are probed with `nic.<tld>`.
+const PROBE_DOMAINS: &[(&str, &str)] = &[
+    ("com", "example.com"),
+    ("net", "example.net"),
+    ("org", "example.org"),
+    ("edu", "educause.edu"),
+    ("it", "nic.it"),
+    ("uk", "nic.uk"),
+    ("ru", "nic.ru"),
+    ("de", "denic.de"),
+    ("nl", "sidn.nl"),
+    ("au", "auda.org.au"),
+    ("br", "registro.br"),
+];
+
+fn probe_domain(tld: &str) -> String {
+    PROBE_DOMAINS
+        .iter()
+        .find(|(probe_tld, _)| *probe_tld == tld)
+        .map_or_else(|| format!("nic.{}", tld), |(_, domain)| domain.to_string())
+}
+
+/// The outcome for one host of one table entry.
+#[derive(Serialize)]
+struct Probe {
+    tld: String,
+    server: String,
+    domain: String,
+    pass: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_ms: Option<u64>,
+    /// Why the server failed, or the number of fields it returned
+    detail: String,
+}
+
+#[derive(Serialize)]
+struct SelftestReport {
+    passed: usize,
+    failed: usize,
+    servers: Vec<Probe>,
+}
+
+/// The table entries to test: all of them, or the one for `only_tld`.
+fn entries(only_tld: Option<&str>) -> Result<Vec<WhoisServer>> {
+    let servers = whois::create_whois_servers();
+    let Some(tld) = only_tld else {
+        return Ok(servers);
+    };
+    let tld = tld.trim_start_matches('.').to_lowercase();
+    let entry: Vec<WhoisServer> = servers.into_iter().filter(|entry| entry.tld == tld).collect();
+    if entry.is_empty() {
+        return Err(anyhow::anyhow!("The server table has no entry for .{}", tld));
+    }
+    Ok(entry)
+}
+
+pub fn print_plan(only_tld: Option<&str>) -> Result<()> {
+    println!("{}", "Selftest Plan:".green().bold());
+    println!("{}", "-".repeat(50));
+    for entry in entries(only_tld)? {
+        let domain = probe_domain(&entry.tld);
+        for host in &entry.hosts {
+            println!("{:<6} {:<28} {}", entry.tld, host, domain);
+        }
+    }
+    Ok(())
+}
+
+/// Queries every host of every entry at once and prints a pass/fail table.
+/// Fails with exit code 1 when any server fails, so it can run from CI.
+pub async fn run_selftest(only_tld: Option<&str>, timeout: u64, format: OutputFormat) -> Result<()> {
+    let options = WhoisOptions { timeout: Some(timeout), ..Default::default() };
+    let mut lookups = Vec::new();
+    for entry in entries(only_tld)? {
+        let domain = probe_domain(&entry.tld);
+        for host in &entry.hosts {
+            let (tld, host, domain) = (entry.tld.clone(), host.clone(), domain.clone());
+            let (prefix, options) = (entry.prefix.clone(), options.clone());
+            lookups.push(tokio::task::spawn_blocking(move || {
+                let start = Instant::now();
+                let result = whois::query_whois_server(&host, &prefix, &domain, &options);
+                probe(tld, host, domain, result, start.elapsed())
+            }));
+        }
+    }
+
+    let mut servers = Vec::new();
+    for lookup in lookups {
+        servers.push(lookup.await?);
+    }
+    let failed = servers.iter().filter(|probe| !probe.pass).count();
+    let report = SelftestReport { passed: servers.len() - failed, failed, servers };
+
+    match format {
+        OutputFormat::Text => print_report(&report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&Versioned::new(&report))?),
+        OutputFormat::Jsonl => {
+            for probe in &report.servers {
+                println!("{}", serde_json::to_string(&Versioned::new(probe))?);
+            }
+        }
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&Versioned::new(&report))?),
+    }
+
+    if report.failed > 0 {
+        let total = report.servers.len();
+        return Err(Exit { code: 1, message: format!("{} of {} servers failed", report.failed, total) }.into());
+    }
+    Ok(())
+}
+
+fn probe(tld: String, server: String, domain: String, result: Result<String>, elapsed: Duration) -> Probe {
+    let (pass, time_ms, detail) = match result {
+        Ok(response) => {
+            let time_ms = Some(elapsed.as_millis() as u64);
+            match plausibility(&response, &domain) {
+                Ok(fields) => (true, time_ms, format!("{} fields", fields)),
+                Err(reason) => (false, time_ms, reason),
+            }
+        }
+        Err(e) => (false, None, e.to_string()),
+    };
+    Probe { tld, server, domain, pass, time_ms, detail }
+}
+
+/// A plausible answer names the domain and parses into at least a few
+/// fields. Anything else is usually a rate-limit notice, a usage message
+/// for a query in the wrong format, or a "no match".
+fn plausibility(response: &str, domain: &str) -> Result<usize, String> {
+    let first_line = || {
+        let line = response.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+        line.chars().take(60).collect::<String>()
+    };
+    if response.trim().is_empty() {
+        return Err("empty response".to_string());
+    }
+    let fields = parse::parse_whois_fields(response).len();
+    if !response.to_lowercase().contains(domain) {
+        return Err(format!("response does not mention {}: {}", domain, first_line()));
+    }
+    if fields < 3 {
+        return Err(format!("only {} parsable fields: {}", fields, first_line()));
+    }
+    Ok(fields)
+}
+
+fn print_report(report: &SelftestReport) {
+    if !diag::is_quiet() {
+        println!("{}", "Selftest:".green().bold());
+        println!("{}", "-".repeat(50));
+        println!("{:<6} {:<28} {:<6} {:>8}  Detail", "TLD", "Server", "Result", "Time");
+    }
+    for probe in &report.servers {
+        let result = if probe.pass { "PASS".green().bold() } else { "FAIL".red().bold() };
+        let time = probe.time_ms.map_or("-".to_string(), |ms| format!("{} ms", ms));
+        println!(
+            "{:<6} {:<28} {:<6} {:>8}  {}",
+            probe.tld, probe.server, result, time, probe.detail
+        );
+    }
+    if !diag::is_quiet() {
+        println!();
+        println!("{} passed, {} failed", report.passed, report.failed);
+    }
+}
@@ -0,0 +1,291 @@
+//! WHOIS server entries kept as data in a TOML file, merged into the
+//! built-in table. The file is `$XDG_CONFIG_HOME/whois-dns/servers.toml`
+//! (or `~/.config/whois-dns/servers.toml`), or the one given with
+//! `--servers-file`:
+//!
+//! ```toml
+//! [[servers]]
+//! tld = "dev"
+//! host = "whois.nic.google"
+//! port = 43
+//! prefix = ""
+//! thick = true
+//! line_ending = "crlf"
+//! ```
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::diag;
+use crate::whois::{self, WhoisServer};
+
+/// What ends the query line. Nearly every server takes CRLF; a few old
+/// ones answer nothing until they see a bare LF.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Crlf,
+    Lf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Lf => "\n",
+        }
+    }
+}
+
+/// One `[[servers]]` table.
+#[derive(Clone, Debug)]
+pub struct FileEntry {
+    pub tld: String,
+    /// `host` with `port` appended when one was given
+    pub server: String,
+    pub prefix: String,
+    pub thick: bool,
+    pub line_ending: LineEnding,
+    /// Line of the `[[servers]]` header, for messages
+    pub line: usize,
+}
+
+struct ServerFile {
+    path: PathBuf,
+    entries: Vec<FileEntry>,
+}
+
+static FILE: OnceLock<Option<ServerFile>> = OnceLock::new();
+
+/// `$XDG_CONFIG_HOME/whois-dns/servers.toml` or
+/// `~/.config/whois-dns/servers.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("whois-dns").join("servers.toml"))
+}
+
+/// Loads and validates the server file given with `--servers-file`, or the
+/// default one if it exists. An invalid file stops the run rather than
+/// silently sending queries to the built-in servers. Later calls, such as
+/// from REPL commands, keep the file already loaded.
+pub fn init(path: Option<PathBuf>) -> Result<()> {
+    if FILE.get().is_some() {
+        return Ok(());
+    }
+    let explicit = path.is_some();
+    let file = match path.or_else(default_path).filter(|path| explicit || path.exists()) {
+        Some(path) => {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))?;
+            let entries = parse(&contents).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            report_shadowed(&path, &entries);
+            Some(ServerFile { path, entries })
+        }
+        None => None,
+    };
+    let _ = FILE.set(file);
+    Ok(())
+}
+
+fn report_shadowed(path: &Path, entries: &[FileEntry]) {
+    let builtin = whois::builtin_whois_servers();
+    for entry in entries {
+        match builtin.iter().find(|server| server.tld == entry.tld) {
+            Some(server) => diag::verbose(&format!(
+                "{} line {}: .{} entry replaces the built-in {}",
+                path.display(),
+                entry.line,
+                entry.tld,
+                server.hosts.join(", ")
+            )),
+            None => diag::verbose(&format!("{} line {}: adds .{}", path.display(), entry.line, entry.tld)),
+        }
+    }
+}
+
+/// The file the entries were read from, if one was loaded.
+pub fn path() -> Option<&'static Path> {
+    FILE.get()?.as_ref().map(|file| file.path.as_path())
+}
+
+pub fn entries() -> &'static [FileEntry] {
+    FILE.get().and_then(Option::as_ref).map_or(&[], |file| file.entries.as_slice())
+}
+
+/// Replaces the table entries for the TLDs in the file and adds the others.
+pub fn apply(servers: &mut Vec<WhoisServer>) {
+    for entry in entries() {
+        let server = WhoisServer::from_file(entry);
+        match servers.iter_mut().find(|server| server.tld == entry.tld) {
+            Some(existing) => *existing = server,
+            None => servers.push(server),
+        }
+    }
+}
+
+/// The line ending the file configures for `server`, CRLF otherwise.
+pub fn line_ending(server: &str) -> LineEnding {
+    entries()
+        .iter()
+        .find(|entry| entry.server.eq_ignore_ascii_case(server))
+        .map_or(LineEnding::Crlf, |entry| entry.line_ending)
+}
+
+/// The file as written. Unknown keys and tables are rejected, so a typo
+/// such as `prefx` is reported instead of ignored.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFile {
+    #[serde(default)]
+    servers: Vec<toml::Spanned<RawEntry>>,
+}
+
+/// One `[[servers]]` table as written; `validate` checks what TOML types
+/// cannot express.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntry {
+    tld: String,
+    host: String,
+    port: Option<u16>,
+    #[serde(default)]
+    prefix: String,
+    #[serde(default = "thick_by_default")]
+    thick: bool,
+    #[serde(default)]
+    line_ending: LineEnding,
+}
+
+fn thick_by_default() -> bool {
+    true
+}
+
+/// Reads the `[[servers]]` tables and checks every entry, reporting the
+/// first problem with its line number.
+fn parse(contents: &str) -> Result<Vec<FileEntry>> {
+    let file: RawFile = toml::from_str(contents).map_err(|e| anyhow::anyhow!("{}", e.to_string().trim_end()))?;
+
+    let mut entries: Vec<FileEntry> = Vec::new();
+    for spanned in file.servers {
+        let line = contents[..spanned.span().start].matches('\n').count() + 1;
+        let entry = validate(line, spanned.into_inner())?;
+        if let Some(earlier) = entries.iter().find(|earlier| earlier.tld == entry.tld) {
+            return Err(anyhow::anyhow!(
+                "line {}: .{} already has an entry at line {}",
+                line,
+                entry.tld,
+                earlier.line
+            ));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn validate(line: usize, raw: RawEntry) -> Result<FileEntry> {
+    let tld = raw.tld.trim().trim_start_matches('.').to_lowercase();
+    if tld.is_empty() || !tld.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+        return Err(anyhow::anyhow!("line {}: invalid tld {:?}", line, raw.tld));
+    }
+    let host = raw.host.trim().to_string();
+    if host.is_empty() || host.contains(char::is_whitespace) || host.contains(',') {
+        return Err(anyhow::anyhow!("line {}: invalid host {:?}", line, raw.host));
+    }
+    let server = match raw.port {
+        Some(0) => return Err(anyhow::anyhow!("line {}: port 0 is not between 1 and 65535", line)),
+        Some(port) => {
+            if whois::host_and_port(&host) != (host.as_str(), whois::WHOIS_PORT) {
+                return Err(anyhow::anyhow!("line {}: host {} already carries a port", line, host));
+            }
+            match host.contains(':') {
+                true => format!("[{}]:{}", host, port),
+                false => format!("{}:{}", host, port),
+            }
+        }
+        None => host,
+    };
+    if raw.prefix.contains(['\r', '\n']) {
+        return Err(anyhow::anyhow!("line {}: prefix must not contain a line break", line));
+    }
+    Ok(FileEntry { tld, server, prefix: raw.prefix, thick: raw.thick, line_ending: raw.line_ending, line })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_entries_with_defaults() {
+        let contents = "# my servers\n[[servers]]\ntld = \".Dev\"\nhost = \"whois.nic.google\"\n\n\
+                        [[servers]]\ntld = \"test\"\nhost = \"127.0.0.1\"\nport = 4343\nprefix = \"# not a comment\"\n\
+                        thick = false\nline_ending = \"lf\"\n";
+        let entries = parse(contents).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].tld, "dev");
+        assert_eq!(entries[0].server, "whois.nic.google");
+        assert_eq!(entries[0].prefix, "");
+        assert!(entries[0].thick);
+        assert_eq!(entries[0].line_ending, LineEnding::Crlf);
+        assert_eq!(entries[0].line, 2);
+
+        assert_eq!(entries[1].server, "127.0.0.1:4343");
+        assert_eq!(entries[1].prefix, "# not a comment");
+        assert!(!entries[1].thick);
+        assert_eq!(entries[1].line_ending, LineEnding::Lf);
+        assert_eq!(entries[1].line, 6);
+    }
+
+    #[test]
+    fn appends_the_port_to_ipv6_hosts_in_brackets() {
+        let entries = parse("[[servers]]\ntld = \"test\"\nhost = \"::1\"\nport = 4343\n").unwrap();
+        assert_eq!(entries[0].server, "[::1]:4343");
+    }
+
+    #[test]
+    fn an_empty_file_has_no_entries() {
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("# nothing yet\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_wrong_types() {
+        let error = parse("[[servers]]\ntld = \"dev\"\nhost = \"a\"\nprefx = \"x\"\n").unwrap_err();
+        assert!(error.to_string().contains("prefx"), "{}", error);
+        assert!(parse("[server]\ntld = \"dev\"\n").is_err());
+        assert!(parse("[[servers]]\ntld = \"dev\"\nhost = \"a\"\nthick = \"yes\"\n").is_err());
+        assert!(parse("[[servers]]\ntld = \"dev\"\nhost = \"a\"\nline_ending = \"cr\"\n").is_err());
+        assert!(parse("[[servers]]\ntld = \"dev\"\nhost = \"a\"\nport = 70000\n").is_err());
+        assert!(parse("[[servers]]\ntld = \"dev\"\n").is_err());
+    }
+
+    #[test]
+    fn reports_invalid_values_with_their_line() {
+        let port_zero = "[[servers]]\ntld = \"dev\"\nhost = \"a\"\n\n[[servers]]\ntld = \"app\"\nhost = \"b\"\nport = 0\n";
+        assert_eq!(parse(port_zero).unwrap_err().to_string(), "line 5: port 0 is not between 1 and 65535");
+
+        let two_ports = "[[servers]]\ntld = \"dev\"\nhost = \"a:4343\"\nport = 43\n";
+        assert_eq!(parse(two_ports).unwrap_err().to_string(), "line 1: host a:4343 already carries a port");
+
+        let bad_tld = "[[servers]]\ntld = \"d v\"\nhost = \"a\"\n";
+        assert!(parse(bad_tld).unwrap_err().to_string().contains("invalid tld"));
+
+        let bad_host = "[[servers]]\ntld = \"dev\"\nhost = \"a, b\"\n";
+        assert!(parse(bad_host).unwrap_err().to_string().contains("invalid host"));
+
+        let multiline_prefix = "[[servers]]\ntld = \"dev\"\nhost = \"a\"\nprefix = \"\"\"\nx\ny\"\"\"\n";
+        assert!(parse(multiline_prefix).unwrap_err().to_string().contains("line break"));
+    }
+
+    #[test]
+    fn rejects_a_second_entry_for_the_same_tld() {
+        let contents = "[[servers]]\ntld = \"dev\"\nhost = \"a\"\n\n[[servers]]\ntld = \".DEV\"\nhost = \"b\"\n";
+        assert_eq!(parse(contents).unwrap_err().to_string(), "line 5: .dev already has an entry at line 1");
+    }
+}
@@ -0,0 +1,152 @@
+//! Batch results written to a SQLite database with `batch --sqlite`, for
+//! querying large inventories with SQL.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use whois_dns_tool::dns::DnsAnswer;
+
+use crate::parse;
+
+/// Every run appends one `domains` row per target, so the database keeps
+/// the history of repeated runs.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS domains (
+    id INTEGER PRIMARY KEY,
+    target TEXT NOT NULL,
+    looked_up_at TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    server TEXT,
+    registrar TEXT,
+    created TEXT,
+    updated TEXT,
+    expires TEXT,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS domains_target ON domains (target);
+CREATE TABLE IF NOT EXISTS dns_records (
+    domain_id INTEGER NOT NULL REFERENCES domains (id),
+    name TEXT NOT NULL,
+    type TEXT NOT NULL,
+    ttl INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS whois_fields (
+    domain_id INTEGER NOT NULL REFERENCES domains (id),
+    key TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+";
+
+/// Results written in one transaction. Committing for every target would
+/// make a large batch wait on the disk for each one.
+const ROWS_PER_TRANSACTION: usize = 500;
+
+pub struct SqliteSink {
+    connection: Connection,
+    /// Targets written since the last commit
+    pending: usize,
+}
+
+impl SqliteSink {
+    /// Opens the database, creating it and its tables on first use.
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("Cannot create the tables in {}", path.display()))?;
+        Ok(SqliteSink { connection, pending: 0 })
+    }
+
+    pub fn record_whois(&mut self, target: &str, server: &str, response: &str) -> Result<()> {
+        let fields = parse::parse_whois_fields(response);
+        let dates = parse::dates(&fields);
+        let id = self.insert_domain(target, Some(server), parse::registrar(&fields), &dates, None)?;
+        let mut insert = self
+            .connection
+            .prepare_cached("INSERT INTO whois_fields (domain_id, key, value) VALUES (?1, ?2, ?3)")?;
+        for (key, value) in &fields {
+            insert.execute(params![id, key, value])?;
+        }
+        drop(insert);
+        self.written()
+    }
+
+    pub fn record_dns(&mut self, target: &str, answers: &[DnsAnswer]) -> Result<()> {
+        let id = self.insert_domain(target, None, None, &parse::Dates::default(), None)?;
+        let mut insert = self
+            .connection
+            .prepare_cached("INSERT INTO dns_records (domain_id, name, type, ttl, data) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        for answer in answers {
+            insert.execute(params![
+                id,
+                answer.name,
+                answer.record_type.to_string(),
+                answer.ttl,
+                answer.data.to_string()
+            ])?;
+        }
+        drop(insert);
+        self.written()
+    }
+
+    pub fn record_failure(&mut self, target: &str, error: &anyhow::Error) -> Result<()> {
+        self.insert_domain(target, None, None, &parse::Dates::default(), Some(&error.to_string()))?;
+        self.written()
+    }
+
+    /// Commits the results written since the last commit.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.pending > 0 {
+            self.connection.execute_batch("COMMIT")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    /// Adds the `domains` row of a target and returns its id. A result
+    /// without an error is a successful lookup.
+    fn insert_domain(
+        &mut self,
+        target: &str,
+        server: Option<&str>,
+        registrar: Option<&str>,
+        dates: &parse::Dates,
+        error: Option<&str>,
+    ) -> Result<i64> {
+        if self.pending == 0 {
+            self.connection.execute_batch("BEGIN")?;
+        }
+        self.connection
+            .prepare_cached(
+                "INSERT INTO domains (target, looked_up_at, success, server, registrar, created, updated, expires, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?
+            .execute(params![
+                target,
+                now(),
+                error.is_none(),
+                server,
+                registrar,
+                dates.created,
+                dates.updated,
+                dates.expires,
+                error
+            ])
+            .with_context(|| format!("Cannot store the result for {}", target))?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn written(&mut self) -> Result<()> {
+        self.pending += 1;
+        if self.pending >= ROWS_PER_TRANSACTION {
+            self.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// The time of a lookup in RFC 3339, which sorts and compares as text.
+fn now() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
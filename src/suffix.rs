@@ -0,0 +1,206 @@
+use anyhow::Result;
+use publicsuffix::{IcannList, Psl};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::{diag, rdap};
+
+/// Snapshot of https://publicsuffix.org/list/public_suffix_list.dat. Only the
+/// ICANN section is used, private suffixes such as `github.io` are not
+/// delegated by a registry and have no WHOIS server of their own.
+const EMBEDDED_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+pub const PSL_URL: &str = "https://publicsuffix.org/list/public_suffix_list.dat";
+
+/// Marks the start of the ICANN section, used to tell a suffix list from an
+/// error page.
+const ICANN_MARKER: &str = "===BEGIN ICANN DOMAINS===";
+
+static LIST_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Sets the list given with `--psl`. Must be called before the first lookup;
+/// later calls, such as from REPL commands, keep the list already loaded.
+pub fn init(path: Option<PathBuf>) {
+    let _ = LIST_PATH.set(path);
+}
+
+/// Where `psl update` stores the downloaded list:
+/// `$XDG_CACHE_HOME/whois-dns/` or `~/.cache/whois-dns/`.
+pub fn cache_path() -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("whois-dns").join("public_suffix_list.dat"))
+}
+
+/// Loads the list from `--psl`, then from the cache written by `psl update`,
+/// and otherwise uses the embedded copy. A list that cannot be read or parsed
+/// is skipped with a notice.
+fn list() -> &'static IcannList {
+    static LIST: OnceLock<IcannList> = OnceLock::new();
+    LIST.get_or_init(|| {
+        let explicit = LIST_PATH.get().cloned().flatten();
+        let from_file = explicit.is_some();
+        if let Some(path) = explicit.or_else(cache_path).filter(|path| from_file || path.exists()) {
+            match std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| parse_list(&contents))
+            {
+                Ok(list) => {
+                    diag::verbose(&format!("Using public suffix list {}", path.display()));
+                    return list;
+                }
+                Err(e) => diag::notice(&format!(
+                    "Could not use public suffix list {}: {}. Using the embedded list",
+                    path.display(),
+                    e
+                )),
+            }
+        }
+        parse_list(EMBEDDED_LIST).expect("embedded public suffix list is valid")
+    })
+}
+
+fn parse_list(contents: &str) -> Result<IcannList> {
+    if !contents.contains(ICANN_MARKER) {
+        return Err(anyhow::anyhow!("not a public suffix list"));
+    }
+    contents
+        .parse()
+        .map_err(|e| anyhow::anyhow!("invalid public suffix list: {:?}", e))
+}
+
+/// Downloads the current list from publicsuffix.org into the cache. The
+/// file is only replaced once the download is complete and parses, so a
+/// failed update keeps the previous list in use.
+pub async fn update() -> Result<()> {
+    let path = cache_path().ok_or_else(|| anyhow::anyhow!("No cache directory (HOME is not set)"))?;
+    diag::verbose(&format!("Fetching {}", PSL_URL));
+
+    let contents = download()
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not update the public suffix list: {}. The current list stays in use", e))?;
+
+    save_list(&path, &contents)?;
+
+    if !diag::is_quiet() {
+        println!("Saved {} bytes to {}", contents.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Writes the list to a file of its own next to `path` and renames it into
+/// place, so neither a reader nor a concurrent update sees a half-written
+/// list.
+fn save_list(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension(format!("dat.{}.partial", std::process::id()));
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&partial);
+    })?;
+    Ok(())
+}
+
+async fn download() -> Result<String> {
+    let response = rdap::create_client(false)?.get(PSL_URL).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("{} returned {}", PSL_URL, status));
+    }
+    let contents = response.text().await?;
+    parse_list(&contents)?;
+    Ok(contents)
+}
+
+/// Returns the public suffix of `domain`, e.g. `co.uk` for
+/// `www.example.co.uk`. `domain` is expected to be normalized.
+pub fn public_suffix(domain: &str) -> Option<String> {
+    let suffix = list().suffix(domain.as_bytes())?;
+    std::str::from_utf8(suffix.as_bytes()).ok().map(str::to_string)
+}
+
+/// Whether `domain` ends in a suffix from the list rather than in an
+/// unknown last label such as `log` or `txt`.
+pub fn has_known_suffix(domain: &str) -> bool {
+    list().suffix(domain.as_bytes()).is_some_and(|suffix| suffix.is_known())
+}
+
+/// Returns the registrable domain of `domain`, e.g. `example.co.uk` for
+/// `www.example.co.uk`, or `None` when `domain` is itself a public suffix.
+pub fn registrable_domain(domain: &str) -> Option<String> {
+    let registrable = list().domain(domain.as_bytes())?;
+    std::str::from_utf8(registrable.as_bytes()).ok().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::whois;
+
+    fn suffix_of(list: &IcannList, domain: &str) -> String {
+        String::from_utf8(list.suffix(domain.as_bytes()).unwrap().as_bytes().to_vec()).unwrap()
+    }
+
+    fn domain_of(list: &IcannList, domain: &str) -> String {
+        String::from_utf8(list.domain(domain.as_bytes()).unwrap().as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn embedded_list_knows_multi_label_suffixes() {
+        let list = parse_list(EMBEDDED_LIST).unwrap();
+        for (domain, suffix, registrable) in [
+            ("www.example.co.uk", "co.uk", "example.co.uk"),
+            ("www.example.com.au", "com.au", "example.com.au"),
+            ("www.example.com.br", "com.br", "example.com.br"),
+            ("www.example.com", "com", "example.com"),
+        ] {
+            assert_eq!(suffix_of(&list, domain), suffix, "{}", domain);
+            assert_eq!(domain_of(&list, domain), registrable, "{}", domain);
+        }
+    }
+
+    #[test]
+    fn a_public_suffix_has_no_registrable_domain() {
+        let list = parse_list(EMBEDDED_LIST).unwrap();
+        assert!(list.domain(b"com.au").is_none());
+    }
+
+    #[test]
+    fn server_is_selected_by_the_longest_suffix_with_an_entry() {
+        let servers = whois::builtin_whois_servers();
+        for (domain, host) in [
+            ("example.co.uk", "whois.nic.uk"),
+            ("example.com.au", "whois.auda.org.au"),
+            ("example.com.br", "whois.registro.br"),
+        ] {
+            let entry = whois::select_whois_server(&servers, domain).unwrap();
+            assert_eq!(entry.hosts[0], host, "{}", domain);
+        }
+    }
+
+    #[test]
+    fn lists_without_the_icann_section_are_rejected() {
+        assert!(parse_list("<html>Not found</html>").is_err());
+    }
+
+    #[test]
+    fn saved_lists_leave_no_partial_file() {
+        let dir = std::env::temp_dir().join(format!("whois-dns-psl-{}", std::process::id()));
+        let path = dir.join("cache").join("public_suffix_list.dat");
+        let partial = path.with_extension(format!("dat.{}.partial", std::process::id()));
+        save_list(&path, EMBEDDED_LIST).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), EMBEDDED_LIST);
+        assert!(!partial.exists());
+
+        // A directory in the way makes the rename fail
+        let blocked = dir.join("blocked.dat");
+        std::fs::create_dir_all(blocked.join("inside")).unwrap();
+        assert!(save_list(&blocked, EMBEDDED_LIST).is_err());
+        assert!(!blocked.with_extension(format!("dat.{}.partial", std::process::id())).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
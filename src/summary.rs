@@ -0,0 +1,232 @@
+use anyhow::Result;
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::task::JoinSet;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::proto::rr::RecordType;
+use whois_dns_tool::dns::DnsAnswer;
+
+use crate::checks::{self, Issue};
+use crate::pretty::print_row;
+use crate::whois::{lookup_whois_async, plan_whois, WhoisOptions};
+use crate::{concurrency, create_resolver, diag, family, normalize_target, parse, print_dns_plan, print_whois_plan, OutputFormat, Versioned};
+
+/// The record types gathered for a summary, in the order they are printed.
+const SUMMARY_TYPES: [RecordType; 6] = [
+    RecordType::A,
+    RecordType::AAAA,
+    RecordType::MX,
+    RecordType::NS,
+    RecordType::TXT,
+    RecordType::SOA,
+];
+
+/// The summary types without the address records `-4` or `-6` leave out.
+fn summary_types() -> impl Iterator<Item = RecordType> {
+    let family = family::get();
+    SUMMARY_TYPES.into_iter().filter(move |record_type| family.allows_record_type(*record_type))
+}
+
+/// Everything known about a domain, as printed by `summary --format json`.
+#[derive(Serialize)]
+struct DomainSummary {
+    domain: String,
+    /// One section per record type, keyed by its name
+    dns: BTreeMap<String, DnsSection>,
+    whois: WhoisSection,
+    /// The issues found by `--checks`, left out without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checks: Option<Vec<Issue>>,
+}
+
+#[derive(Serialize)]
+struct DnsSection {
+    records: Vec<DnsAnswer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The WHOIS highlights also shown by `whois --pretty`.
+#[derive(Default, Serialize)]
+struct WhoisSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registrar: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<String>,
+    dates: parse::Dates,
+    status: Vec<parse::StatusCode>,
+    name_servers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl WhoisSection {
+    fn new(result: Result<(String, String)>) -> Self {
+        let (server, response) = match result {
+            Ok(answer) => answer,
+            Err(e) => {
+                return WhoisSection {
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                }
+            }
+        };
+        let fields = parse::parse_whois_fields(&response);
+        WhoisSection {
+            registrar: parse::registrar(&fields).map(str::to_string),
+            created: parse::creation_date(&fields).map(str::to_string),
+            expires: parse::expiry_date(&fields).map(str::to_string),
+            dates: parse::dates(&fields),
+            status: parse::status_codes(&fields),
+            name_servers: parse::name_servers(&fields),
+            server: Some(server),
+            error: None,
+        }
+    }
+}
+
+/// Gathers the DNS records and the WHOIS highlights of `target` at the same
+/// time and prints them together, followed by the issues `checks` finds.
+pub async fn perform_summary(
+    target: &str,
+    format: OutputFormat,
+    run_checks: bool,
+    options: &WhoisOptions,
+    dry_run: bool,
+) -> Result<()> {
+    let domain = normalize_target(target);
+
+    if dry_run {
+        for record_type in summary_types() {
+            print_dns_plan(&domain, record_type);
+        }
+        if run_checks {
+            print_dns_plan(&format!("_dmarc.{}", domain), RecordType::TXT);
+            print_dns_plan(&domain, RecordType::CNAME);
+        }
+        print_whois_plan(&plan_whois(&domain, options), options);
+        return Ok(());
+    }
+
+    let whois_task = {
+        let domain = domain.clone();
+        let options = options.clone();
+        tokio::spawn(async move { lookup_whois_async(&domain, &options).await })
+    };
+
+    let mut lookups = JoinSet::new();
+    for record_type in summary_types() {
+        let domain = domain.clone();
+        lookups.spawn(async move { (record_type, lookup_section(&domain, record_type).await) });
+    }
+
+    // Lookups finish in any order, so the sections are keyed by type and
+    // the records sorted to keep the output stable between runs
+    let mut dns = BTreeMap::new();
+    while let Some(joined) = lookups.join_next().await {
+        let (record_type, section) = joined?;
+        dns.insert(record_type.to_string(), section);
+    }
+
+    let whois = WhoisSection::new(whois_task.await?);
+    let checks = if run_checks {
+        let records = |record_type: RecordType| {
+            dns.get(&record_type.to_string())
+                .filter(|section| section.error.is_none())
+                .map(|section| section.records.as_slice())
+        };
+        let (txt, mx, ns) = (records(RecordType::TXT), records(RecordType::MX), records(RecordType::NS));
+        Some(checks::run_checks(&domain, txt, mx, ns, &whois.name_servers).await)
+    } else {
+        None
+    };
+    let summary = DomainSummary { domain, dns, whois, checks };
+
+    match format {
+        OutputFormat::Text => {
+            print_summary(&summary);
+            if let Some(issues) = &summary.checks {
+                checks::print_issues(issues);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&Versioned::new(&summary))?),
+        OutputFormat::Jsonl => println!("{}", serde_json::to_string(&Versioned::new(&summary))?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&Versioned::new(&summary))?),
+    }
+    Ok(())
+}
+
+/// Looks up one record type. A name without records of that type is an
+/// empty section rather than an error.
+async fn lookup_section(domain: &str, record_type: RecordType) -> DnsSection {
+    match concurrency::limited(create_resolver().lookup(domain, record_type)).await {
+        Ok(lookup) => {
+            let mut records: Vec<DnsAnswer> = DnsAnswer::from_lookup(&lookup)
+                .into_iter()
+                .filter(|answer| answer.record_type == record_type)
+                .collect();
+            records.sort_by_key(|answer| answer.data.to_string());
+            DnsSection { records, error: None }
+        }
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => DnsSection {
+            records: Vec::new(),
+            error: None,
+        },
+        Err(e) => DnsSection {
+            records: Vec::new(),
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+fn print_summary(summary: &DomainSummary) {
+    if !diag::is_quiet() {
+        println!("{}", "Domain Summary:".green().bold());
+        println!("{}", "-".repeat(50));
+        print_row("Domain", &summary.domain);
+    }
+
+    for record_type in SUMMARY_TYPES {
+        let label = record_type.to_string();
+        let Some(section) = summary.dns.get(&label) else {
+            continue;
+        };
+        if let Some(error) = &section.error {
+            print_row(&label, &error.red().to_string());
+        }
+        for answer in &section.records {
+            print_row(&label, &answer.data.to_string());
+        }
+    }
+
+    let whois = &summary.whois;
+    if let Some(error) = &whois.error {
+        print_row("WHOIS", &error.red().to_string());
+        return;
+    }
+    let optional_rows = [
+        ("Server", &whois.server),
+        ("Registrar", &whois.registrar),
+        ("Created", &whois.created),
+        ("Expires", &whois.expires),
+    ];
+    for (label, value) in optional_rows {
+        if let Some(value) = value {
+            print_row(label, value);
+        }
+    }
+    for status in &whois.status {
+        match status.description {
+            Some(description) => print_row("Status", &format!("{} ({})", status.code, description.dimmed())),
+            None => print_row("Status", &status.code),
+        }
+    }
+    for ns in &whois.name_servers {
+        print_row("Name Server", ns);
+    }
+}
@@ -0,0 +1,1322 @@
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::{concurrency, diag, dnscache, encoding, family, get_tld, normalize_target, parse, redact, serverfile, suffix};
+
+const VERISIGN_WHOIS: &str = "whois.verisign-grs.com";
+pub const IANA_WHOIS: &str = "whois.iana.org";
+pub const WHOIS_PORT: u16 = 43;
+
+/// A registry WHOIS server from the built-in table.
+#[derive(Clone)]
+pub struct WhoisServer {
+    pub tld: String,
+    /// Servers to try in order; later ones are alternates used when the
+    /// earlier ones fail. A host may carry a port, as in `127.0.0.1:4343`.
+    pub hosts: Vec<String>,
+    pub prefix: String,
+    /// Thick registries hold the full record themselves, thin ones only
+    /// point at the registrar's WHOIS server.
+    pub thick: bool,
+    pub origin: Origin,
+}
+
+/// Where a table entry comes from, later ones overriding earlier ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+    Builtin,
+    /// A `[[servers]]` entry of the server file
+    File,
+    /// A `WHOIS_SERVER_<TLD>` variable
+    Env,
+}
+
+impl WhoisServer {
+    fn new(tld: &str, hosts: &[&str], prefix: &str, thick: bool) -> Self {
+        WhoisServer {
+            tld: tld.to_string(),
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            prefix: prefix.to_string(),
+            thick,
+            origin: Origin::Builtin,
+        }
+    }
+
+    pub fn from_file(entry: &serverfile::FileEntry) -> Self {
+        WhoisServer {
+            origin: Origin::File,
+            ..WhoisServer::new(&entry.tld, &[&entry.server], &entry.prefix, entry.thick)
+        }
+    }
+
+    /// Where the entry comes from, for `--dry-run` and `which-server`.
+    pub fn source(&self) -> String {
+        match self.origin {
+            Origin::Builtin => format!("built-in table (.{})", self.tld),
+            Origin::File => match serverfile::path() {
+                Some(path) => format!("server file {} (.{})", path.display(), self.tld),
+                None => format!("server file (.{})", self.tld),
+            },
+            Origin::Env => format!("environment ({})", server_env_var(&self.tld)),
+        }
+    }
+}
+
+/// The built-in table, kept as data so that adding a TLD does not touch
+/// the code.
+const BUILTIN_TABLE: &str = include_str!("../data/servers.json");
+
+/// One entry of `data/servers.json`. Entries are thick with no prefix
+/// unless they say otherwise.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableEntry {
+    tld: String,
+    hosts: Vec<String>,
+    #[serde(default)]
+    prefix: String,
+    #[serde(default = "thick_by_default")]
+    thick: bool,
+}
+
+fn thick_by_default() -> bool {
+    true
+}
+
+fn parse_builtin_table(json: &str) -> Result<Vec<WhoisServer>> {
+    let entries: Vec<TableEntry> = serde_json::from_str(json)?;
+    let mut servers: Vec<WhoisServer> = Vec::new();
+    for entry in entries {
+        if entry.tld.is_empty() || entry.tld != entry.tld.to_lowercase() || entry.tld.starts_with('.') {
+            return Err(anyhow::anyhow!("invalid tld {:?}", entry.tld));
+        }
+        if entry.hosts.is_empty() || entry.hosts.iter().any(|host| host.is_empty()) {
+            return Err(anyhow::anyhow!(".{} entry has no hosts", entry.tld));
+        }
+        if servers.iter().any(|server| server.tld == entry.tld) {
+            return Err(anyhow::anyhow!(".{} is listed twice", entry.tld));
+        }
+        let hosts: Vec<&str> = entry.hosts.iter().map(String::as_str).collect();
+        servers.push(WhoisServer::new(&entry.tld, &hosts, &entry.prefix, entry.thick));
+    }
+    Ok(servers)
+}
+
+fn builtin_table() -> &'static Result<Vec<WhoisServer>> {
+    static TABLE: OnceLock<Result<Vec<WhoisServer>>> = OnceLock::new();
+    TABLE.get_or_init(|| parse_builtin_table(BUILTIN_TABLE))
+}
+
+/// Checks at startup that the embedded table is valid, so a broken data
+/// edit stops the run instead of silently sending every query to IANA.
+pub fn check_builtin_table() -> Result<()> {
+    match builtin_table() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow::anyhow!("The built-in WHOIS server table (data/servers.json) is invalid: {}", e)),
+    }
+}
+
+/// The table compiled into the binary, before the server file and
+/// `WHOIS_SERVER_<TLD>` variables are applied.
+pub fn builtin_whois_servers() -> Vec<WhoisServer> {
+    builtin_table().as_ref().map_or_else(|_| Vec::new(), Vec::clone)
+}
+
+/// The built-in table with the server file merged in and the environment
+/// overrides applied.
+pub fn create_whois_servers() -> Vec<WhoisServer> {
+    let mut servers = builtin_whois_servers();
+    serverfile::apply(&mut servers);
+    apply_env_overrides(&mut servers);
+    servers
+}
+
+const SERVER_ENV_PREFIX: &str = "WHOIS_SERVER_";
+
+/// The variable that overrides the servers for `tld`: `WHOIS_SERVER_COM` for
+/// `com`, `WHOIS_SERVER_CO_UK` for `co.uk`.
+pub fn server_env_var(tld: &str) -> String {
+    format!("{}{}", SERVER_ENV_PREFIX, tld.to_uppercase().replace(['.', '-'], "_"))
+}
+
+/// Replaces the hosts of a table entry with the comma-separated list in its
+/// `WHOIS_SERVER_<TLD>` variable, keeping the prefix and registry model.
+/// Variables for TLDs without an entry add a thick entry with no prefix.
+fn apply_env_overrides(servers: &mut Vec<WhoisServer>) {
+    for (name, value) in std::env::vars_os() {
+        let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else {
+            continue;
+        };
+        let Some(tld) = name.strip_prefix(SERVER_ENV_PREFIX) else {
+            continue;
+        };
+        let tld = tld.to_lowercase().replace('_', ".");
+        let hosts: Vec<&str> = value.split(',').map(str::trim).filter(|host| !host.is_empty()).collect();
+        if tld.is_empty() || hosts.is_empty() {
+            continue;
+        }
+
+        let entry = match servers.iter_mut().find(|entry| entry.tld == tld) {
+            Some(entry) => entry,
+            None => {
+                servers.push(WhoisServer::new(&tld, &[], "", true));
+                servers.last_mut().unwrap()
+            }
+        };
+        entry.hosts = hosts.iter().map(|host| host.to_string()).collect();
+        entry.origin = Origin::Env;
+    }
+}
+
+/// Splits an optional port off a server name, so `127.0.0.1:4343` and
+/// `[::1]:4343` work next to plain host names on port 43.
+pub fn host_and_port(server: &str) -> (&str, u16) {
+    match server.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.starts_with('[') => match port.parse() {
+            Ok(port) => (host.trim_start_matches('[').trim_end_matches(']'), port),
+            Err(_) => (server, WHOIS_PORT),
+        },
+        _ => (server, WHOIS_PORT),
+    }
+}
+
+/// How to treat the registry answering for a TLD.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum RegistryModel {
+    /// Follow the registrar referral, as thin registries like .com require
+    Thin,
+    /// Use the registry response as is, it already holds the full record
+    Thick,
+    /// Decide from the server table
+    #[default]
+    Auto,
+}
+
+impl RegistryModel {
+    fn follows_referral(self, server: &WhoisServer) -> bool {
+        match self {
+            RegistryModel::Thin => true,
+            RegistryModel::Thick => false,
+            RegistryModel::Auto => !server.thick,
+        }
+    }
+}
+
+/// Options that change how a WHOIS lookup is performed, shared by the
+/// `whois` and `batch` commands.
+#[derive(Args, Clone, Default)]
+pub struct WhoisOptions {
+    #[arg(long, value_enum, default_value = "auto", help = "Registry model: follow registrar referrals (thin) or not (thick)")]
+    pub model: RegistryModel,
+    #[arg(long, help = "Timeout in seconds for WHOIS connections [default: 10]")]
+    pub timeout: Option<u64>,
+    #[arg(long, value_name = "SECS", help = "Timeout for establishing the connection [default: --timeout]")]
+    pub connect_timeout: Option<u64>,
+    #[arg(long, value_name = "SECS", help = "Timeout for sending the query and reading the response [default: --timeout]")]
+    pub read_timeout: Option<u64>,
+    #[arg(
+        long,
+        value_name = "FLAGS",
+        value_parser = parse_server_flags,
+        allow_hyphen_values = true,
+        help = "Flags prepended to the query sent to the registry server, e.g. \"-B\"",
+        long_help = SERVER_FLAGS_HELP
+    )]
+    pub server_flags: Option<String>,
+    #[arg(long, help = "Follow \"query X for more\" hints in the response, up to 3 extra queries")]
+    pub full: bool,
+    #[arg(long, help = "Mask email addresses, phone numbers and street addresses in the response")]
+    pub redact: bool,
+    #[arg(
+        long,
+        visible_alias = "no-fallback-iana",
+        help = "Only use servers from the built-in table, never fall back to IANA"
+    )]
+    pub only_tld_server: bool,
+    #[arg(
+        long,
+        value_name = "SERVERS",
+        value_delimiter = ',',
+        conflicts_with = "only_tld_server",
+        help = "Servers to try in order when the table servers fail or there is no entry [default: whois.iana.org]"
+    )]
+    pub fallback: Vec<String>,
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Give up on a target after this many seconds, across all servers and referrals"
+    )]
+    pub deadline: Option<u64>,
+    #[arg(long, help = "Print the addresses each WHOIS server resolves to and the one connected to")]
+    pub resolve_whois_server: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Most WHOIS queries for one target, across referrals, IANA and --full [default: 5]"
+    )]
+    pub max_depth: Option<u64>,
+    #[arg(long, help = "Ask a server again when it answers with an empty response")]
+    pub retry_on_empty: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 2,
+        requires = "retry_on_empty",
+        help = "Extra attempts per server for --retry-on-empty"
+    )]
+    pub retries: u32,
+    #[arg(
+        long,
+        conflicts_with = "only_tld_server",
+        help = "Ask the table server and the fallback (IANA) at once and use the first valid answer"
+    )]
+    pub race: bool,
+    /// Leaves out the fallback and retry notices of this lookup, as
+    /// `--quiet` does; set by `exists`, whose output is the exit code.
+    #[arg(skip)]
+    pub quiet: bool,
+}
+
+const SERVER_FLAGS_HELP: &str = "Flags prepended to the query sent to the registry server from the \
+built-in table. They are not sent to IANA or to referred registrar servers.
+
+Common flags:
+  .uk   -B           full output, including the registrant where it is published
+  .de   -C UTF-8     character set of the response, sent before the default -T dn";
+
+/// Rejects flags that would break the single query line, such as embedded
+/// newlines, and collapses runs of whitespace so the server sees the flags
+/// exactly as separate words.
+fn parse_server_flags(value: &str) -> Result<String, String> {
+    if value.chars().any(|c| c.is_control()) {
+        return Err("server flags must not contain control characters or newlines".to_string());
+    }
+    Ok(value.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+impl WhoisOptions {
+    /// `diag::notice`, unless this lookup was asked to keep quiet; `--verbose`
+    /// still shows the notice.
+    fn notice(&self, message: &str) {
+        if !self.quiet || diag::is_verbose() {
+            diag::notice(message);
+        }
+    }
+
+    fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout.or(self.timeout).unwrap_or(10))
+    }
+
+    fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout.or(self.timeout).unwrap_or(10))
+    }
+
+    fn max_depth(&self) -> usize {
+        self.max_depth.unwrap_or(5) as usize
+    }
+
+    /// The servers asked after the table servers: the `--fallback` chain,
+    /// IANA by default, none with `--only-tld-server`.
+    pub fn fallback_chain(&self) -> Vec<&str> {
+        if self.only_tld_server {
+            Vec::new()
+        } else if self.fallback.is_empty() {
+            vec![IANA_WHOIS]
+        } else {
+            self.fallback.iter().map(String::as_str).collect()
+        }
+    }
+
+    /// Fails with a timeout once `--deadline` has passed, however many
+    /// servers `lookup` is still waiting for.
+    pub async fn within_deadline<T>(&self, target: &str, lookup: impl Future<Output = Result<T>>) -> Result<T> {
+        let Some(secs) = self.deadline else {
+            return lookup.await;
+        };
+        match tokio::time::timeout(Duration::from_secs(secs), lookup).await {
+            Ok(result) => result,
+            Err(_) => Err(std::io::Error::new(
+                ErrorKind::TimedOut,
+                format!("Deadline of {}s exceeded for {}", secs, target),
+            )
+            .into()),
+        }
+    }
+
+    /// The text sent before the target to a table server: `--server-flags`
+    /// followed by the entry's own prefix.
+    pub fn query_prefix(&self, entry: &WhoisServer) -> String {
+        match self.server_flags.as_deref().filter(|flags| !flags.is_empty()) {
+            Some(flags) => format!("{} {}", flags, entry.prefix),
+            None => entry.prefix.to_string(),
+        }
+    }
+}
+
+/// What kind of object a WHOIS target names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetKind {
+    Domain,
+    Ip,
+    /// A CIDR range such as `192.0.2.0/24`
+    Network,
+    /// An autonomous system number such as `AS15169`
+    Asn,
+}
+
+impl std::fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TargetKind::Domain => "domain",
+            TargetKind::Ip => "ip",
+            TargetKind::Network => "network",
+            TargetKind::Asn => "asn",
+        })
+    }
+}
+
+/// Classifies an already normalized target.
+pub fn classify_target(target: &str) -> TargetKind {
+    if target.parse::<IpAddr>().is_ok() {
+        TargetKind::Ip
+    } else if target.parse::<ipnet::IpNet>().is_ok() {
+        TargetKind::Network
+    } else if target
+        .strip_prefix("as")
+        .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+    {
+        TargetKind::Asn
+    } else {
+        TargetKind::Domain
+    }
+}
+
+/// Picks the table entry for the longest suffix of `target` that has one,
+/// starting from the public suffix, so `example.com.au` tries `com.au` and
+/// then `au`.
+pub fn select_whois_server<'a>(servers: &'a [WhoisServer], target: &str) -> Option<&'a WhoisServer> {
+    let suffix = suffix::public_suffix(target).or_else(|| get_tld(target))?;
+    let mut candidate = suffix.as_str();
+    loop {
+        if let Some(entry) = servers.iter().find(|entry| entry.tld == candidate) {
+            return Some(entry);
+        }
+        candidate = candidate.split_once('.')?.1;
+    }
+}
+
+/// What a lookup for a target will do, worked out without any network
+/// access. Used both to run the lookup and for `--dry-run`.
+#[derive(Clone)]
+pub struct WhoisPlan {
+    /// The normalized string sent to the servers
+    pub query: String,
+    pub kind: TargetKind,
+    /// The registry server from the table, if the target has one. IANA is
+    /// asked when there is none or when it fails.
+    pub server: Option<WhoisServer>,
+    pub follow_referral: bool,
+}
+
+pub fn plan_whois(target: &str, options: &WhoisOptions) -> WhoisPlan {
+    let mut query = normalize_target(target);
+    let kind = classify_target(&query);
+
+    // Registries only know the registrable domain, not its subdomains
+    if kind == TargetKind::Domain {
+        if let Some(domain) = suffix::registrable_domain(&query) {
+            query = domain;
+        }
+    }
+
+    let server = select_whois_server(&create_whois_servers(), &query).cloned();
+    let follow_referral = server
+        .as_ref()
+        .is_some_and(|entry| options.model.follows_referral(entry));
+    tracing::trace!(
+        query = %query,
+        kind = ?kind,
+        server = server.as_ref().map_or("none", |entry| entry.hosts[0].as_str()),
+        follow_referral,
+        "selected server"
+    );
+
+    WhoisPlan { query, kind, server, follow_referral }
+}
+
+/// Counts the queries of one lookup against `--max-depth`, so referrals,
+/// IANA discovery and `--full` continuations cannot chain without end.
+struct RoundTrips<'a> {
+    options: &'a WhoisOptions,
+    sent: Cell<usize>,
+    /// Set when a query was left out because the limit was reached
+    truncated: Cell<bool>,
+}
+
+impl<'a> RoundTrips<'a> {
+    fn new(options: &'a WhoisOptions) -> Self {
+        RoundTrips { options, sent: Cell::new(0), truncated: Cell::new(false) }
+    }
+
+    /// Whether another query is allowed. A follow-up that is not sent marks
+    /// the lookup as truncated.
+    fn allow(&self) -> bool {
+        let allowed = self.sent.get() < self.options.max_depth();
+        if !allowed {
+            self.truncated.set(true);
+        }
+        allowed
+    }
+
+    fn query(&self, server: &str, prefix: &str, target: &str) -> Result<String> {
+        if !self.allow() {
+            return Err(anyhow::anyhow!("--max-depth {} reached", self.options.max_depth()));
+        }
+        self.sent.set(self.sent.get() + 1);
+        query_whois_server(server, prefix, target, self.options)
+    }
+}
+
+/// Runs the WHOIS lookup for `target` and returns the server that answered
+/// together with its raw response. When `--max-depth` cuts a chain short,
+/// the response gathered so far is returned with a notice.
+pub fn lookup_whois(target: &str, options: &WhoisOptions) -> Result<(String, String)> {
+    let _span = tracing::info_span!("lookup", target).entered();
+    let trips = RoundTrips::new(options);
+    let (server, mut response) = lookup_record(target, &trips)?;
+    if options.full {
+        response = follow_continuations(&server, response, &trips);
+    }
+    if trips.truncated.get() {
+        options.notice(&format!(
+            "Chain truncated at --max-depth {} for {}; showing the responses gathered so far",
+            options.max_depth(),
+            target
+        ));
+    }
+    if options.redact {
+        response = redact::redact(&response);
+    }
+    Ok((server, response))
+}
+
+/// Runs `lookup_whois` on a blocking thread under `--deadline`, in a slot
+/// of the global `--concurrency`. When the deadline passes, the connection
+/// still open is left to end under its own timeouts, keeping its slot
+/// until then, and its answer is discarded.
+pub async fn lookup_whois_async(target: &str, options: &WhoisOptions) -> Result<(String, String)> {
+    let (owned_target, owned_options) = (target.to_string(), options.clone());
+    let lookup = async move {
+        let permit = concurrency::acquire().await;
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            lookup_whois(&owned_target, &owned_options)
+        })
+        .await?
+    };
+    options.within_deadline(target, lookup).await
+}
+
+fn lookup_record(target: &str, trips: &RoundTrips) -> Result<(String, String)> {
+    run_plan(&plan_whois(target, trips.options), trips)
+}
+
+fn run_plan(plan: &WhoisPlan, trips: &RoundTrips) -> Result<(String, String)> {
+    let options = trips.options;
+    let target = &plan.query;
+    let chain = options.fallback_chain();
+
+    if options.race && plan.server.is_some() && !chain.is_empty() {
+        return race_fallback(plan, options);
+    }
+
+    // First try the TLD-specific servers, alternates in order
+    if let Some(entry) = &plan.server {
+        let prefix = options.query_prefix(entry);
+        for (attempt, server) in entry.hosts.iter().enumerate() {
+            let response = trips.query(server, &prefix, target)
+                .and_then(|result| {
+                    if server == VERISIGN_WHOIS {
+                        narrow_to_exact_match(server, target, result, trips)
+                    } else {
+                        Ok(result)
+                    }
+                });
+            match response {
+                Ok(result) => {
+                    if attempt > 0 {
+                        diag::verbose(&format!("Alternate server {} answered", server));
+                    }
+                    if plan.follow_referral {
+                        return Ok(follow_referral(server, target, result, trips));
+                    }
+                    return Ok((server.clone(), result));
+                }
+                Err(e) => {
+                    let next = entry.hosts.get(attempt + 1).map(String::as_str).or(chain.first().copied());
+                    let Some(next) = next else {
+                        let message = format!("All configured servers for .{} failed: {}", entry.tld, e);
+                        return Err(e.context(message));
+                    };
+                    let next = if next == IANA_WHOIS { "IANA" } else { next };
+                    trips.options.notice(&format!("TLD-specific server {} failed: {}. Trying {}...", server, e, next));
+                }
+            }
+        }
+    }
+
+    if chain.is_empty() {
+        return Err(no_configured_server(plan));
+    }
+    query_fallback_chain(plan, &chain, trips)
+}
+
+/// The servers asked when the table has no entry or its servers failed.
+fn query_fallback_chain(plan: &WhoisPlan, chain: &[&str], trips: &RoundTrips) -> Result<(String, String)> {
+    let target = &plan.query;
+
+    // A registry IANA named earlier in this run is asked directly
+    if plan.server.is_none() && chain.contains(&IANA_WHOIS) {
+        if let Some((server, quirk)) = discovered_registry(plan) {
+            match query_registry(&server, quirk, target, trips) {
+                Ok(result) => return Ok((server, result)),
+                Err(e) => trips.options.notice(&format!("Registry server {} failed: {}. Trying {}...", server, e, chain[0])),
+            }
+        }
+    }
+
+    // Then the fallback chain. IANA only knows the responsible registry, so
+    // its answer is followed; other fallback servers answer for the record.
+    let mut last_error = None;
+    for (attempt, server) in chain.iter().enumerate() {
+        match trips.query(server, "", target) {
+            Ok(result) if *server == IANA_WHOIS => return Ok(follow_iana_referral(plan, result, trips)),
+            Ok(result) => return Ok((server.to_string(), result)),
+            Err(e) => {
+                if let Some(next) = chain.get(attempt + 1) {
+                    trips.options.notice(&format!("Fallback server {} failed: {}. Trying {}...", server, e, next));
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+    let e = last_error.expect("the fallback chain is not empty");
+    let message = format!("WHOIS lookup failed: {}", e);
+    Err(e.context(message))
+}
+
+/// Which path of a `--race` lookup an answer came from.
+#[derive(Clone, Copy, PartialEq)]
+enum RaceSide {
+    Table,
+    Fallback,
+}
+
+/// `--race`: runs the table servers and the fallback chain at once, each
+/// with its own `--max-depth` budget, and returns the first valid answer.
+/// A rate-limit notice or IANA's own record (a referral that could not be
+/// followed) is not valid; it is used only when the other path fails too.
+/// The slower path is abandoned and its answer dropped.
+fn race_fallback(plan: &WhoisPlan, options: &WhoisOptions) -> Result<(String, String)> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    for side in [RaceSide::Table, RaceSide::Fallback] {
+        let (plan, sender) = (plan.clone(), sender.clone());
+        let mut options = WhoisOptions { race: false, ..options.clone() };
+        if side == RaceSide::Table {
+            options.only_tld_server = true;
+        }
+        std::thread::spawn(move || {
+            let trips = RoundTrips::new(&options);
+            let result = match side {
+                RaceSide::Table => lookup_record(&plan.query, &trips),
+                RaceSide::Fallback => query_fallback_chain(&plan, &options.fallback_chain(), &trips),
+            };
+            let _ = sender.send((side, result));
+        });
+    }
+    drop(sender);
+
+    let mut runner_up: Option<(RaceSide, (String, String))> = None;
+    let mut first_error = None;
+    for (side, result) in receiver {
+        match result {
+            Ok((server, response)) => {
+                let usable = !parse::is_rate_limited(&response) && (side == RaceSide::Table || server != IANA_WHOIS);
+                if usable {
+                    diag::verbose(&format!("--race: {} answered first", server));
+                    return Ok((server, response));
+                }
+                diag::verbose(&format!("--race: ignoring the answer from {} while the other path runs", server));
+                if runner_up.as_ref().is_none_or(|(earlier, _)| *earlier != RaceSide::Table) {
+                    runner_up = Some((side, (server, response)));
+                }
+            }
+            Err(e) => {
+                diag::verbose(&format!("--race: {}", e));
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match (runner_up, first_error) {
+        (Some((_, answer)), _) => Ok(answer),
+        (None, Some(e)) => {
+            let message = format!("WHOIS lookup failed: {}", e);
+            Err(e.context(message))
+        }
+        (None, None) => Err(anyhow::anyhow!("WHOIS lookup failed: no answer")),
+    }
+}
+
+/// The error for `--only-tld-server` when the table has no entry for a target.
+pub fn no_configured_server(plan: &WhoisPlan) -> anyhow::Error {
+    match plan.kind {
+        TargetKind::Domain => {
+            let tld = suffix::public_suffix(&plan.query)
+                .or_else(|| get_tld(&plan.query))
+                .unwrap_or_default();
+            anyhow::anyhow!("No configured server for TLD `{}`", tld)
+        }
+        kind => anyhow::anyhow!("No configured server for {} targets", kind),
+    }
+}
+
+/// Asks IANA which WHOIS server is responsible for a target that has no
+/// table entry. Domains are looked up by their TLD, addresses and AS numbers
+/// as they are.
+pub fn discover_whois_server(plan: &WhoisPlan, options: &WhoisOptions) -> Result<Option<String>> {
+    let query = match plan.kind {
+        TargetKind::Domain => get_tld(&plan.query).unwrap_or_default(),
+        _ => plan.query.clone(),
+    };
+    let response = query_whois_server(IANA_WHOIS, "", &query, options)?;
+    Ok(parse::parse_iana_response(&response).referral().map(str::to_string))
+}
+
+/// The query format a registry needs, when it differs from the bare name.
+/// IANA referrals only carry the server name, so the known formats are
+/// looked up by server.
+pub struct RegistryQuirk {
+    pub server: &'static str,
+    /// Sent before the name
+    pub prefix: &'static str,
+    /// Sent after the name
+    pub suffix: &'static str,
+}
+
+const REGISTRY_QUIRKS: [RegistryQuirk; 5] = [
+    // Without a prefix DENIC only says whether the domain is registered
+    RegistryQuirk { server: "whois.denic.de", prefix: "-T dn ", suffix: "" },
+    // Verisign matches host and registrar objects as well as domains
+    RegistryQuirk { server: "whois.verisign-grs.com", prefix: "domain ", suffix: "" },
+    RegistryQuirk { server: "ccwhois.verisign-grs.com", prefix: "domain ", suffix: "" },
+    // The handles of the contacts are left out unless asked for
+    RegistryQuirk { server: "whois.dk-hostmaster.dk", prefix: "--show-handles ", suffix: "" },
+    // JPRS answers in Japanese unless `/e` asks for English
+    RegistryQuirk { server: "whois.jprs.jp", prefix: "", suffix: "/e" },
+];
+
+/// The query format of a registry found through IANA, if it needs one.
+pub fn registry_quirk(server: &str) -> Option<&'static RegistryQuirk> {
+    REGISTRY_QUIRKS.iter().find(|quirk| quirk.server.eq_ignore_ascii_case(server))
+}
+
+/// Registry servers IANA named for a TLD during this run, with their query
+/// format, so later lookups in a batch or the REPL skip IANA.
+type DiscoveredRegistries = Mutex<HashMap<String, (String, Option<&'static RegistryQuirk>)>>;
+
+fn discovered_registries() -> &'static DiscoveredRegistries {
+    static DISCOVERED: OnceLock<DiscoveredRegistries> = OnceLock::new();
+    DISCOVERED.get_or_init(Default::default)
+}
+
+fn discovered_registry(plan: &WhoisPlan) -> Option<(String, Option<&'static RegistryQuirk>)> {
+    let tld = get_tld(&plan.query).filter(|_| plan.kind == TargetKind::Domain)?;
+    discovered_registries().lock().unwrap().get(&tld).cloned()
+}
+
+/// Sends `target` to a registry in the format it needs.
+fn query_registry(server: &str, quirk: Option<&RegistryQuirk>, target: &str, trips: &RoundTrips) -> Result<String> {
+    let (prefix, suffix) = quirk.map_or(("", ""), |quirk| (quirk.prefix, quirk.suffix));
+    trips.query(server, prefix, &format!("{}{}", target, suffix))
+}
+
+/// Asks the registry IANA names for the record, in the format that
+/// registry needs, and remembers the registry for the TLD. IANA's answer is
+/// kept when it names no registry or the registry fails.
+fn follow_iana_referral(plan: &WhoisPlan, response: String, trips: &RoundTrips) -> (String, String) {
+    let referral = match parse::parse_iana_response(&response).referral() {
+        Some(host) if !host.eq_ignore_ascii_case(IANA_WHOIS) && trips.allow() => host.to_string(),
+        _ => return (IANA_WHOIS.to_string(), response),
+    };
+    // The formats are for domain queries; addresses and AS numbers are sent
+    // as they are
+    let quirk = registry_quirk(&referral).filter(|_| plan.kind == TargetKind::Domain);
+
+    diag::verbose(&format!("Following referral from {} to {}", IANA_WHOIS, referral));
+    if let Some(quirk) = quirk {
+        diag::verbose(&format!(
+            "{} needs the query as {:?}",
+            referral,
+            format!("{}{}{}", quirk.prefix, plan.query, quirk.suffix)
+        ));
+    }
+    match query_registry(&referral, quirk, &plan.query, trips) {
+        Ok(registry_response) => {
+            if let Some(tld) = get_tld(&plan.query).filter(|_| plan.kind == TargetKind::Domain) {
+                discovered_registries()
+                    .lock()
+                    .unwrap()
+                    .insert(tld, (referral.clone(), quirk));
+            }
+            (referral, registry_response)
+        }
+        Err(e) => {
+            trips.options.notice(&format!(
+                "Referred server {} failed: {}. Showing the response from {}",
+                referral, e, IANA_WHOIS
+            ));
+            (IANA_WHOIS.to_string(), response)
+        }
+    }
+}
+
+/// Asks the server named in a referral for the full record: the registrar
+/// server of a thin registry.
+/// The original response is kept when there is no referral or the referred
+/// server fails.
+fn follow_referral(server: &str, target: &str, response: String, trips: &RoundTrips) -> (String, String) {
+    let fields = parse::parse_whois_fields(&response);
+    tracing::trace!(server, fields = fields.len(), "parsed response");
+    let referral = match parse::referral_server(&fields) {
+        Some(host) if !host.eq_ignore_ascii_case(server) && trips.allow() => host,
+        _ => return (server.to_string(), response),
+    };
+
+    diag::verbose(&format!("Following referral from {} to {}", server, referral));
+    match trips.query(&referral, "", target) {
+        Ok(registrar_response) => (referral, registrar_response),
+        Err(e) => {
+            trips.options.notice(&format!(
+                "Referred server {} failed: {}. Showing the response from {}",
+                referral, e, server
+            ));
+            (server.to_string(), response)
+        }
+    }
+}
+
+/// Most extra queries `--full` sends for one lookup.
+const MAX_CONTINUATIONS: usize = 3;
+
+/// Some servers answer with part of the data and a hint such as
+/// `to get more info query "NET-192-0-2-0-1"`. Send the hinted queries to the
+/// same server and append the answers, skipping queries already sent. This
+/// is a continuation on the same server, unlike a registrar referral.
+fn follow_continuations(server: &str, response: String, trips: &RoundTrips) -> String {
+    let mut full = response;
+    let mut sent: Vec<String> = Vec::new();
+
+    while sent.len() < MAX_CONTINUATIONS {
+        let Some(query) = parse::continuation_query(&full, &sent) else {
+            break;
+        };
+        if !trips.allow() {
+            break;
+        }
+        diag::verbose(&format!("Following continuation on {}: {}", server, query));
+        match trips.query(server, "", &query) {
+            Ok(more) => full = format!("{}\n% Continuation: {}\n{}", full.trim_end(), query, more),
+            Err(e) => {
+                trips.options.notice(&format!("Continuation query {:?} on {} failed: {}", query, server, e));
+                break;
+            }
+        }
+        sent.push(query);
+    }
+
+    if sent.len() == MAX_CONTINUATIONS && parse::continuation_query(&full, &sent).is_some() {
+        trips.options.notice(&format!("Stopped after {} continuation queries", MAX_CONTINUATIONS));
+    }
+    full
+}
+
+/// Verisign answers a query that matches several objects with a list of
+/// them and a hint to repeat the query as `=name`. Pick the record for
+/// `target` itself out of such a list, asking again for the full display when
+/// the list does not contain it.
+fn narrow_to_exact_match(server: &str, target: &str, response: String, trips: &RoundTrips) -> Result<String> {
+    const SINGLE_OUT_NOTICE: &str = "To single out one record";
+
+    let records = parse::domain_records(&response);
+    if records.len() <= 1 && !response.contains(SINGLE_OUT_NOTICE) {
+        return Ok(response);
+    }
+
+    trips.options.notice(&format!(
+        "{} returned {} matches for {}, selecting the exact record",
+        server,
+        records.len(),
+        target
+    ));
+
+    let exact = |records: &[(&str, &str)]| {
+        records
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(target))
+            .map(|(_, record)| record.to_string())
+    };
+
+    if let Some(record) = exact(&records) {
+        return Ok(record);
+    }
+
+    if !trips.allow() {
+        return Ok(response);
+    }
+    let full = trips.query(server, "=", target)?;
+    Ok(exact(&parse::domain_records(&full)).unwrap_or(full))
+}
+
+/// Resolves the address of a WHOIS server once per run. WHOIS needs a new
+/// connection for every query, so large batches would otherwise resolve the
+/// same server name for each target. `--no-dns-cache` resolves it every time.
+/// With `-4` or `-6` only the addresses of that family are returned.
+fn resolve_whois_server(server: &str) -> Result<Vec<SocketAddr>> {
+    static ADDRS: OnceLock<Mutex<HashMap<String, Vec<SocketAddr>>>> = OnceLock::new();
+    let cache = ADDRS.get_or_init(Default::default);
+
+    let cached = cache.lock().unwrap().get(server).filter(|_| dnscache::is_enabled()).cloned();
+    let addrs = match cached {
+        Some(addrs) => addrs,
+        None => {
+            let addrs: Vec<SocketAddr> = host_and_port(server)
+                .to_socket_addrs()
+                .map_err(|source| LookupError::Resolve { server: server.to_string(), source })?
+                .collect();
+            if dnscache::is_enabled() {
+                cache.lock().unwrap().insert(server.to_string(), addrs.clone());
+            }
+            addrs
+        }
+    };
+
+    let family = family::get();
+    let (usable, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.into_iter().partition(|addr| family.allows(&addr.ip()));
+    match (usable.is_empty(), other.is_empty(), family.name()) {
+        (true, false, Some(name)) => Err(LookupError::WrongFamily { server: server.to_string(), family: name }.into()),
+        _ => Ok(usable),
+    }
+}
+
+/// Why no connection to a WHOIS server could be made. The causes point at
+/// different problems: a name that does not resolve is usually a wrong
+/// table entry, a refused connection a server that is down, and a timeout
+/// a firewall dropping port 43.
+#[derive(Debug)]
+pub enum LookupError {
+    Resolve { server: String, source: std::io::Error },
+    NoAddresses { server: String },
+    /// The server only has addresses of the family that `-4` or `-6` left out
+    WrongFamily { server: String, family: &'static str },
+    Refused { server: String, addr: SocketAddr, source: std::io::Error },
+    TimedOut { server: String, addr: SocketAddr, source: std::io::Error },
+    Connect { server: String, addr: SocketAddr, source: std::io::Error },
+}
+
+impl LookupError {
+    fn connect(server: &str, addr: SocketAddr, source: std::io::Error) -> Self {
+        let server = server.to_string();
+        match source.kind() {
+            ErrorKind::ConnectionRefused => LookupError::Refused { server, addr, source },
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => LookupError::TimedOut { server, addr, source },
+            _ => LookupError::Connect { server, addr, source },
+        }
+    }
+
+    /// What the failure suggests checking, for `--verbose`.
+    fn hint(&self) -> &'static str {
+        match self {
+            LookupError::Resolve { .. } | LookupError::NoAddresses { .. } => {
+                "check the server name in the table, WHOIS_SERVER_<TLD> or the server file"
+            }
+            LookupError::WrongFamily { .. } => "drop -4 or -6 to use its other addresses",
+            LookupError::Refused { .. } => "the host is up but nothing listens on the WHOIS port",
+            LookupError::TimedOut { .. } => "a firewall may be dropping WHOIS traffic, see --connect-timeout",
+            LookupError::Connect { .. } => "the network path to the server is failing",
+        }
+    }
+}
+
+/// The server name with the address it resolved to, or just the address
+/// when the server was given as one.
+fn server_at(server: &str, addr: &SocketAddr) -> String {
+    if host_and_port(server).0.parse::<IpAddr>().is_ok() {
+        server.to_string()
+    } else {
+        format!("{} ({})", server, addr)
+    }
+}
+
+impl std::fmt::Display for LookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LookupError::Resolve { server, source } => {
+                write!(f, "Cannot resolve WHOIS server {}: {}", host_and_port(server).0, source)
+            }
+            LookupError::NoAddresses { server } => write!(f, "WHOIS server {} has no addresses", server),
+            LookupError::WrongFamily { server, family } => {
+                write!(f, "WHOIS server {} has no {} address", host_and_port(server).0, family)
+            }
+            LookupError::Refused { server, addr, .. } => {
+                write!(f, "{} refused the connection", server_at(server, addr))
+            }
+            LookupError::TimedOut { server, addr, .. } => {
+                write!(f, "Connection to {} timed out", server_at(server, addr))
+            }
+            LookupError::Connect { server, addr, source } => {
+                write!(f, "Could not connect to {}: {}", server_at(server, addr), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LookupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LookupError::Resolve { source, .. }
+            | LookupError::Refused { source, .. }
+            | LookupError::TimedOut { source, .. }
+            | LookupError::Connect { source, .. } => Some(source),
+            LookupError::NoAddresses { .. } | LookupError::WrongFamily { .. } => None,
+        }
+    }
+}
+
+/// Connects to the first reachable address of `server`, waiting at most
+/// `--connect-timeout` for each so dead hosts fail fast without shortening
+/// the time allowed to answer. With `--resolve-whois-server` the addresses
+/// are printed before the query is sent, so a tampered DNS answer shows up
+/// next to the server name.
+fn connect(server: &str, options: &WhoisOptions) -> Result<TcpStream> {
+    let addrs = match resolve_whois_server(server) {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            if let Some(e) = e.downcast_ref::<LookupError>() {
+                diag::verbose(&format!("{} ({})", e, e.hint()));
+            }
+            return Err(e);
+        }
+    };
+    if options.resolve_whois_server {
+        let ips: Vec<String> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+        eprintln!("{} resolves to {}", server, ips.join(", "));
+    }
+
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, options.connect_timeout()) {
+            Ok(stream) => {
+                if options.resolve_whois_server {
+                    eprintln!("Connected to {} at {}", server, addr);
+                }
+                return Ok(stream);
+            }
+            Err(e) => {
+                let e = LookupError::connect(server, addr, e);
+                diag::verbose(&format!("{} ({})", e, e.hint()));
+                last_error = Some(e);
+            }
+        }
+    }
+    let e = last_error.unwrap_or_else(|| {
+        let e = LookupError::NoAddresses { server: server.to_string() };
+        diag::verbose(&format!("{} ({})", e, e.hint()));
+        e
+    });
+    Err(e.into())
+}
+
+/// Sends one query and returns the response. An empty response is an error,
+/// unless `--retry-on-empty` asks for the query to be sent again first.
+pub fn query_whois_server(
+    server: &str,
+    prefix: &str,
+    target: &str,
+    options: &WhoisOptions,
+) -> Result<String> {
+    let retries = if options.retry_on_empty { options.retries } else { 0 };
+    for attempt in 1..=retries {
+        match send_query(server, prefix, target, options)? {
+            Some(response) => return Ok(response),
+            None => options.notice(&format!(
+                "Empty response from {}, retrying ({} of {})",
+                server, attempt, retries
+            )),
+        }
+    }
+    send_query(server, prefix, target, options)?.ok_or_else(|| anyhow::anyhow!("Empty response from server"))
+}
+
+/// Returns `None` when the server closes the connection without an answer.
+fn send_query(server: &str, prefix: &str, target: &str, options: &WhoisOptions) -> Result<Option<String>> {
+    let _span = tracing::info_span!("query", server).entered();
+    diag::verbose(&format!("Querying {} for {}", server, target));
+    let mut stream = connect(server, options)?;
+    if let Ok(peer) = stream.peer_addr() {
+        tracing::trace!(%peer, "connected");
+    }
+    stream.set_read_timeout(Some(options.read_timeout()))?;
+    stream.set_write_timeout(Some(options.read_timeout()))?;
+
+    // Send query
+    let query = format!("{}{}{}", prefix, target, serverfile::line_ending(server).as_str());
+    stream.write_all(query.as_bytes())?;
+
+    // Read response
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes)?;
+    tracing::trace!(bytes = bytes.len(), "read response");
+    let response = encoding::decode_response(server, bytes);
+
+    let response = parse::normalize_response(&response);
+    Ok(Some(response).filter(|response| !response.trim().is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+
+    /// A WHOIS server on a local port that answers one connection with each
+    /// of `responses` in turn and reports the query lines it received.
+    fn mock_server(responses: &[&str]) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let responses: Vec<String> = responses.iter().map(|response| response.to_string()).collect();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut query = String::new();
+                BufReader::new(&stream).read_line(&mut query).unwrap();
+                let _ = sender.send(query);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (addr, receiver)
+    }
+
+    /// An address nothing listens on, so connections are refused.
+    fn closed_port() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    /// A plan for `example.test` that asks `hosts` as its table servers.
+    fn table_plan(hosts: &[&str]) -> WhoisPlan {
+        WhoisPlan {
+            query: "example.test".to_string(),
+            kind: TargetKind::Domain,
+            server: Some(WhoisServer::new("test", hosts, "", true)),
+            follow_referral: false,
+        }
+    }
+
+    /// Trimmed from a `whois.verisign-grs.com` answer to `example.com`
+    /// without the `domain` keyword: the name server objects whose names
+    /// start with the query are listed before the domain itself.
+    const VERISIGN_MATCHES: &str = "   Server Name: EXAMPLE.COM.AU.NS.EXAMPLE.NET
+   IP Address: 192.0.2.53
+   Registrar: Example Registrar, Inc.
+   Registrar WHOIS Server: whois.example-registrar.com
+   Registrar URL: http://www.example-registrar.com
+
+   Domain Name: EXAMPLE.COM
+   Registry Domain ID: 2336799_DOMAIN_COM-VRSN
+   Registrar WHOIS Server: whois.iana.org
+   Updated Date: 2024-08-14T07:01:34Z
+   Creation Date: 1995-08-14T04:00:00Z
+   Name Server: A.IANA-SERVERS.NET
+   Name Server: B.IANA-SERVERS.NET
+>>> Last update of whois database: 2024-10-14T11:56:31Z <<<
+
+To single out one record, look it up with \"xxx\", where xxx is one of the
+records displayed above. If the records are the same, look them up
+with \"=xxx\" to receive a full display for each record.
+";
+
+    #[test]
+    fn com_and_net_queries_use_the_domain_keyword() {
+        let (servers, options) = (builtin_whois_servers(), WhoisOptions::default());
+        for domain in ["example.com", "example.net"] {
+            let entry = select_whois_server(&servers, domain).unwrap();
+            assert_eq!(entry.hosts, [VERISIGN_WHOIS]);
+            assert_eq!(options.query_prefix(entry), "domain ");
+        }
+    }
+
+    #[test]
+    fn verisign_match_list_is_narrowed_to_the_exact_domain() {
+        let options = WhoisOptions::default();
+        let trips = RoundTrips::new(&options);
+        let record = narrow_to_exact_match(VERISIGN_WHOIS, "example.com", VERISIGN_MATCHES.to_string(), &trips).unwrap();
+        assert!(record.trim_start().starts_with("Domain Name: EXAMPLE.COM"));
+        assert!(record.contains("Registry Domain ID: 2336799_DOMAIN_COM-VRSN"));
+        assert!(!record.contains("Server Name:"));
+        assert!(!record.contains(">>> Last update"));
+        assert_eq!(trips.sent.get(), 0);
+    }
+
+    #[test]
+    fn single_verisign_record_is_kept_as_is() {
+        let options = WhoisOptions::default();
+        let trips = RoundTrips::new(&options);
+        let single = "   Domain Name: EXAMPLE.COM\n   Registry Domain ID: 2336799_DOMAIN_COM-VRSN\n";
+        let record = narrow_to_exact_match(VERISIGN_WHOIS, "example.com", single.to_string(), &trips).unwrap();
+        assert_eq!(record, single);
+    }
+
+    #[test]
+    fn verisign_list_without_the_domain_is_asked_again_with_equals() {
+        let full = "   Domain Name: EXAMPLE.COM\n   Registry Domain ID: 2336799_DOMAIN_COM-VRSN\n";
+        let (server, queries) = mock_server(&[full]);
+        let options = WhoisOptions::default();
+        let trips = RoundTrips::new(&options);
+        let servers_only = VERISIGN_MATCHES.split("   Domain Name:").next().unwrap();
+        let partial = format!("{}>>> Last update <<<\n\nTo single out one record, look it up with \"xxx\"\n", servers_only);
+        let record = narrow_to_exact_match(&server, "example.com", partial, &trips).unwrap();
+        assert_eq!(queries.recv().unwrap(), "=example.com\r\n");
+        assert!(record.contains("2336799_DOMAIN_COM-VRSN"));
+    }
+
+    #[test]
+    fn alternate_server_answers_when_the_first_fails() {
+        let (alternate, queries) = mock_server(&["Domain Name: EXAMPLE.TEST\r\n"]);
+        let options = WhoisOptions { only_tld_server: true, ..WhoisOptions::default() };
+        let trips = RoundTrips::new(&options);
+        let (server, response) = run_plan(&table_plan(&[&closed_port(), &alternate]), &trips).unwrap();
+        assert_eq!(server, alternate);
+        assert_eq!(response.trim(), "Domain Name: EXAMPLE.TEST");
+        assert_eq!(queries.recv().unwrap(), "example.test\r\n");
+        assert_eq!(trips.sent.get(), 2);
+    }
+
+    #[test]
+    fn first_server_that_answers_is_used() {
+        let (first, _) = mock_server(&["Domain Name: EXAMPLE.TEST\n"]);
+        let options = WhoisOptions { only_tld_server: true, ..WhoisOptions::default() };
+        let trips = RoundTrips::new(&options);
+        let (server, _) = run_plan(&table_plan(&[&first, &closed_port()]), &trips).unwrap();
+        assert_eq!(server, first);
+        assert_eq!(trips.sent.get(), 1);
+    }
+
+    #[test]
+    fn failure_of_every_alternate_names_the_tld() {
+        let options = WhoisOptions { only_tld_server: true, ..WhoisOptions::default() };
+        let trips = RoundTrips::new(&options);
+        let e = run_plan(&table_plan(&[&closed_port(), &closed_port()]), &trips).unwrap_err();
+        assert!(e.to_string().starts_with("All configured servers for .test failed"), "{}", e);
+    }
+
+    #[test]
+    fn fallback_chain_defaults_to_iana() {
+        assert_eq!(WhoisOptions::default().fallback_chain(), [IANA_WHOIS]);
+        let options = WhoisOptions { only_tld_server: true, ..WhoisOptions::default() };
+        assert!(options.fallback_chain().is_empty());
+        let options = WhoisOptions { fallback: vec!["whois.example.net".to_string()], ..WhoisOptions::default() };
+        assert_eq!(options.fallback_chain(), ["whois.example.net"]);
+    }
+
+    #[test]
+    fn fallback_chain_is_walked_in_order_after_the_table_servers() {
+        let (proxy, queries) = mock_server(&["Domain Name: EXAMPLE.TEST\n"]);
+        let options = WhoisOptions { fallback: vec![closed_port(), proxy.clone()], ..WhoisOptions::default() };
+        let trips = RoundTrips::new(&options);
+        let (server, _) = run_plan(&table_plan(&[&closed_port()]), &trips).unwrap();
+        assert_eq!(server, proxy);
+        // The table server and the first fallback failed before the proxy answered
+        assert_eq!(trips.sent.get(), 3);
+        assert_eq!(queries.recv().unwrap(), "example.test\r\n");
+    }
+
+    #[test]
+    fn fallback_chain_is_used_when_the_table_has_no_entry() {
+        let (proxy, _) = mock_server(&["Domain Name: EXAMPLE.TEST\n"]);
+        let options = WhoisOptions { fallback: vec![proxy.clone()], ..WhoisOptions::default() };
+        let trips = RoundTrips::new(&options);
+        let plan = WhoisPlan { server: None, ..table_plan(&[]) };
+        let (server, response) = run_plan(&plan, &trips).unwrap();
+        assert_eq!((server, response.trim()), (proxy, "Domain Name: EXAMPLE.TEST"));
+    }
+
+    #[test]
+    fn exhausted_fallback_chain_reports_the_last_error() {
+        let options = WhoisOptions { fallback: vec![closed_port()], ..WhoisOptions::default() };
+        let trips = RoundTrips::new(&options);
+        let plan = WhoisPlan { server: None, ..table_plan(&[]) };
+        let e = run_plan(&plan, &trips).unwrap_err();
+        assert!(e.to_string().starts_with("WHOIS lookup failed"), "{}", e);
+    }
+
+    #[test]
+    fn registry_named_by_iana_is_asked_directly_later() {
+        let (registry, queries) = mock_server(&["Domain Name: EXAMPLE.DISCOVERED\n"]);
+        let quirk = registry_quirk("whois.denic.de");
+        discovered_registries().lock().unwrap().insert("discovered".to_string(), (registry.clone(), quirk));
+
+        let options = WhoisOptions::default();
+        let trips = RoundTrips::new(&options);
+        let plan = WhoisPlan { query: "example.discovered".to_string(), server: None, ..table_plan(&[]) };
+        let (server, _) = run_plan(&plan, &trips).unwrap();
+        assert_eq!(server, registry);
+        assert_eq!(queries.recv().unwrap(), "-T dn example.discovered\r\n");
+        assert_eq!(trips.sent.get(), 1);
+    }
+
+    #[test]
+    fn builtin_table_parses_with_known_tlds() {
+        let servers = parse_builtin_table(include_str!("../data/servers.json")).unwrap();
+        // The nine entries the table started with, then the ones added since
+        let expected: &[(&str, &[&str], &str, bool)] = &[
+            ("com", &["whois.verisign-grs.com"], "domain ", false),
+            ("net", &["whois.verisign-grs.com"], "domain ", false),
+            ("org", &["whois.pir.org"], "", true),
+            ("edu", &["whois.educause.edu"], "", true),
+            ("it", &["whois.nic.it"], "", true),
+            ("uk", &["whois.nic.uk"], "", true),
+            ("ru", &["whois.tcinet.ru", "whois.ripn.net"], "", true),
+            ("de", &["whois.denic.de"], "-T dn ", true),
+            ("nl", &["whois.domain-registry.nl"], "", true),
+            ("au", &["whois.auda.org.au"], "", true),
+            ("br", &["whois.registro.br"], "", true),
+        ];
+        for &(tld, hosts, prefix, thick) in expected {
+            let server = servers.iter().find(|server| server.tld == tld).unwrap_or_else(|| panic!(".{} is missing", tld));
+            assert_eq!(server.hosts, hosts, "{}", tld);
+            assert_eq!(server.prefix, prefix, "{}", tld);
+            assert_eq!(server.thick, thick, "{}", tld);
+            assert_eq!(server.origin, Origin::Builtin, "{}", tld);
+        }
+    }
+
+    #[test]
+    fn builtin_table_rejects_broken_entries() {
+        assert!(parse_builtin_table(r#"[{"tld": ".com", "hosts": ["a"]}]"#).is_err());
+        assert!(parse_builtin_table(r#"[{"tld": "com", "hosts": []}]"#).is_err());
+        assert!(parse_builtin_table(r#"[{"tld": "com", "hosts": ["a"]}, {"tld": "com", "hosts": ["b"]}]"#).is_err());
+        assert!(parse_builtin_table(r#"[{"tld": "com", "hosts": ["a"], "thin": true}]"#).is_err());
+    }
+}
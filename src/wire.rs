@@ -0,0 +1,336 @@
+use anyhow::Result;
+use colored::*;
+use ring::rand::SystemRandom;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use trust_dns_resolver::config::Protocol;
+use trust_dns_resolver::proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns_resolver::proto::rr::{DNSClass, Name, RecordType};
+use whois_dns_tool::dns::DnsAnswer;
+
+use crate::{checks, diag, dnscache, emit_dns_records, print_dns_records, resolver, DnsOutput, OutputFormat};
+
+const WIRE_TIMEOUT: Duration = Duration::from_secs(5);
+const DNS_PORT: u16 = 53;
+
+/// One query and its response, sent as single UDP packets, or over TCP when
+/// the UDP answer was truncated.
+struct Exchange {
+    server: SocketAddr,
+    protocol: Protocol,
+    request: Vec<u8>,
+    response: Vec<u8>,
+    message: Message,
+}
+
+/// Sends the query as a single UDP packet, bypassing the resolver so the raw
+/// request and response bytes can be shown next to the parsed answer. A
+/// truncated answer is asked for again over TCP, and that exchange is shown.
+pub async fn perform_wire_query(
+    domain: &str,
+    record_type: RecordType,
+    class: DNSClass,
+    server: Option<&str>,
+) -> Result<()> {
+    let exchange = exchange(domain, record_type, class, server_addr(server).await?).await?;
+
+    let (server, protocol) = (exchange.server, exchange.protocol.to_string().to_uppercase());
+    println!("{} {} bytes to {} over {}", "Request:".green().bold(), exchange.request.len(), server, protocol);
+    println!("{}", hex_dump(&exchange.request));
+    println!("{} {} bytes from {} over {}", "Response:".green().bold(), exchange.response.len(), server, protocol);
+    println!("{}", hex_dump(&exchange.response));
+
+    let response = &exchange.message;
+    println!("{} {}", "Answer:".green().bold(), response.response_code());
+    if response.truncated() {
+        println!("{}", "Response was truncated (TC bit set)".yellow());
+    }
+    for answer in response.answers().iter().map(DnsAnswer::from) {
+        println!("{} {} {} {}", answer.name, answer.ttl, answer.record_type, answer.data);
+    }
+
+    Ok(())
+}
+
+/// Asks one server directly, for `--server` and for classes other than IN,
+/// which the resolver cannot query. `dns version.bind TXT --class CH
+/// --server 192.0.2.53` is the equivalent of `dig CH TXT version.bind
+/// @192.0.2.53`.
+pub async fn perform_direct_query(
+    domain: &str,
+    record_type: RecordType,
+    class: DNSClass,
+    server: Option<&str>,
+    output: DnsOutput,
+) -> Result<()> {
+    let (server, response) = cached_exchange(domain, record_type, class, server).await?;
+    if response.response_code() != ResponseCode::NoError {
+        return Err(anyhow::anyhow!("{} answered {}", server, response.response_code()));
+    }
+
+    let answers: Vec<DnsAnswer> = response.answers().iter().map(DnsAnswer::from).collect();
+    if output.format != OutputFormat::Text {
+        emit_dns_records(None, &answers, output)?;
+    } else {
+        if !diag::is_quiet() && !output.only_record_data {
+            println!("{} {}", "Server:".bold(), server);
+        }
+        print_dns_records(&answers, output);
+    }
+    checks::warn_apex_cname(domain, record_type, &answers);
+    Ok(())
+}
+
+/// Sends one IN query to `server` and returns the whole response, for callers
+/// that need the authority and additional sections as well as the answers.
+pub async fn query_server(domain: &str, record_type: RecordType, server: &str) -> Result<Message> {
+    Ok(cached_exchange(domain, record_type, DNSClass::IN, Some(server)).await?.1)
+}
+
+/// Like `exchange`, but answers from the DNS cache while the TTL of an
+/// earlier response lasts. Returns the server and its response.
+async fn cached_exchange(
+    domain: &str,
+    record_type: RecordType,
+    class: DNSClass,
+    server: Option<&str>,
+) -> Result<(SocketAddr, Message)> {
+    let server = server_addr(server).await?;
+    if let Some(message) = dnscache::get(domain, record_type, class, server) {
+        return Ok((server, message));
+    }
+    let exchange = exchange(domain, record_type, class, server).await?;
+    dnscache::insert(domain, record_type, class, server, &exchange.message);
+    Ok((server, exchange.message))
+}
+
+/// The server to send a query to: the one given, or the first UDP name
+/// server of the resolver configuration.
+async fn server_addr(server: Option<&str>) -> Result<SocketAddr> {
+    match server {
+        Some(server) => resolve_server(server).await,
+        None => resolver::config()
+            .name_servers()
+            .iter()
+            .find(|ns| ns.protocol == Protocol::Udp)
+            .map(|ns| ns.socket_addr)
+            .ok_or_else(|| anyhow::anyhow!("No UDP name server configured")),
+    }
+}
+
+/// Sends the query over UDP and waits for the answer to it. Datagrams from
+/// other addresses, or with another ID, are not answers to this query and
+/// are skipped until one arrives or the timeout passes. A truncated answer
+/// is asked for again over TCP.
+async fn exchange(domain: &str, record_type: RecordType, class: DNSClass, server: SocketAddr) -> Result<Exchange> {
+    let query = build_query(domain, record_type, class)?;
+    let request = query.to_vec()?;
+
+    let bind: SocketAddr = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse()?;
+    let socket = UdpSocket::bind(bind).await?;
+    socket.send_to(&request, server).await?;
+
+    let deadline = tokio::time::Instant::now() + WIRE_TIMEOUT;
+    let no_response = || anyhow::anyhow!("No response from {} within {:?}", server, WIRE_TIMEOUT);
+    let mut buffer = vec![0u8; 4096];
+    let (response, message) = loop {
+        let (len, source) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer))
+            .await
+            .map_err(|_| no_response())??;
+        if source != server {
+            diag::verbose(&format!("Ignoring a datagram from {}, the query went to {}", source, server));
+            continue;
+        }
+        match Message::from_vec(&buffer[..len]) {
+            Ok(message) if message.id() == query.id() && message.message_type() == MessageType::Response => {
+                break (buffer[..len].to_vec(), message)
+            }
+            Ok(message) => diag::verbose(&format!(
+                "Ignoring a message with ID {} from {}, the query has ID {}",
+                message.id(),
+                server,
+                query.id()
+            )),
+            Err(e) => diag::verbose(&format!("Ignoring a datagram from {} that does not parse: {}", server, e)),
+        }
+    };
+
+    if message.truncated() {
+        diag::verbose(&format!("Response from {} was truncated; asking again over TCP", server));
+        return tcp_exchange(&query, request, server).await;
+    }
+    Ok(Exchange { server, protocol: Protocol::Udp, request, response, message })
+}
+
+/// Sends the query over TCP, each message preceded by its two-byte length.
+async fn tcp_exchange(query: &Message, request: Vec<u8>, server: SocketAddr) -> Result<Exchange> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(server).await?;
+        let length = u16::try_from(request.len())?;
+        stream.write_all(&[&length.to_be_bytes()[..], &request].concat()).await?;
+
+        let mut length = [0u8; 2];
+        stream.read_exact(&mut length).await?;
+        let mut response = vec![0u8; u16::from_be_bytes(length) as usize];
+        stream.read_exact(&mut response).await?;
+        anyhow::Ok(response)
+    };
+    let response = tokio::time::timeout(WIRE_TIMEOUT, exchange)
+        .await
+        .map_err(|_| anyhow::anyhow!("No TCP response from {} within {:?}", server, WIRE_TIMEOUT))??;
+
+    let message = Message::from_vec(&response)?;
+    if message.id() != query.id() {
+        return Err(anyhow::anyhow!("{} answered over TCP with ID {}, the query has ID {}", server, message.id(), query.id()));
+    }
+    Ok(Exchange { server, protocol: Protocol::Tcp, request, response, message })
+}
+
+/// Accepts `192.0.2.53`, `192.0.2.53:5353`, `[2001:db8::53]:53` or a host
+/// name, using port 53 unless one is given.
+async fn resolve_server(server: &str) -> Result<SocketAddr> {
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, DNS_PORT));
+    }
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| anyhow::anyhow!("Invalid port in {}", server))?),
+        None => (server, DNS_PORT),
+    };
+    tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Could not resolve DNS server {}", server))
+}
+
+fn build_query(domain: &str, record_type: RecordType, class: DNSClass) -> Result<Message> {
+    // An unguessable ID is what keeps off-path spoofed answers out of
+    // `exchange`
+    let id: [u8; 2] = ring::rand::generate(&SystemRandom::new())
+        .map_err(|_| anyhow::anyhow!("Could not generate a random query ID"))?
+        .expose();
+    let mut query = Query::query(Name::from_str(domain)?, record_type);
+    query.set_query_class(class);
+
+    let mut message = Message::new();
+    message
+        .set_id(u16::from_be_bytes(id))
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(query);
+    Ok(message)
+}
+
+/// Formats bytes in the classic offset / hex / ASCII columns, 16 per line.
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  |{}|", line * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+    use trust_dns_resolver::proto::rr::rdata::A;
+    use trust_dns_resolver::proto::rr::{RData, Record};
+
+    /// The answer to `request` with one A record, or none and the TC bit.
+    fn answer(request: &[u8], truncated: bool) -> Vec<u8> {
+        let mut message = Message::from_vec(request).unwrap();
+        message.set_message_type(MessageType::Response).set_truncated(truncated);
+        if !truncated {
+            let name = message.queries()[0].name().clone();
+            message.add_answer(Record::from_rdata(name, 300, RData::A(A(Ipv4Addr::new(192, 0, 2, 1)))));
+        }
+        message.to_vec().unwrap()
+    }
+
+    fn answered_ip(exchange: &Exchange) -> Option<IpAddr> {
+        exchange.message.answers().first()?.data()?.ip_addr()
+    }
+
+    #[test]
+    fn queries_built_at_once_get_different_ids() {
+        let ids: std::collections::HashSet<u16> = (0..64)
+            .map(|_| build_query("example.com.", RecordType::A, DNSClass::IN).unwrap().id())
+            .collect();
+        // 64 random IDs out of 65536 rarely repeat; a clock-based ID would
+        // give a handful at most
+        assert!(ids.len() > 60, "{} distinct IDs", ids.len());
+    }
+
+    #[tokio::test]
+    async fn datagrams_from_other_sources_or_with_another_id_are_skipped() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; 512];
+            let (len, client) = server.recv_from(&mut buffer).await.unwrap();
+            let reply = answer(&buffer[..len], false);
+
+            let spoofer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            spoofer.send_to(&reply, client).await.unwrap();
+            let mut wrong_id = Message::from_vec(&reply).unwrap();
+            wrong_id.set_id(wrong_id.id().wrapping_add(1));
+            server.send_to(&wrong_id.to_vec().unwrap(), client).await.unwrap();
+            server.send_to(&reply, client).await.unwrap();
+        });
+
+        let exchange = exchange("example.test.", RecordType::A, DNSClass::IN, addr).await.unwrap();
+        assert_eq!(exchange.protocol, Protocol::Udp);
+        assert_eq!(exchange.message.id(), Message::from_vec(&exchange.request).unwrap().id());
+        assert_eq!(answered_ip(&exchange), Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+    }
+
+    #[tokio::test]
+    async fn truncated_answers_are_asked_again_over_tcp() {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).await.unwrap();
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; 512];
+            let (len, client) = udp.recv_from(&mut buffer).await.unwrap();
+            udp.send_to(&answer(&buffer[..len], true), client).await.unwrap();
+
+            let (mut stream, _) = tcp.accept().await.unwrap();
+            let mut length = [0u8; 2];
+            stream.read_exact(&mut length).await.unwrap();
+            let mut request = vec![0u8; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut request).await.unwrap();
+            let reply = answer(&request, false);
+            stream.write_all(&(reply.len() as u16).to_be_bytes()).await.unwrap();
+            stream.write_all(&reply).await.unwrap();
+        });
+
+        let exchange = exchange("example.test.", RecordType::A, DNSClass::IN, addr).await.unwrap();
+        assert_eq!(exchange.protocol, Protocol::Tcp);
+        assert!(!exchange.message.truncated());
+        assert_eq!(answered_ip(&exchange), Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+    }
+
+    #[test]
+    fn hex_dump_has_offset_hex_and_ascii_columns() {
+        let dump = hex_dump(b"\x12\x34example\x00\x01\x00\x01abcdefgh");
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "00000000  12 34 65 78 61 6d 70 6c 65 00 01 00 01 61 62 63  |.4example....abc|");
+        assert_eq!(lines[1], format!("00000010  {:<47}  |defgh|", "64 65 66 67 68"));
+    }
+}